@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::error::IndexerResult;
+use crate::output::write_atomic;
+
+/// The newest transaction seen by a completed indexing cycle. The next cycle
+/// passes its signature as `until` so only newer transactions are fetched.
+///
+/// A reorg can drop the checkpointed transaction, and paginating `until` a
+/// signature the node doesn't know never stops early, so the next cycle would
+/// page through the wallet's whole history. Before resuming, the signature is
+/// looked up; if it is gone, the cycle instead pages back to the checkpoint's
+/// slot (slot windows) or block time (time windows). That boundary is
+/// inclusive, so transactions at the checkpoint itself are fetched again and
+/// left to de-duplication.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub signature: String,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot: Option<u64>, // Missing from checkpoints saved by older versions
+}
+
+impl Checkpoint {
+    /// Load the checkpoint from `path`, or `None` if no state has been saved yet
+    pub fn load(path: &Path) -> IndexerResult<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let json = std::fs::read_to_string(path)?;
+        Ok(Some(serde_json::from_str(&json)?))
+    }
+
+    /// Whether the cycle that saved this checkpoint already saw the
+    /// transaction `signature` at `slot`. Block times are too coarse to tell,
+    /// so this goes by slot; others in the checkpoint's own slot count as new,
+    /// since a slot holds no order to compare by. Without a slot, only the
+    /// checkpoint's own transaction was seen.
+    pub fn has_seen(&self, slot: u64, signature: &str) -> bool {
+        signature == self.signature || self.slot.is_some_and(|seen| slot < seen)
+    }
+
+    pub fn save(&self, path: &Path) -> IndexerResult<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        // A checkpoint cut short by a crash would fail to load next run
+        write_atomic(path, json)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn has_seen_goes_by_slot_and_signature() {
+        let checkpoint = Checkpoint {
+            signature: "newest".to_string(),
+            timestamp: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            slot: Some(100),
+        };
+
+        assert!(checkpoint.has_seen(100, "newest"));
+        assert!(checkpoint.has_seen(99, "older"));
+        // Same block time or same slot, but not the checkpoint's transaction
+        assert!(!checkpoint.has_seen(100, "sibling"));
+        assert!(!checkpoint.has_seen(101, "newer"));
+
+        let legacy = Checkpoint { slot: None, ..checkpoint };
+        assert!(legacy.has_seen(100, "newest"));
+        assert!(!legacy.has_seen(99, "older"));
+    }
+}
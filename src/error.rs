@@ -0,0 +1,75 @@
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use thiserror::Error;
+
+/// Everything the indexer can fail with, split by kind so callers can react
+/// differently to bad input, flaky endpoints and malformed data
+#[derive(Debug, Error)]
+pub enum IndexerError {
+    #[error("Invalid public key: {0}")]
+    InvalidPubkey(String),
+
+    #[error("RPC request failed: {0}")]
+    Rpc(String),
+
+    #[error("Rate limited by RPC endpoint: {0}")]
+    RateLimited(String),
+
+    #[error("RPC request timed out: {0}")]
+    Timeout(String),
+
+    #[error("Failed to parse transaction: {0}")]
+    ParseTransaction(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type IndexerResult<T> = std::result::Result<T, IndexerError>;
+
+impl IndexerError {
+    /// Process exit code used by the CLI for this kind of failure
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            IndexerError::InvalidPubkey(_) => 2,
+            IndexerError::Rpc(_) => 3,
+            IndexerError::RateLimited(_) => 4,
+            IndexerError::ParseTransaction(_) => 5,
+            IndexerError::Io(_) => 6,
+            IndexerError::Timeout(_) => 7,
+        }
+    }
+
+    /// Whether retrying the same request may succeed
+    pub fn is_transient(&self) -> bool {
+        matches!(self, IndexerError::Rpc(_) | IndexerError::RateLimited(_) | IndexerError::Timeout(_))
+    }
+}
+
+impl From<ClientError> for IndexerError {
+    fn from(error: ClientError) -> Self {
+        let rate_limited = match error.kind() {
+            ClientErrorKind::Reqwest(e) => e.status() == Some(reqwest::StatusCode::TOO_MANY_REQUESTS),
+            _ => error.to_string().contains("429"),
+        };
+
+        // Some calls first query the node version and wrap its failure as text
+        let timed_out = match error.kind() {
+            ClientErrorKind::Reqwest(e) => e.is_timeout(),
+            _ => error.to_string().contains("operation timed out"),
+        };
+
+        if rate_limited {
+            IndexerError::RateLimited(error.to_string())
+        } else if timed_out {
+            IndexerError::Timeout(error.to_string())
+        } else {
+            IndexerError::Rpc(error.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for IndexerError {
+    fn from(error: serde_json::Error) -> Self {
+        IndexerError::ParseTransaction(error.to_string())
+    }
+}
@@ -0,0 +1,111 @@
+use reqwest::Url;
+
+use crate::transfer::UsdcTransfer;
+use crate::mints::{USDC_DEVNET, USDC_MAINNET};
+
+/// A Solana cluster, for --network defaults and transaction links
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Cluster {
+    #[value(alias = "mainnet")]
+    MainnetBeta,
+    Devnet,
+    Testnet,
+    Localnet, // A local validator, linked through the explorer's custom RPC setting
+}
+
+impl Cluster {
+    /// Guess the cluster from an RPC URL; anything unrecognized is mainnet
+    pub fn infer(rpc_url: &str) -> Self {
+        let rpc_url = rpc_url.to_lowercase();
+        if rpc_url.contains("devnet") {
+            Cluster::Devnet
+        } else if rpc_url.contains("testnet") {
+            Cluster::Testnet
+        } else if rpc_url.contains("localhost") || rpc_url.contains("127.0.0.1") {
+            Cluster::Localnet
+        } else {
+            Cluster::MainnetBeta
+        }
+    }
+
+    /// Public RPC endpoint, or the default solana-test-validator port
+    pub fn default_rpc_url(&self) -> &'static str {
+        match self {
+            Cluster::MainnetBeta => "https://api.mainnet-beta.solana.com",
+            Cluster::Devnet => "https://api.devnet.solana.com",
+            Cluster::Testnet => "https://api.testnet.solana.com",
+            Cluster::Localnet => "http://127.0.0.1:8899",
+        }
+    }
+
+    /// Circle's USDC mint on this cluster; testnet and local validators have none
+    pub fn usdc_mint(&self) -> Option<&'static str> {
+        match self {
+            Cluster::MainnetBeta => Some(USDC_MAINNET),
+            Cluster::Devnet => Some(USDC_DEVNET),
+            Cluster::Testnet | Cluster::Localnet => None,
+        }
+    }
+
+    /// Identify a public cluster by its genesis hash; local validators and
+    /// private clusters each have their own, so they aren't recognized
+    pub fn from_genesis_hash(hash: &str) -> Option<Self> {
+        match hash {
+            "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d" => Some(Cluster::MainnetBeta),
+            "EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG" => Some(Cluster::Devnet),
+            "4uhcVJyU9pJkvQyS88uRDiswHXSCkY3zQawwpjk2NsNY" => Some(Cluster::Testnet),
+            _ => None,
+        }
+    }
+}
+
+/// Builds transaction links for a block explorer. Solana Explorer and Solscan
+/// both take the cluster as a `cluster` query parameter.
+#[derive(Debug, Clone)]
+pub struct Explorer {
+    base: String,
+    cluster: Cluster,
+    rpc_url: String,
+}
+
+impl Explorer {
+    pub fn new(base: &str, cluster: Cluster, rpc_url: &str) -> Self {
+        Self {
+            base: base.trim_end_matches('/').to_string(),
+            cluster,
+            rpc_url: rpc_url.to_string(),
+        }
+    }
+
+    pub fn transaction_url(&self, signature: &str) -> String {
+        let url = format!("{}/{}", self.base, signature);
+        let Ok(mut parsed) = Url::parse(&url) else {
+            return url;
+        };
+
+        match self.cluster {
+            Cluster::MainnetBeta => {}
+            Cluster::Devnet => {
+                parsed.query_pairs_mut().append_pair("cluster", "devnet");
+            }
+            Cluster::Testnet => {
+                parsed.query_pairs_mut().append_pair("cluster", "testnet");
+            }
+            Cluster::Localnet => {
+                parsed
+                    .query_pairs_mut()
+                    .append_pair("cluster", "custom")
+                    .append_pair("customUrl", &self.rpc_url);
+            }
+        }
+
+        parsed.into()
+    }
+
+    /// Set `explorer_url` on every transfer
+    pub fn annotate(&self, transfers: &mut [UsdcTransfer]) {
+        for transfer in transfers {
+            transfer.explorer_url = Some(self.transaction_url(&transfer.signature));
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use solana_account_decoder::parse_token::UiTokenAmount;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::{RpcBlockConfig, RpcTransactionConfig};
+use solana_client::rpc_response::{RpcConfirmedTransactionStatusWithSignature, RpcKeyedAccount};
+use solana_sdk::{
+    clock::{Slot, UnixTimestamp},
+    pubkey::Pubkey,
+    signature::Signature,
+};
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, UiConfirmedBlock};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::{IndexerError, IndexerResult};
+use crate::rpc::RpcProvider;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Written alongside the fixtures so replays evaluate time windows against
+/// the moment of recording rather than the moment of replay
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    recorded_at: DateTime<Utc>,
+}
+
+// Fixture file names. Each one is keyed on everything that determines the
+// response so that replay is exact.
+
+fn signatures_key(address: &Pubkey, config: &GetConfirmedSignaturesForAddress2Config) -> String {
+    let cursor = |signature: Option<Signature>| signature.map_or_else(|| "none".to_string(), |s| s.to_string());
+    format!(
+        "signatures_{}_before-{}_until-{}_limit-{}.json",
+        address,
+        cursor(config.before),
+        cursor(config.until),
+        config.limit.unwrap_or(0)
+    )
+}
+
+fn transaction_key(signature: &Signature, config: &RpcTransactionConfig) -> String {
+    let encoding = config
+        .encoding
+        .map_or_else(|| "default".to_string(), |e| format!("{:?}", e).to_lowercase());
+    format!("transaction_{}_{}.json", signature, encoding)
+}
+
+fn block_time_key(slot: Slot) -> String {
+    format!("block_time_{}.json", slot)
+}
+
+fn block_key(slot: Slot, config: &RpcBlockConfig) -> String {
+    let details = config
+        .transaction_details
+        .map_or_else(|| "default".to_string(), |d| format!("{:?}", d).to_lowercase());
+    format!("block_{}_{}.json", slot, details)
+}
+
+fn token_accounts_key(owner: &Pubkey, mint: &Pubkey) -> String {
+    format!("token_accounts_{}_{}.json", owner, mint)
+}
+
+fn token_balance_key(account: &Pubkey) -> String {
+    format!("token_balance_{}.json", account)
+}
+
+fn signature_status_key(signature: &Signature) -> String {
+    format!("signature_status_{}.json", signature)
+}
+
+/// Passes every call through to `inner` and saves each response as JSON
+pub struct RecordingProvider<P: RpcProvider> {
+    inner: P,
+    dir: PathBuf,
+    recorded_at: DateTime<Utc>,
+}
+
+impl<P: RpcProvider> RecordingProvider<P> {
+    pub fn new(inner: P, dir: &Path) -> IndexerResult<Self> {
+        std::fs::create_dir_all(dir)?;
+
+        let recorded_at = Utc::now();
+        let manifest = serde_json::to_string_pretty(&Manifest { recorded_at })?;
+        std::fs::write(dir.join(MANIFEST_FILE), manifest)?;
+
+        Ok(Self {
+            inner,
+            dir: dir.to_path_buf(),
+            recorded_at,
+        })
+    }
+
+    fn record<T: Serialize>(&self, key: &str, response: IndexerResult<T>) -> IndexerResult<T> {
+        let response = response?;
+        let json = serde_json::to_string_pretty(&response)?;
+        std::fs::write(self.dir.join(key), json)?;
+        Ok(response)
+    }
+}
+
+impl<P: RpcProvider> RpcProvider for RecordingProvider<P> {
+    fn get_signatures_for_address_with_config(
+        &self,
+        address: &Pubkey,
+        config: GetConfirmedSignaturesForAddress2Config,
+    ) -> IndexerResult<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        let key = signatures_key(address, &config);
+        self.record(&key, self.inner.get_signatures_for_address_with_config(address, config))
+    }
+
+    fn get_transaction_with_config(
+        &self,
+        signature: &Signature,
+        config: RpcTransactionConfig,
+    ) -> IndexerResult<EncodedConfirmedTransactionWithStatusMeta> {
+        let key = transaction_key(signature, &config);
+        self.record(&key, self.inner.get_transaction_with_config(signature, config))
+    }
+
+    fn get_block_time(&self, slot: Slot) -> IndexerResult<UnixTimestamp> {
+        self.record(&block_time_key(slot), self.inner.get_block_time(slot))
+    }
+
+    fn get_block_with_config(&self, slot: Slot, config: RpcBlockConfig) -> IndexerResult<UiConfirmedBlock> {
+        self.record(&block_key(slot, &config), self.inner.get_block_with_config(slot, config))
+    }
+
+    fn get_token_accounts_by_owner(&self, owner: &Pubkey, mint: &Pubkey) -> IndexerResult<Vec<RpcKeyedAccount>> {
+        self.record(&token_accounts_key(owner, mint), self.inner.get_token_accounts_by_owner(owner, mint))
+    }
+
+    fn get_token_account_balance(&self, account: &Pubkey) -> IndexerResult<UiTokenAmount> {
+        self.record(&token_balance_key(account), self.inner.get_token_account_balance(account))
+    }
+
+    fn signature_exists(&self, signature: &Signature) -> IndexerResult<bool> {
+        self.record(&signature_status_key(signature), self.inner.signature_exists(signature))
+    }
+
+    fn last_endpoint(&self) -> &str {
+        self.inner.last_endpoint()
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        self.recorded_at
+    }
+}
+
+/// Serves responses previously saved by `RecordingProvider`, without any network
+pub struct ReplayProvider {
+    dir: PathBuf,
+    label: String,
+    recorded_at: DateTime<Utc>,
+}
+
+impl ReplayProvider {
+    pub fn new(dir: &Path) -> IndexerResult<Self> {
+        let manifest: Manifest = serde_json::from_str(&std::fs::read_to_string(dir.join(MANIFEST_FILE))?)?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            label: format!("replay:{}", dir.display()),
+            recorded_at: manifest.recorded_at,
+        })
+    }
+
+    fn load<T: DeserializeOwned>(&self, key: &str) -> IndexerResult<T> {
+        // A missing fixture means the live run never made this call, which is
+        // the replay equivalent of the RPC node rejecting it
+        let json = std::fs::read_to_string(self.dir.join(key))
+            .map_err(|_| IndexerError::Rpc(format!("No recorded response for {}", key)))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+impl RpcProvider for ReplayProvider {
+    fn get_signatures_for_address_with_config(
+        &self,
+        address: &Pubkey,
+        config: GetConfirmedSignaturesForAddress2Config,
+    ) -> IndexerResult<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        self.load(&signatures_key(address, &config))
+    }
+
+    fn get_transaction_with_config(
+        &self,
+        signature: &Signature,
+        config: RpcTransactionConfig,
+    ) -> IndexerResult<EncodedConfirmedTransactionWithStatusMeta> {
+        self.load(&transaction_key(signature, &config))
+    }
+
+    fn get_block_time(&self, slot: Slot) -> IndexerResult<UnixTimestamp> {
+        self.load(&block_time_key(slot))
+    }
+
+    fn get_block_with_config(&self, slot: Slot, config: RpcBlockConfig) -> IndexerResult<UiConfirmedBlock> {
+        self.load(&block_key(slot, &config))
+    }
+
+    fn get_token_accounts_by_owner(&self, owner: &Pubkey, mint: &Pubkey) -> IndexerResult<Vec<RpcKeyedAccount>> {
+        self.load(&token_accounts_key(owner, mint))
+    }
+
+    fn get_token_account_balance(&self, account: &Pubkey) -> IndexerResult<UiTokenAmount> {
+        self.load(&token_balance_key(account))
+    }
+
+    fn signature_exists(&self, signature: &Signature) -> IndexerResult<bool> {
+        self.load(&signature_status_key(signature))
+    }
+
+    fn last_endpoint(&self) -> &str {
+        &self.label
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        self.recorded_at
+    }
+}
+
+/// Stands in for an RPC connection when transactions are read from files
+/// with --input. Every call fails, so nothing reaches the network.
+pub struct OfflineProvider;
+
+impl OfflineProvider {
+    fn unavailable<T>(&self) -> IndexerResult<T> {
+        Err(IndexerError::Rpc("No RPC access when reading transactions from --input".to_string()))
+    }
+}
+
+impl RpcProvider for OfflineProvider {
+    fn get_signatures_for_address_with_config(
+        &self,
+        _address: &Pubkey,
+        _config: GetConfirmedSignaturesForAddress2Config,
+    ) -> IndexerResult<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        self.unavailable()
+    }
+
+    fn get_transaction_with_config(
+        &self,
+        _signature: &Signature,
+        _config: RpcTransactionConfig,
+    ) -> IndexerResult<EncodedConfirmedTransactionWithStatusMeta> {
+        self.unavailable()
+    }
+
+    fn get_block_time(&self, _slot: Slot) -> IndexerResult<UnixTimestamp> {
+        self.unavailable()
+    }
+
+    fn get_block_with_config(&self, _slot: Slot, _config: RpcBlockConfig) -> IndexerResult<UiConfirmedBlock> {
+        self.unavailable()
+    }
+
+    fn get_token_accounts_by_owner(&self, _owner: &Pubkey, _mint: &Pubkey) -> IndexerResult<Vec<RpcKeyedAccount>> {
+        self.unavailable()
+    }
+
+    fn get_token_account_balance(&self, _account: &Pubkey) -> IndexerResult<UiTokenAmount> {
+        self.unavailable()
+    }
+
+    fn signature_exists(&self, _signature: &Signature) -> IndexerResult<bool> {
+        self.unavailable()
+    }
+
+    fn last_endpoint(&self) -> &str {
+        "offline"
+    }
+}
+
+/// Read saved `getTransaction` responses from `path`: a file holding one
+/// transaction, an array of them or an object of them keyed by signature (as
+/// written by --include-raw), or a directory of such files. The
+/// `transaction_*.json` files written by `RecordingProvider` qualify, so a
+/// --record directory can be re-parsed as is. Other JSON files in a directory
+/// are skipped.
+pub fn load_transactions(path: &Path) -> IndexerResult<Vec<(Signature, EncodedConfirmedTransactionWithStatusMeta)>> {
+    let files = if path.is_dir() {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file| file.extension().is_some_and(|extension| extension == "json"))
+            .collect();
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut transactions = Vec::new();
+    for file in &files {
+        let json = std::fs::read_to_string(file)?;
+        let parsed: Vec<EncodedConfirmedTransactionWithStatusMeta> = match serde_json::from_str(&json) {
+            Ok(transaction) => vec![transaction],
+            Err(single_error) => match serde_json::from_str(&json) {
+                Ok(transactions) => transactions,
+                Err(_) => match serde_json::from_str::<BTreeMap<String, EncodedConfirmedTransactionWithStatusMeta>>(&json) {
+                    Ok(transactions) => transactions.into_values().collect(),
+                    // A file given explicitly must be a transaction
+                    Err(_) if !path.is_dir() => return Err(single_error.into()),
+                    Err(_) => continue,
+                },
+            },
+        };
+
+        for transaction in parsed {
+            let signature = match &transaction.transaction.transaction {
+                EncodedTransaction::Json(ui_transaction) => ui_transaction.signatures.first().cloned(),
+                _ => None,
+            };
+            let signature = signature
+                .and_then(|signature| Signature::from_str(&signature).ok())
+                .ok_or_else(|| {
+                    IndexerError::ParseTransaction(format!(
+                        "{}: transactions must be saved with the json or jsonParsed encoding",
+                        file.display()
+                    ))
+                })?;
+            transactions.push((signature, transaction));
+        }
+    }
+
+    Ok(transactions)
+}
+
+/// Serves canned signature pages and transactions to tests. Pages follow
+/// the RPC spec: newest first, `before` and `until` exclusive, at most
+/// `limit` long.
+#[cfg(test)]
+pub struct MockProvider {
+    signatures: Vec<RpcConfirmedTransactionStatusWithSignature>, // Newest first
+    transactions: std::collections::HashMap<String, serde_json::Value>, // Served as copies; the type isn't Clone
+    repeat_boundary: bool, // Start each page with its `before` signature, as some providers do
+    token_accounts: Vec<(String, u64)>, // The wallet's mainnet USDC accounts and their current balances
+    now: DateTime<Utc>,
+    cursors: std::sync::Mutex<Vec<(Option<Signature>, Option<Signature>)>>, // `before` and `until` of each page served
+}
+
+#[cfg(test)]
+impl MockProvider {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            signatures: Vec::new(),
+            transactions: std::collections::HashMap::new(),
+            repeat_boundary: false,
+            token_accounts: Vec::new(),
+            now,
+            cursors: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Add a transaction older than the ones added before it
+    pub fn with_transaction(mut self, transaction: EncodedConfirmedTransactionWithStatusMeta) -> Self {
+        let signature = match &transaction.transaction.transaction {
+            EncodedTransaction::Json(ui) => ui.signatures[0].clone(),
+            _ => panic!("mock transactions must be JSON encoded"),
+        };
+        self.signatures.push(RpcConfirmedTransactionStatusWithSignature {
+            signature: signature.clone(),
+            slot: transaction.slot,
+            err: None,
+            memo: None,
+            block_time: transaction.block_time,
+            confirmation_status: None,
+        });
+        let json = serde_json::to_value(&transaction).expect("mock transaction should serialize");
+        self.transactions.insert(signature, json);
+        self
+    }
+
+    pub fn with_repeated_boundary(mut self) -> Self {
+        self.repeat_boundary = true;
+        self
+    }
+
+    pub fn with_token_account(mut self, account: &str, balance: u64) -> Self {
+        self.token_accounts.push((account.to_string(), balance));
+        self
+    }
+
+    /// The `before` and `until` cursors of each signature page requested
+    pub fn cursors(&self) -> Vec<(Option<Signature>, Option<Signature>)> {
+        self.cursors.lock().expect("cursors lock poisoned").clone()
+    }
+
+    fn position(&self, signature: &Signature) -> Option<usize> {
+        let signature = signature.to_string();
+        self.signatures.iter().position(|sig_info| sig_info.signature == signature)
+    }
+}
+
+#[cfg(test)]
+impl RpcProvider for MockProvider {
+    fn get_signatures_for_address_with_config(
+        &self,
+        _address: &Pubkey,
+        config: GetConfirmedSignaturesForAddress2Config,
+    ) -> IndexerResult<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        self.cursors.lock().expect("cursors lock poisoned").push((config.before, config.until));
+
+        let start = match config.before {
+            Some(before) => match self.position(&before) {
+                Some(index) if self.repeat_boundary => index,
+                Some(index) => index + 1,
+                None => return Ok(Vec::new()),
+            },
+            None => 0,
+        };
+        let end = config.until.and_then(|until| self.position(&until)).unwrap_or(self.signatures.len());
+        let limit = config.limit.unwrap_or(1000);
+
+        Ok(self.signatures[start..end.max(start)].iter().take(limit).cloned().collect())
+    }
+
+    fn get_transaction_with_config(
+        &self,
+        signature: &Signature,
+        _config: RpcTransactionConfig,
+    ) -> IndexerResult<EncodedConfirmedTransactionWithStatusMeta> {
+        self.transactions
+            .get(&signature.to_string())
+            .ok_or_else(|| IndexerError::Rpc(format!("Unknown transaction {}", signature)))
+            .and_then(|json| Ok(serde_json::from_value(json.clone())?))
+    }
+
+    fn get_block_time(&self, slot: Slot) -> IndexerResult<UnixTimestamp> {
+        self.signatures
+            .iter()
+            .find(|sig_info| sig_info.slot == slot)
+            .and_then(|sig_info| sig_info.block_time)
+            .ok_or_else(|| IndexerError::Rpc(format!("No block time for slot {}", slot)))
+    }
+
+    fn get_block_with_config(&self, slot: Slot, _config: RpcBlockConfig) -> IndexerResult<UiConfirmedBlock> {
+        Err(IndexerError::Rpc(format!("No block {}", slot)))
+    }
+
+    fn get_token_accounts_by_owner(&self, _owner: &Pubkey, mint: &Pubkey) -> IndexerResult<Vec<RpcKeyedAccount>> {
+        if mint.to_string() != crate::mints::USDC_MAINNET {
+            return Ok(Vec::new());
+        }
+        let accounts = self.token_accounts.iter().map(|(account, _)| {
+            serde_json::json!({
+                "pubkey": account,
+                "account": {
+                    "lamports": 0,
+                    "data": ["", "base64"],
+                    "owner": crate::utils::TOKEN_PROGRAM_ID,
+                    "executable": false,
+                    "rentEpoch": 0,
+                    "space": 165,
+                },
+            })
+        });
+        Ok(serde_json::from_value(serde_json::Value::Array(accounts.collect()))?)
+    }
+
+    fn get_token_account_balance(&self, account: &Pubkey) -> IndexerResult<UiTokenAmount> {
+        let account = account.to_string();
+        let (_, balance) = self
+            .token_accounts
+            .iter()
+            .find(|(address, _)| *address == account)
+            .ok_or_else(|| IndexerError::Rpc(format!("No balance for {}", account)))?;
+        Ok(UiTokenAmount {
+            ui_amount: Some(*balance as f64 / 1e6),
+            decimals: 6,
+            amount: balance.to_string(),
+            ui_amount_string: (*balance as f64 / 1e6).to_string(),
+        })
+    }
+
+    fn signature_exists(&self, signature: &Signature) -> IndexerResult<bool> {
+        Ok(self.position(signature).is_some())
+    }
+
+    fn last_endpoint(&self) -> &str {
+        "mock"
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        self.now
+    }
+}
+
+/// A transaction in which `wallet` receives `amount` raw USDC from a fresh
+/// counterparty, in the shape `getTransaction` returns
+#[cfg(test)]
+pub fn usdc_receipt(wallet: &Pubkey, slot: Slot, block_time: UnixTimestamp, amount: u64) -> EncodedConfirmedTransactionWithStatusMeta {
+    let counterparty = Pubkey::new_unique();
+    let balance = |index: usize, owner: &Pubkey, amount: u64| {
+        serde_json::json!({
+            "accountIndex": index,
+            "mint": crate::mints::USDC_MAINNET,
+            "uiTokenAmount": {
+                "uiAmount": amount as f64 / 1e6,
+                "decimals": 6,
+                "amount": amount.to_string(),
+                "uiAmountString": (amount as f64 / 1e6).to_string(),
+            },
+            "owner": owner.to_string(),
+            "programId": crate::utils::TOKEN_PROGRAM_ID,
+        })
+    };
+    let key = |pubkey: Pubkey, signer: bool| {
+        serde_json::json!({ "pubkey": pubkey.to_string(), "writable": true, "signer": signer, "source": "transaction" })
+    };
+
+    serde_json::from_value(serde_json::json!({
+        "slot": slot,
+        "blockTime": block_time,
+        "transaction": {
+            "signatures": [Signature::new_unique().to_string()],
+            "message": {
+                "accountKeys": [key(counterparty, true), key(Pubkey::new_unique(), false), key(Pubkey::new_unique(), false)],
+                "recentBlockhash": "11111111111111111111111111111111",
+                "instructions": [],
+            },
+        },
+        "meta": {
+            "err": null,
+            "status": { "Ok": null },
+            "fee": 5000,
+            "preBalances": [1, 0, 0],
+            "postBalances": [1, 0, 0],
+            "innerInstructions": [],
+            "logMessages": [],
+            "preTokenBalances": [balance(1, &counterparty, amount), balance(2, wallet, 0)],
+            "postTokenBalances": [balance(1, &counterparty, 0), balance(2, wallet, amount)],
+            "rewards": [],
+            "loadedAddresses": { "writable": [], "readonly": [] },
+        },
+        "version": 0,
+    }))
+    .expect("mock transaction should deserialize")
+}
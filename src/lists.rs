@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Context, Result};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashSet;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::transfer::UsdcTransfer;
+
+/// A set of addresses loaded from a file: one per line, with blank lines and
+/// `#` comments ignored
+#[derive(Debug, Clone, Default)]
+pub struct AddressList {
+    addresses: HashSet<String>,
+}
+
+impl AddressList {
+    /// Load and validate the addresses in `path`; an invalid one is an error
+    /// naming its line
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+        let mut addresses = HashSet::new();
+
+        for (number, line) in contents.lines().enumerate() {
+            let address = line.split('#').next().unwrap_or_default().trim();
+            if address.is_empty() {
+                continue;
+            }
+            let address = Pubkey::from_str(address)
+                .map_err(|_| anyhow!("{}:{}: invalid address: {}", path.display(), number + 1, address))?;
+            addresses.insert(address.to_string());
+        }
+
+        Ok(Self { addresses })
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    /// Whether `transfer` moves tokens from or to an address on the list
+    pub fn touches(&self, transfer: &UsdcTransfer) -> bool {
+        self.addresses.contains(&transfer.from) || self.addresses.contains(&transfer.to)
+    }
+}
+
+/// Counterparties loaded with --allow-file and --deny-file
+#[derive(Debug, Clone, Default)]
+pub struct CounterpartyLists {
+    pub allow: Option<AddressList>,
+    pub deny: Option<AddressList>,
+}
+
+/// How many transfers each list matched
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ListMatches {
+    pub allowed: usize,
+    pub dropped: usize, // Touched no address on the allow list, so left out
+    pub denied: usize,
+}
+
+impl CounterpartyLists {
+    /// Keep only transfers touching an allowed address, when there is an
+    /// allow list, and flag those touching a denied one
+    pub fn apply(&self, transfers: &mut Vec<UsdcTransfer>) -> ListMatches {
+        let mut matches = ListMatches::default();
+
+        if let Some(allow) = &self.allow {
+            let before = transfers.len();
+            transfers.retain(|transfer| allow.touches(transfer));
+            matches.allowed = transfers.len();
+            matches.dropped = before - transfers.len();
+        }
+
+        if let Some(deny) = &self.deny {
+            for transfer in transfers.iter_mut() {
+                transfer.denied = deny.touches(transfer);
+                matches.denied += transfer.denied as usize;
+            }
+        }
+
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transfer::TransferDirection;
+
+    const ALLOWED: &str = "9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM";
+    const DENIED: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+
+    fn list(name: &str, contents: &str) -> AddressList {
+        let path = std::env::temp_dir().join(format!("usdc_{}_{}.txt", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let list = AddressList::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        list
+    }
+
+    #[test]
+    fn allow_list_keeps_and_deny_list_flags() {
+        let sent_to = |signature: &str, to: &str| UsdcTransfer {
+            to: to.to_string(),
+            ..UsdcTransfer::sample(signature, TransferDirection::Sent, 1)
+        };
+        let mut transfers = vec![sent_to("a", ALLOWED), sent_to("b", DENIED), sent_to("c", "elsewhere")];
+        let lists = CounterpartyLists {
+            allow: Some(list("allow", &format!("# Partners\n{}\n{}\n", ALLOWED, DENIED))),
+            deny: Some(list("deny", &format!("{} # Flagged\n", DENIED))),
+        };
+
+        let matches = lists.apply(&mut transfers);
+
+        let kept: Vec<(&str, bool)> = transfers.iter().map(|transfer| (transfer.signature.as_str(), transfer.denied)).collect();
+        assert_eq!(kept, vec![("a", false), ("b", true)]);
+        assert_eq!((matches.allowed, matches.dropped, matches.denied), (2, 1, 1));
+    }
+}
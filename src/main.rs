@@ -1,268 +1,3144 @@
-use anyhow::{anyhow, Result};
-use chrono::{DateTime, Duration, Utc};
-use clap::Parser;
-use solana_client::rpc_client::RpcClient;
-use solana_client::rpc_config::{RpcTransactionConfig};
+// solana_client's ClientError is large by design and we pass it through as-is
+#![allow(clippy::result_large_err)]
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use solana_client::rpc_config::{RpcBlockConfig, RpcTransactionConfig};
+use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
 use solana_sdk::{
     commitment_config::CommitmentConfig,
     pubkey::Pubkey,
     signature::Signature,
 };
-use solana_transaction_status::UiTransactionEncoding;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, EncodedTransactionWithStatusMeta, TransactionDetails,
+    UiConfirmedBlock, UiTransactionEncoding, UiTransactionStatusMeta,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::IsTerminal;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
 
+mod checkpoint;
+mod error;
+mod explorer;
+mod fixtures;
+mod lists;
+mod metrics;
+mod mints;
+mod output;
+mod pricing;
+mod progress;
+mod rate_limit;
+mod report;
+mod rpc;
+mod store;
+mod table;
+mod tokens;
 mod transfer;
 mod utils;
+mod watch;
+mod webhook;
+mod window;
+
+use checkpoint::Checkpoint;
+use error::{IndexerError, IndexerResult};
+use explorer::{Cluster, Explorer};
+use fixtures::{OfflineProvider, RecordingProvider, ReplayProvider};
+use lists::{AddressList, CounterpartyLists, ListMatches};
+use metrics::Timings;
+use mints::{DecimalsOverride, MintFilter};
+use output::{OutputFormat, OutputSink};
+use pricing::{PriceOracle, PriceSource};
+use progress::{Progress, Span};
+use report::{CostBasis, GroupBy, Period, Summary};
+use rpc::{RpcPool, RpcProvider};
+use store::{TransferFilter, TransferStore};
+use tokens::TokenRegistry;
+use transfer::{
+    dedupe_transfers, Backfill, ParserDiscrepancy, Reconciliation, TransactionCounts, TransactionKind,
+    TransferDirection, TransferKey, TransferLeg, UsdcTransfer, Validation,
+};
+use utils::{
+    classify_transaction, compare_transfer_legs, cpi_transfer_programs, format_amount, invoked_programs,
+    owner_balance_changes, owner_usdc_balances, parse_instruction_transfers, parse_memo, parse_supply_changes,
+    parse_token_transfers, parse_ui_amount, short_address, token_account_owner, transaction_account_keys,
+    transfer_authorities, wallet_usdc_token_accounts, MatchBy, TokenProgram, USDC_DECIMALS,
+};
+use window::{parse_interval, parse_lookback, IndexWindow, WindowBounds, WindowPosition};
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Without a subcommand, the options run `backfill`
+    #[command(flatten)]
+    args: Args,
+}
+
+impl Cli {
+    /// Whether the run prints its result alone on stdout, with --stdout or
+    /// --summary-only
+    fn wants_stdout(&self) -> bool {
+        match &self.command {
+            None => self.args.stdout || self.args.summary_only,
+            Some(Command::Backfill(args)) => args.stdout || args.summary_only,
+            Some(Command::Watch(_) | Command::Query(_)) => false,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Index transfers in the configured window (the default)
+    Backfill(Args),
+    /// Stream new transfers live over a websocket
+    Watch(Args),
+    /// Query transfers saved with --format sqlite, without re-indexing
+    Query(QueryArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct QueryArgs {
+    /// SQLite database written by --format sqlite
+    #[arg(long, default_value = "usdc_transfers.db")]
+    db: PathBuf,
+
+    /// Earliest transfer time, as YYYY-MM-DD or RFC 3339
+    #[arg(long)]
+    from: Option<String>,
+
+    /// Latest transfer time, as YYYY-MM-DD (inclusive) or RFC 3339 (exclusive)
+    #[arg(long)]
+    to: Option<String>,
+
+    #[arg(long, value_enum)]
+    direction: Option<TransferDirection>,
+
+    /// Only transfers to or from this address
+    #[arg(long)]
+    counterparty: Option<String>,
+
+    /// Smallest amount to include, in whole USDC
+    #[arg(long)]
+    min_amount: Option<String>,
+
+    /// Print transfers as a table even when stdout isn't a terminal
+    #[arg(long, default_value_t = false)]
+    table: bool,
+
+    /// Characters of each address shown in the table (0 shows full addresses)
+    #[arg(long, default_value_t = 8)]
+    address_width: usize,
+}
+
+#[derive(clap::Args, Debug)]
+struct Args {
+    /// Wallet address to index (repeat to index several)
+    #[arg(short, long, required_unless_present_any = ["wallets_file", "emit_schema", "open_signature"])]
+    wallet: Vec<String>,
+
+    /// File with one wallet address per line; blank lines and `#` comments are ignored
+    #[arg(long)]
+    wallets_file: Option<PathBuf>,
+
+    /// Every wallet to index, from --wallet and --wallets-file, deduplicated
+    #[arg(skip)]
+    wallets: Vec<String>,
+
+    /// RPC endpoint URL (repeat to rotate and fail over between endpoints);
+    /// defaults to the public endpoint of --network, or of mainnet-beta
+    #[arg(short, long, env = "SOLANA_RPC_URL", hide_env_values = true)]
+    rpc_url: Vec<String>,
+
+    /// Network to index: picks the default --rpc-url, the USDC mint to
+    /// recognize and the explorer cluster
+    #[arg(long, value_enum)]
+    network: Option<Cluster>,
+
+    /// Value of the `Authorization` header sent to every RPC endpoint, for
+    /// providers that take the API key as a header
+    #[arg(long, env = "SOLANA_RPC_AUTH", hide_env_values = true)]
+    rpc_auth: Option<String>,
+
+    /// Annotate each transfer with the wallet's balance after it, starting from
+    /// the on-chain balance before the oldest transfer
+    #[arg(long)]
+    running_balance: bool,
+
+    /// Starting balance in USDC for --running-balance instead of looking it up
+    /// on-chain (implies --running-balance)
+    #[arg(long)]
+    starting_balance: Option<String>,
+
+    /// Print count, mean, median, min, max and standard deviation of sent and
+    /// received amounts
+    #[arg(long)]
+    stats: bool,
+
+    /// Print how long fetching signatures, fetching transactions and parsing
+    /// took, and how many RPC calls were made
+    #[arg(long)]
+    timings: bool,
+
+    /// Process transactions that failed on-chain instead of skipping them;
+    /// their transfers are marked as failed
+    #[arg(long)]
+    include_failed: bool,
+
+    /// Save the raw transaction behind each transfer to a side file keyed by
+    /// signature (`<output>_raw.json`), which --input can parse again
+    #[arg(long, conflicts_with = "stdout")]
+    include_raw: bool,
+
+    /// Cross-check the balance-diff parser against the transactions' transfer
+    /// instructions and report where they disagree
+    #[arg(long)]
+    validate: bool,
+
+    /// Fail the run when --validate finds discrepancies in more than this
+    /// many transactions
+    #[arg(long, default_value_t = 0, requires = "validate")]
+    validate_threshold: usize,
+
+    /// Only index transactions that invoke this program, directly or through
+    /// CPI. Repeat to match any of several programs.
+    #[arg(long = "program", value_name = "PUBKEY", value_parser = Pubkey::from_str)]
+    programs: Vec<Pubkey>,
+
+    /// Parse transactions saved as JSON (a file, or a directory such as one
+    /// written by --record) instead of fetching them
+    #[arg(long, conflicts_with_all = ["replay", "record"])]
+    input: Option<PathBuf>,
+
+    /// Seconds to wait for each RPC request before retrying it
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    rpc_timeout: u64,
+
+    /// Extra `Key: Value` header sent to every RPC endpoint (repeatable)
+    #[arg(long, value_parser = rpc::parse_header)]
+    rpc_header: Vec<(HeaderName, HeaderValue)>,
+
+    /// How far back to index, as a duration like `90m`, `24h` or `7d`
+    /// (a bare number is hours)
+    #[arg(long, default_value = "24h", value_parser = parse_lookback)]
+    lookback: std::time::Duration,
+
+    /// Deprecated: use --lookback
+    #[arg(long, conflicts_with = "lookback", value_parser = clap::value_parser!(u64).range(1..))]
+    hours: Option<u64>,
+
+    /// Index by slot range instead of time, starting at this slot (inclusive).
+    /// Takes precedence over --lookback when set.
+    #[arg(long)]
+    from_slot: Option<u64>,
+
+    /// Last slot to index (inclusive); defaults to the chain tip
+    #[arg(long, requires = "from_slot")]
+    to_slot: Option<u64>,
+
+    /// Index --from-slot..--to-slot by fetching every block in it instead of
+    /// paging the wallet's signatures. One getBlock call per slot: slow and
+    /// costly, and needs an archival RPC node for old slots.
+    #[arg(long, requires = "to_slot", conflicts_with = "chunks")]
+    scan_blocks: bool,
+
+    /// Run as a service (keep running and re-index every --interval)
+    #[arg(long, default_value_t = false)]
+    service: bool,
+
+    /// After a single indexing run, keep the process alive instead of
+    /// exiting (for hosting platforms that restart exited processes)
+    #[arg(long, conflicts_with = "service")]
+    keep_alive: bool,
+
+    /// Compare the indexed net change against the wallet's on-chain USDC
+    /// balance. Only the current balance is known, so the window must run up
+    /// to now.
+    #[arg(long, default_value_t = false, conflicts_with = "to_slot")]
+    reconcile: bool,
+
+    /// Only index balances owned by this token program
+    #[arg(long, value_enum, default_value_t = TokenProgram::Any)]
+    token_program: TokenProgram,
+
+    /// Attribute transfers by the owner in token balances, or by the wallet's
+    /// token accounts fetched up front (robust to delegated or blank owners)
+    #[arg(long, value_enum, default_value_t = MatchBy::Owner)]
+    match_by: MatchBy,
+
+    /// Look up the USD value of each transfer at the time it happened
+    #[arg(long, value_enum)]
+    price_source: Option<PriceSource>,
+
+    /// Save every RPC response as a JSON fixture in this directory
+    #[arg(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Serve RPC responses from fixtures recorded with --record instead of the network
+    #[arg(long)]
+    replay: Option<PathBuf>,
+
+    /// Persist the newest indexed transaction here and only fetch newer ones next time
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+
+    /// Merge results into the existing output file instead of overwriting it
+    #[arg(long, default_value_t = false)]
+    append: bool,
+
+    /// Before appending, check JSON and CSV output against its .sha256 and
+    /// refuse to touch it on a mismatch
+    #[arg(long, requires = "append")]
+    verify: bool,
+
+    /// Output file format (repeat or comma-separate to write several at once)
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [OutputFormat::Json])]
+    format: Vec<OutputFormat>,
+
+    /// Maximum RPC requests per second across all calls
+    #[arg(long)]
+    rps: Option<u32>,
+
+    /// Add a breakdown of totals to the summary
+    #[arg(long, value_enum)]
+    group_by: Option<GroupBy>,
+
+    /// Report the N counterparties with the largest volume (also written as
+    /// CSV, except with --stdout)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    top: Option<u16>,
+
+    /// Bucket transfers into periods and report per-period totals (also
+    /// written as CSV, except with --stdout)
+    #[arg(long, value_enum)]
+    aggregate: Option<Period>,
+
+    /// Look for payments repeated to the same counterparty on a regular cadence
+    #[arg(long, default_value_t = false)]
+    recurring: bool,
+
+    /// Match sent amounts against earlier receipts and report the lots each
+    /// one used up, with realized gains when --price-source is set
+    #[arg(long, value_enum)]
+    cost_basis: Option<CostBasis>,
+
+    /// POST newly discovered transfers to this URL after each cycle
+    #[arg(long, env = "USDC_INDEXER_WEBHOOK_URL", hide_env_values = true)]
+    webhook_url: Option<String>,
+
+    /// Alert when net outflow (sent minus received) in the window exceeds this many USDC
+    #[arg(long)]
+    alert_outflow: Option<String>,
+
+    /// Flag transfers more than this many standard deviations above the mean
+    /// amount of their direction
+    #[arg(long)]
+    anomaly_sigma: Option<f64>,
+
+    /// Flag transfers above this many USDC
+    #[arg(long)]
+    anomaly_threshold: Option<String>,
+
+    /// POST newly flagged anomalies to --webhook-url as an alert
+    #[arg(long)]
+    alert_anomalies: bool,
+
+    /// Only keep transfers from or to an address in this file (one per
+    /// line, `#` starts a comment)
+    #[arg(long)]
+    allow_file: Option<PathBuf>,
+
+    /// Flag transfers from or to an address in this file
+    #[arg(long)]
+    deny_file: Option<PathBuf>,
+
+    /// POST newly flagged transfers with a denied address to --webhook-url as an alert
+    #[arg(long)]
+    alert_denied: bool,
+
+    /// Split a --from-slot/--to-slot range into this many chunks indexed concurrently
+    #[arg(long, default_value_t = 1)]
+    chunks: usize,
+
+    /// Send getTransaction calls in JSON-RPC batches of this size, for
+    /// providers that accept batches; falls back to single calls otherwise
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    transaction_batch: u16,
+
+    /// How many wallets to index at the same time; --rps still caps them all together
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    wallet_concurrency: u16,
+
+    /// Hide the backfill progress bar
+    #[arg(short, long, default_value_t = false)]
+    quiet: bool,
+
+    /// Print transfers as a table even when stdout isn't a terminal
+    #[arg(long, default_value_t = false)]
+    table: bool,
+
+    /// Characters of each address shown in the table (0 shows full addresses)
+    #[arg(long, default_value_t = 8)]
+    address_width: usize,
+
+    /// Solana token list JSON (solana.tokenlist.json format) used to show
+    /// symbols for mints beyond the bundled well-known ones
+    #[arg(long)]
+    token_list: Option<PathBuf>,
+
+    /// Where to write exported transfers; `{wallet}` is replaced with the
+    /// wallet address (defaults to usdc_transfers.<ext> in the working directory)
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// Gzip JSON and NDJSON output, adding `.gz` to the file name. An
+    /// --output ending in `.gz` is always compressed.
+    #[arg(long)]
+    compress: bool,
+
+    /// Print the transfers as a JSON array on stdout instead of writing any
+    /// files; logs go to stderr
+    #[arg(long, conflicts_with_all = ["service", "keep_alive"])]
+    stdout: bool,
+
+    /// Print only a one-line total per wallet on stdout, without listing or
+    /// exporting transfers; logs go to stderr. For cron logs.
+    #[arg(long, conflicts_with_all = ["stdout", "include_raw"])]
+    summary_only: bool,
+
+    /// Transaction link prefix; the signature and cluster are added to it
+    #[arg(long, default_value = "https://explorer.solana.com/tx")]
+    explorer_url: String,
+
+    /// Cluster for transaction links (inferred from the first --rpc-url by default)
+    #[arg(long, value_enum)]
+    cluster: Option<Cluster>,
+
+    /// Serve Prometheus metrics on this port at /metrics
+    #[arg(long)]
+    metrics_port: Option<u16>,
+
+    /// Stop a backfill after processing this many transactions, leaving the
+    /// rest of the window unindexed
+    #[arg(long)]
+    max_transactions: Option<usize>,
+
+    /// Price in USD per million RPC calls, to estimate what the
+    /// getSignaturesForAddress and getTransaction calls of a run cost
+    #[arg(long, value_name = "USD")]
+    cost_per_million: Option<f64>,
+
+    /// Websocket endpoint for `watch` (derived from the first --rpc-url by default)
+    #[arg(long, env = "SOLANA_WS_URL", hide_env_values = true)]
+    ws_url: Option<String>,
+
+    /// With `watch`, follow balance changes of the wallet's USDC token
+    /// accounts (accountSubscribe) instead of every transaction mentioning it
+    #[arg(long)]
+    watch_accounts: bool,
+
+    /// Don't probe the RPC endpoints before indexing
+    #[arg(long)]
+    skip_health_check: bool,
+
+    /// Treat this mint as USDC too, e.g. one created on solana-test-validator
+    #[arg(long, value_parser = Pubkey::from_str)]
+    usdc_mint_override: Option<Pubkey>,
+
+    /// Force a mint's decimals instead of reading them from the chain: `N`
+    /// for the USDC mints or `MINT:N` for one mint, at most 18. Repeatable.
+    #[arg(long = "decimals", value_name = "[MINT:]N", value_parser = mints::parse_decimals_override)]
+    decimals: Vec<DecimalsOverride>,
+
+    /// Show at most N decimal places in terminal output, rounding the rest
+    #[arg(long, value_name = "N")]
+    decimals_display: Option<u8>,
+
+    /// Also index wrapped SOL transfers between the wallet and others. Wraps
+    /// and unwraps of the wallet's own SOL are left out, and wSOL doesn't
+    /// count towards the USDC totals.
+    #[arg(long)]
+    include_wsol: bool,
+
+    /// How often --service re-indexes, as seconds or a duration like `15m`
+    #[arg(long, default_value = "1h", value_parser = parse_interval)]
+    interval: std::time::Duration,
+
+    /// Validate the configuration and print what would be indexed, then exit
+    /// without fetching any transactions
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Write the JSON Schema of the JSON output to this file and exit
+    #[arg(long, value_name = "PATH")]
+    emit_schema: Option<PathBuf>,
+
+    /// Print the explorer link for this transaction and exit
+    #[arg(long, value_name = "SIG")]
+    open_signature: Option<String>,
+
+    /// With --open-signature, also open the link in the default browser
+    #[arg(long, requires = "open_signature")]
+    open: bool,
+
+    /// The mints to index, built by `main` from --network,
+    /// --usdc-mint-override, --include-wsol and --decimals
+    #[arg(skip)]
+    mints: MintFilter,
+
+    /// Mint symbols, built by `main` from the bundled tokens and --token-list
+    #[arg(skip)]
+    tokens: TokenRegistry,
+
+    /// Counterparties loaded by `main` from --allow-file and --deny-file
+    #[arg(skip)]
+    lists: CounterpartyLists,
+}
+
+impl Args {
+    fn rpc_pool(&self) -> IndexerResult<RpcPool> {
+        let pool = RpcPool::new(&self.rpc_url, self.rpc_headers()?, self.rpc_timeout())?;
+        Ok(match self.rps {
+            Some(rps) => pool.with_rate_limiter(rate_limit::shared(rps)),
+            None => pool,
+        })
+    }
+
+    /// Validate the endpoint URLs and, unless --skip-health-check is set,
+    /// make sure each RPC endpoint answers. Replays never touch the network.
+    fn check_endpoints(&self, watch: bool) -> IndexerResult<()> {
+        if self.replay.is_some() || self.input.is_some() {
+            return Ok(());
+        }
+
+        let pool = RpcPool::new(&self.rpc_url, self.rpc_headers()?, self.rpc_timeout())?;
+        if watch {
+            rpc::check_url(&self.ws_url(), &["ws", "wss"])?;
+        }
+        if !self.skip_health_check {
+            for (url, genesis_hash) in pool.check_health()? {
+                if let Some(genesis_hash) = genesis_hash {
+                    warn_on_cluster_mismatch(&url, Cluster::from_genesis_hash(&genesis_hash), &self.mints);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Extra headers sent with every RPC request. Their values are treated
+    /// as secrets, since they usually carry API keys.
+    fn rpc_headers(&self) -> IndexerResult<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.rpc_header {
+            let mut value = value.clone();
+            value.set_sensitive(true);
+            headers.append(name.clone(), value);
+        }
+
+        // --rpc-auth wins over an Authorization given with --rpc-header
+        if let Some(auth) = &self.rpc_auth {
+            let mut value = HeaderValue::from_str(auth)
+                .map_err(|_| IndexerError::Rpc("--rpc-auth is not a valid header value".to_string()))?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+        Ok(headers)
+    }
+
+    fn rpc_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.rpc_timeout)
+    }
+
+    /// Whether exports can be written while the backfill runs. Running
+    /// balances and --anomaly-sigma need the whole window first, and only
+    /// sequential paging finds transfers newest first, as the JSON array
+    /// keeps them.
+    fn streams_export(&self) -> bool {
+        !self.stdout
+            && !self.summary_only
+            && !self.running_balance
+            && self.starting_balance.is_none()
+            && self.anomaly_sigma.is_none()
+            && !(self.format.contains(&OutputFormat::Json) && (self.scan_blocks || self.chunks > 1))
+    }
+
+    /// Mark unusually large transfers per --anomaly-sigma and --anomaly-threshold
+    fn flag_anomalies(&self, transfers: &mut [UsdcTransfer]) -> Result<()> {
+        if self.anomaly_sigma.is_none() && self.anomaly_threshold.is_none() {
+            return Ok(());
+        }
+
+        let threshold = match &self.anomaly_threshold {
+            Some(amount) => Some(
+                parse_ui_amount(amount, self.mints.usdc_decimals())
+                    .and_then(|raw| u64::try_from(raw).ok())
+                    .ok_or_else(|| anyhow::anyhow!("Invalid --anomaly-threshold: {}", amount))?,
+            ),
+            None => None,
+        };
+        report::flag_anomalies(transfers, &self.mints, self.anomaly_sigma, threshold);
+        Ok(())
+    }
+
+    fn explorer(&self) -> Explorer {
+        let cluster = self.cluster.or(self.network).unwrap_or_else(|| Cluster::infer(&self.rpc_url[0]));
+        Explorer::new(&self.explorer_url, cluster, &self.rpc_url[0])
+    }
+
+    /// The cluster named by --cluster or --network, else the one the RPC
+    /// endpoint reports, else a guess from its URL
+    fn detect_cluster(&self) -> Cluster {
+        if let Some(cluster) = self.cluster.or(self.network) {
+            return cluster;
+        }
+
+        let reported = self
+            .rpc_pool()
+            .and_then(|pool| pool.genesis_hash())
+            .ok()
+            .and_then(|hash| Cluster::from_genesis_hash(&hash));
+        reported.unwrap_or_else(|| Cluster::infer(&self.rpc_url[0]))
+    }
+
+    /// Websocket endpoint for `watch`
+    fn ws_url(&self) -> String {
+        match &self.ws_url {
+            Some(url) => url.clone(),
+            None => watch::websocket_url(&self.rpc_url[0]),
+        }
+    }
+
+    /// Collect wallets from --wallet and --wallets-file, keeping the first
+    /// occurrence of each. File entries are validated so a typo is reported
+    /// with its line number.
+    fn resolve_wallets(&self) -> Result<Vec<String>> {
+        let mut wallets = self.wallet.clone();
+
+        if let Some(path) = &self.wallets_file {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Cannot read wallets file {}", path.display()))?;
+
+            for (index, line) in contents.lines().enumerate() {
+                let address = line.split('#').next().unwrap_or("").trim();
+                if address.is_empty() {
+                    continue;
+                }
+
+                if Pubkey::from_str(address).is_err() {
+                    return Err(IndexerError::InvalidPubkey(format!(
+                        "{} (line {} of {})",
+                        address,
+                        index + 1,
+                        path.display()
+                    ))
+                    .into());
+                }
+                wallets.push(address.to_string());
+            }
+        }
+
+        let mut seen = HashSet::new();
+        wallets.retain(|wallet| seen.insert(wallet.clone()));
+        Ok(wallets)
+    }
+
+    /// Give each wallet its own copy of a file: `{wallet}` in the path is
+    /// replaced, and with several wallets and no placeholder the address is
+    /// appended to the file name
+    fn per_wallet_path(&self, path: &std::path::Path, wallet: &str) -> PathBuf {
+        let path_str = path.to_string_lossy();
+        if path_str.contains("{wallet}") {
+            return PathBuf::from(path_str.replace("{wallet}", wallet));
+        }
+
+        if self.wallets.len() <= 1 {
+            return path.to_path_buf();
+        }
+
+        let stem = path.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+        let name = match path.extension() {
+            Some(extension) => format!("{}_{}.{}", stem, wallet, extension.to_string_lossy()),
+            None => format!("{}_{}", stem, wallet),
+        };
+        path.with_file_name(name)
+    }
+
+    /// CSV side file for a report such as `daily_totals`: `<output stem>_<report>.csv`
+    /// with --output, so its directory and `{wallet}` carry over, and
+    /// `usdc_<report>.csv` otherwise
+    fn report_path(&self, report: &str, wallet: &str) -> PathBuf {
+        let path = match &self.output {
+            Some(output) => {
+                let name = output.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+                let stem = name.split('.').next().unwrap_or_default();
+                output.with_file_name(format!("{}_{}.csv", stem, report))
+            }
+            None => PathBuf::from(format!("usdc_{}.csv", report)),
+        };
+        self.per_wallet_path(&path, wallet)
+    }
+
+    fn output_path(&self, format: OutputFormat, wallet: &str) -> PathBuf {
+        let path = match &self.output {
+            // Several formats share one --output; give each its own extension
+            Some(path) if self.format.len() > 1 => path.with_extension(format.extension()),
+            Some(path) => path.clone(),
+            None => format.default_path(),
+        };
+        let path = match path.file_name() {
+            Some(name) if self.compress && format.compressible() && !output::is_gzip(&path) => {
+                let mut name = name.to_os_string();
+                name.push(".gz");
+                path.with_file_name(name)
+            }
+            _ => path,
+        };
+        self.per_wallet_path(&path, wallet)
+    }
+
+    /// Side file for --include-raw, named after the first output
+    fn raw_path(&self, wallet: &str) -> PathBuf {
+        let format = self.format.first().copied().unwrap_or(OutputFormat::Json);
+        let path = self.output_path(format, wallet);
+        let name = path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        let stem = name.split('.').next().unwrap_or_default();
+        path.with_file_name(format!("{}_raw.json", stem))
+    }
+
+    fn state_path(&self, wallet: &str) -> Option<PathBuf> {
+        self.state_file.as_ref().map(|path| self.per_wallet_path(path, wallet))
+    }
+
+    /// Slot bounds win over the time window when both are given
+    fn window(&self) -> IndexWindow {
+        match self.from_slot {
+            Some(from) => IndexWindow::Slots { from, to: self.to_slot },
+            None => IndexWindow::Lookback(match self.hours {
+                Some(hours) => std::time::Duration::from_secs(hours * 3600),
+                None => self.lookback,
+            }),
+        }
+    }
+}
+
+/// Signatures fetched per `getSignaturesForAddress` page (the RPC maximum)
+const SIGNATURE_BATCH_LIMIT: usize = 1000;
+
+/// Attempts at an RPC call that fails transiently, such as fetching a page
+/// of signatures or a transaction, and the delay before the first retry; it
+/// doubles after each one
+const RPC_RETRY_ATTEMPTS: u32 = 4;
+const RPC_RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Run `call` until it succeeds or fails with a non-transient error, retrying
+/// with exponential backoff up to `RPC_RETRY_ATTEMPTS` times
+async fn retry_with_backoff<T, F, Fut>(progress: &Progress, what: &str, mut call: F) -> IndexerResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = IndexerResult<T>>,
+{
+    let mut backoff = RPC_RETRY_BACKOFF;
+    let mut attempt = 1;
+
+    loop {
+        match call().await {
+            Err(e) if e.is_transient() && attempt < RPC_RETRY_ATTEMPTS => {
+                progress.println(format!("⚠️ {} failed ({}), retrying in {}s...", what, e, backoff.as_secs()));
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// How blocks are fetched by --scan-blocks: just each transaction's accounts,
+/// enough to tell which ones mention the wallet
+const SCAN_BLOCK_CONFIG: RpcBlockConfig = RpcBlockConfig {
+    encoding: None,
+    transaction_details: Some(TransactionDetails::Accounts),
+    rewards: Some(false),
+    commitment: Some(CommitmentConfig::confirmed()),
+    max_supported_transaction_version: Some(0),
+};
+
+/// How transactions are fetched for indexing
+const PARSED_TRANSACTION_CONFIG: RpcTransactionConfig = RpcTransactionConfig {
+    encoding: Some(UiTransactionEncoding::JsonParsed),
+    commitment: Some(CommitmentConfig::confirmed()),
+    max_supported_transaction_version: Some(0),
+};
+
+pub struct SolanaIndexer<P: RpcProvider> {
+    rpc: P,
+    wallet_pubkey: Pubkey,
+    /// The wallet's USDC token account addresses
+    wallet_token_accounts: HashSet<String>,
+    mints: MintFilter,
+    token_program: TokenProgram,
+    match_by: MatchBy,
+    /// Concurrent slot ranges for bounded backfills
+    chunks: usize,
+    /// Signatures requested per page
+    page_size: usize,
+    /// getTransaction calls per JSON-RPC batch; 1 sends them singly
+    transaction_batch: usize,
+    /// A batch failed, so the rest of the backfill sends calls singly
+    batching_failed: AtomicBool,
+    /// Hide the backfill progress bar
+    quiet: bool,
+    /// Stop a backfill after processing this many signatures
+    max_transactions: Option<usize>,
+    /// Signatures processed by the current backfill, across chunks
+    processed: AtomicUsize,
+    /// A range of the current backfill stopped on a signature fetch failure
+    signatures_failed: AtomicBool,
+    /// Signatures of the current backfill that errored even after retrying
+    unprocessed: Mutex<Vec<String>>,
+    include_failed: bool,
+    /// Keep the raw transactions that produced transfers
+    include_raw: bool,
+    /// Index slot ranges block by block instead of by signature
+    scan_blocks: bool,
+    /// Raw transactions of the current backfill, by signature
+    raw_transactions: Mutex<BTreeMap<String, EncodedConfirmedTransactionWithStatusMeta>>,
+    /// Cross-check the parsers on every transaction
+    validate: bool,
+    /// Parser cross-check of the current backfill, or of --input
+    validation: Mutex<Validation>,
+    /// Stops the current backfill early, keeping what it found
+    cancel: CancellationToken,
+    /// The current backfill was stopped through `cancel`
+    interrupted: AtomicBool,
+    /// Only index transactions invoking one of these; empty for all
+    programs: HashSet<String>,
+    /// Tally of the current backfill, across chunks
+    counts: Mutex<TransactionCounts>,
+    timings: Mutex<Timings>,
+}
+
+impl<P: RpcProvider> SolanaIndexer<P> {
+    pub fn new(rpc: P, wallet_address: &str, mints: &MintFilter) -> IndexerResult<Self> {
+        let wallet_pubkey = Pubkey::from_str(wallet_address)
+            .map_err(|_| IndexerError::InvalidPubkey(wallet_address.to_string()))?;
+
+        Ok(Self {
+            rpc,
+            wallet_pubkey,
+            wallet_token_accounts: wallet_usdc_token_accounts(&wallet_pubkey, mints),
+            mints: mints.clone(),
+            token_program: TokenProgram::Any,
+            match_by: MatchBy::Owner,
+            chunks: 1,
+            page_size: SIGNATURE_BATCH_LIMIT,
+            transaction_batch: 1,
+            batching_failed: AtomicBool::new(false),
+            quiet: false,
+            max_transactions: None,
+            processed: AtomicUsize::new(0),
+            signatures_failed: AtomicBool::new(false),
+            unprocessed: Mutex::new(Vec::new()),
+            include_failed: false,
+            include_raw: false,
+            scan_blocks: false,
+            raw_transactions: Mutex::new(BTreeMap::new()),
+            validate: false,
+            validation: Mutex::new(Validation::default()),
+            cancel: CancellationToken::new(),
+            interrupted: AtomicBool::new(false),
+            programs: HashSet::new(),
+            counts: Mutex::new(TransactionCounts::default()),
+            timings: Mutex::new(Timings::default()),
+        })
+    }
+
+    pub fn with_token_program(mut self, token_program: TokenProgram) -> Self {
+        self.token_program = token_program;
+        self
+    }
+
+    pub fn with_chunks(mut self, chunks: usize) -> Self {
+        self.chunks = chunks.max(1);
+        self
+    }
+
+    /// Page signatures `size` at a time, so tests can cross page boundaries
+    /// with a handful of transactions
+    #[cfg(test)]
+    fn with_page_size(mut self, size: usize) -> Self {
+        self.page_size = size.max(1);
+        self
+    }
+
+    /// Fetch transactions in JSON-RPC batches of `size` for providers that
+    /// support them
+    pub fn with_transaction_batch(mut self, size: usize) -> Self {
+        self.transaction_batch = size.max(1);
+        self
+    }
+
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    pub fn with_max_transactions(mut self, max_transactions: Option<usize>) -> Self {
+        self.max_transactions = max_transactions;
+        self
+    }
+
+    /// Choose how transfers are attributed; matching by account fetches the
+    /// wallet's USDC token accounts right away
+    pub fn with_match_by(mut self, match_by: MatchBy) -> IndexerResult<Self> {
+        if match_by == MatchBy::Account {
+            for mint in self.mints.usdc_mints() {
+                for account in self.rpc.get_token_accounts_by_owner(&self.wallet_pubkey, &mint)? {
+                    self.wallet_token_accounts.insert(account.pubkey);
+                }
+            }
+            println!("🗂️ Matching transfers against {} token accounts of the wallet", self.wallet_token_accounts.len());
+        }
+        self.match_by = match_by;
+        Ok(self)
+    }
+
+    pub fn with_include_failed(mut self, include_failed: bool) -> Self {
+        self.include_failed = include_failed;
+        self
+    }
+
+    /// Keep the raw transaction behind each transfer, returned in
+    /// `Backfill::raw_transactions`
+    pub fn with_include_raw(mut self, include_raw: bool) -> Self {
+        self.include_raw = include_raw;
+        self
+    }
+
+    /// Index bounded slot ranges by fetching every block in them rather than
+    /// paging the wallet's signatures; for archival nodes
+    pub fn with_scan_blocks(mut self, scan_blocks: bool) -> Self {
+        self.scan_blocks = scan_blocks;
+        self
+    }
+
+    /// Run the instruction parser alongside the balance-diff one on every
+    /// transaction and record where they disagree; see `take_validation`
+    pub fn with_validate(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+
+    /// The parser cross-check recorded since the last call
+    pub fn take_validation(&self) -> Validation {
+        std::mem::take(&mut *self.validation.lock().expect("validation lock poisoned"))
+    }
+
+    /// Only index transactions that invoke one of `programs`, directly or
+    /// through CPI. No programs means every transaction.
+    pub fn with_programs(mut self, programs: &[Pubkey]) -> Self {
+        self.programs = programs.iter().map(Pubkey::to_string).collect();
+        self
+    }
+
+    /// Stop backfills early once `cancel` is cancelled. They return the
+    /// transfers found so far, marked partial.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Whether the current backfill should stop; records it as interrupted
+    fn interrupted(&self, progress: &Progress) -> bool {
+        if !self.cancel.is_cancelled() {
+            return false;
+        }
+        if !self.interrupted.swap(true, Ordering::Relaxed) {
+            progress.println("🛑 Interrupted; stopping with the transfers found so far");
+        }
+        true
+    }
+
+    fn count(&self, tally: impl FnOnce(&mut TransactionCounts)) {
+        tally(&mut self.counts.lock().expect("counts lock poisoned"));
+    }
+
+    /// Run `f`, adding the time it took to one phase of --timings
+    fn timed<T>(&self, phase: fn(&mut Timings) -> &mut Duration, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        *phase(&mut self.timings.lock().expect("timings lock poisoned")) += start.elapsed();
+        result
+    }
+
+    /// Count one more signature towards --max-transactions. Returns false
+    /// once the cap is used up.
+    fn claim_transaction(&self) -> bool {
+        let processed = self.processed.fetch_add(1, Ordering::Relaxed);
+        self.max_transactions.map_or(true, |max| processed < max)
+    }
+
+    fn cap_reached(&self) -> bool {
+        self.max_transactions
+            .is_some_and(|max| self.processed.load(Ordering::Relaxed) > max)
+    }
+
+    /// Index USDC transfers inside `window`. With a checkpoint
+    /// from a previous run, pagination stops at it so only newer transactions
+    /// are fetched; if a reorg dropped the checkpoint's transaction, it stops
+    /// at the checkpoint's slot or block time instead.
+    pub async fn backfill_usdc_transfers(
+        &self,
+        window: IndexWindow,
+        since: Option<&Checkpoint>,
+    ) -> IndexerResult<Backfill> {
+        self.backfill_usdc_transfers_streaming(window, since, None).await
+    }
+
+    /// Like `backfill_usdc_transfers`, but also sends each transfer to
+    /// `sender` as soon as its transaction is parsed, for exports and
+    /// embedders that handle transfers live. Sent transfers are in the window
+    /// and not repeated, but carry no prices, balances or anomaly flags yet.
+    pub async fn backfill_usdc_transfers_streaming(
+        &self,
+        window: IndexWindow,
+        since: Option<&Checkpoint>,
+        sender: Option<&UnboundedSender<UsdcTransfer>>,
+    ) -> IndexerResult<Backfill> {
+        println!("🔍 Starting USDC transfer indexing for wallet: {}", self.wallet_pubkey);
+        println!("📅 Indexing {}", window);
+
+        // One clock reading for the whole backfill, so transactions landing
+        // while it runs can't shift the edges
+        let now = self.rpc.now();
+        let mut bounds = window.bounds(now);
+
+        let until_signature = match since {
+            Some(checkpoint) => {
+                println!("📌 Resuming after checkpoint {} ({})", checkpoint.signature, checkpoint.timestamp);
+                let signature = parse_signature(&checkpoint.signature)?;
+                match self.rpc.signature_exists(&signature) {
+                    Ok(false) => {
+                        // Dropped by a reorg: paging `until` it would never stop early
+                        bounds = bounds.starting_at(checkpoint.slot, checkpoint.timestamp);
+                        println!("⚠️ Checkpoint transaction is no longer on chain; resuming from {} instead", bounds);
+                        None
+                    }
+                    Ok(true) => Some(signature),
+                    Err(e) => {
+                        println!("⚠️ Could not confirm the checkpoint transaction ({}); resuming from it anyway", e);
+                        Some(signature)
+                    }
+                }
+            }
+            None => None,
+        };
+        self.processed.store(0, Ordering::Relaxed);
+        self.signatures_failed.store(false, Ordering::Relaxed);
+        self.unprocessed.lock().expect("unprocessed lock poisoned").clear();
+        self.raw_transactions.lock().expect("raw transactions lock poisoned").clear();
+        self.take_validation();
+        self.batching_failed.store(false, Ordering::Relaxed);
+        self.interrupted.store(false, Ordering::Relaxed);
+        self.count(|counts| *counts = TransactionCounts::default());
+        *self.timings.lock().expect("timings lock poisoned") = Timings::default();
+        let started = Instant::now();
+        let rpc_calls = metrics::metrics().rpc_requests.get();
+
+        let (all_transfers, newest) = match bounds {
+            WindowBounds::Slots { from, to: Some(to) } if self.scan_blocks => {
+                let progress = Progress::new(self.quiet, Some(Span::Slots { to, slots: to.saturating_sub(from) }));
+                let result = self.scan_blocks(from, to, sender, &progress).await;
+                progress.finish();
+                result?
+            }
+            WindowBounds::Slots { from, to: Some(to) } if self.chunks > 1 && to >= from => {
+                // Chunks advance independently, so there's no single position to show
+                let progress = Progress::new(self.quiet, None);
+                let result = self.backfill_chunks(from, to, until_signature, sender, &progress);
+                progress.finish();
+                result?
+            }
+            _ => {
+                if self.chunks > 1 {
+                    println!("⚠️ --chunks needs both --from-slot and --to-slot; indexing sequentially");
+                }
+
+                let span = match window {
+                    IndexWindow::Lookback(duration) => Some(Span::Time { end: now, seconds: duration.as_secs() }),
+                    IndexWindow::Slots { from, to: Some(to) } => Some(Span::Slots { to, slots: to.saturating_sub(from) }),
+                    IndexWindow::Slots { to: None, .. } => None,
+                };
+                let progress = Progress::new(self.quiet, span);
+                let result = self.backfill_range(bounds, None, until_signature, sender, &progress).await;
+                progress.finish();
+                result?
+            }
+        };
+
+        // `before` boundaries can repeat across batches, so the same
+        // signature may have been processed more than once. Then keep only
+        // transfers within the window.
+        let filtered_transfers: Vec<UsdcTransfer> = dedupe_transfers(all_transfers)
+            .into_iter()
+            .filter(|transfer| bounds.contains(transfer.slot, transfer.timestamp))
+            .collect();
+
+        println!("🎯 Found {} USDC transfers in {}", filtered_transfers.len(), window);
+        metrics::metrics().transfers_found.inc_by(filtered_transfers.len() as u64);
+
+        let capped = self.cap_reached();
+        if capped {
+            println!(
+                "⚠️ Stopped after --max-transactions {}; results may be partial",
+                self.max_transactions.unwrap_or_default()
+            );
+        }
+        let signatures_failed = self.signatures_failed.load(Ordering::Relaxed);
+        let interrupted = self.interrupted.load(Ordering::Relaxed);
+        let partial = capped || signatures_failed || interrupted;
+
+        Ok(Backfill {
+            transfers: filtered_transfers,
+            // Nothing new since the checkpoint: keep resuming from it. A partial
+            // run skipped older transactions, so it must not advance past them.
+            checkpoint: if partial { since.cloned() } else { newest.or_else(|| since.cloned()) },
+            partial,
+            signatures_failed,
+            interrupted,
+            unprocessed: std::mem::take(&mut *self.unprocessed.lock().expect("unprocessed lock poisoned")),
+            raw_transactions: std::mem::take(&mut *self.raw_transactions.lock().expect("raw transactions lock poisoned")),
+            validation: self.take_validation(),
+            counts: *self.counts.lock().expect("counts lock poisoned"),
+            timings: Timings {
+                total: started.elapsed(),
+                rpc_calls: metrics::metrics().rpc_requests.get() - rpc_calls,
+                ..*self.timings.lock().expect("timings lock poisoned")
+            },
+        })
+    }
+
+    /// Page backwards from `before` (or the newest transaction) until the
+    /// window boundary or `until`, processing every signature on the way.
+    /// Returns the transfers found and a checkpoint for the newest signature.
+    async fn backfill_range(
+        &self,
+        bounds: WindowBounds,
+        before: Option<Signature>,
+        until: Option<Signature>,
+        sender: Option<&UnboundedSender<UsdcTransfer>>,
+        progress: &Progress,
+    ) -> IndexerResult<(Vec<UsdcTransfer>, Option<Checkpoint>)> {
+        let mut all_transfers = Vec::new();
+        let mut sent = HashSet::new();
+        let mut seen = HashSet::new(); // Signatures handled so far, so a page overlap isn't processed twice
+        let mut newest: Option<Checkpoint> = None;
+        let mut before_signature = before;
+        let limit = self.page_size;
+
+        loop {
+            if self.interrupted(progress) {
+                break;
+            }
+            progress.println("📡 Fetching transaction batch...");
+            
+            let signatures = match self.fetch_signatures(before_signature, until, limit, progress).await {
+                Ok(signatures) => signatures,
+                // Nothing gathered yet, so there is nothing partial to return
+                Err(e) if newest.is_none() && all_transfers.is_empty() => return Err(e),
+                Err(e) => {
+                    progress.println(format!(
+                        "⚠️ Fetching signatures failed after {} attempts ({}); stopping with partial results",
+                        RPC_RETRY_ATTEMPTS, e
+                    ));
+                    self.signatures_failed.store(true, Ordering::Relaxed);
+                    break;
+                }
+            };
+            progress.println(format!("🐛 Batch served by {}", self.rpc.last_endpoint()));
+
+            if signatures.is_empty() {
+                progress.println("✅ No more transactions found");
+                break;
+            }
+
+            // A provider repeating the boundary of a full page would otherwise
+            // serve the same page forever
+            if signatures.iter().all(|sig_info| seen.contains(&sig_info.signature)) {
+                progress.println("✅ No more transactions found");
+                break;
+            }
+
+            // The first signature of the first batch is the newest one
+            if newest.is_none() {
+                newest = signatures.first().and_then(|sig_info| {
+                    self.resolve_block_time(sig_info.block_time, sig_info.slot)
+                        .map(|timestamp| Checkpoint {
+                            signature: sig_info.signature.clone(),
+                            timestamp,
+                            slot: Some(sig_info.slot),
+                        })
+                });
+            }
+
+            progress.println(format!("🔄 Processing {} signatures...", signatures.len()));
+            let mut pending = Vec::new();
+            let mut reached_target = false;
+            let mut capped = false;
+
+            for sig_info in &signatures {
+                // `before` is exclusive per the RPC spec, but some providers
+                // repeat the boundary signature at the top of the next page
+                if !seen.insert(sig_info.signature.clone()) {
+                    progress.println(format!("🐛 Skipping {}, already seen in the previous batch", sig_info.signature));
+                    continue;
+                }
+
+                let tx_time = if bounds.needs_time() {
+                    match self.resolve_block_time(sig_info.block_time, sig_info.slot) {
+                        Some(tx_time) => Some(tx_time),
+                        None => {
+                            progress.println(format!("⚠️ Skipping transaction with unknown block time: {}", sig_info.signature));
+                            continue;
+                        }
+                    }
+                } else {
+                    None
+                };
+
+                // Check if we've gone back far enough. Signatures come newest
+                // first, so everything after a too-old one is older too.
+                match bounds.position(sig_info.slot, tx_time) {
+                    WindowPosition::After => continue,
+                    WindowPosition::Before => {
+                        progress.println(format!("⏰ Reached the start of {}", bounds));
+                        reached_target = true;
+                        break;
+                    }
+                    WindowPosition::Inside => {}
+                }
+
+                if let Some(err) = sig_info.err.as_ref().filter(|_| !self.include_failed) {
+                    progress.println(format!("⚠️ Skipping failed transaction: {:?}", err));
+                    self.count(|counts| counts.skipped_failed += 1);
+                    continue;
+                }
+
+                if !self.claim_transaction() {
+                    progress.println("🛑 Reached --max-transactions cap");
+                    capped = true;
+                    break;
+                }
+
+                pending.push((sig_info, parse_signature(&sig_info.signature)?));
+            }
+
+            let batch_transfers = self.process_pending(&pending, sender, &mut sent, progress).await;
+
+            all_transfers.extend(batch_transfers);
+
+            if capped {
+                break;
+            }
+
+            // Never fetch another batch once the window boundary was crossed
+            if reached_target {
+                progress.println("✅ Reached target time window");
+                break;
+            }
+
+            if signatures.len() < limit {
+                progress.println("✅ Fetched all available transactions");
+                break;
+            }
+
+            // Set up for next batch
+            if let Some(last) = signatures.last() {
+                before_signature = Some(parse_signature(&last.signature)?);
+            }
+
+            // Small delay to avoid rate limiting
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        }
+
+        Ok((all_transfers, newest))
+    }
+
+    /// Process `pending` signatures in --transaction-batch groups, tallying
+    /// each one and streaming its new transfers to `sender`. Returns the
+    /// transfers found; stops early when the backfill is interrupted.
+    async fn process_pending(
+        &self,
+        pending: &[(&RpcConfirmedTransactionStatusWithSignature, Signature)],
+        sender: Option<&UnboundedSender<UsdcTransfer>>,
+        sent: &mut HashSet<TransferKey>,
+        progress: &Progress,
+    ) -> Vec<UsdcTransfer> {
+        let mut found = Vec::new();
+
+        for group in pending.chunks(self.transaction_batch) {
+            if self.interrupted(progress) {
+                break;
+            }
+            let signatures: Vec<Signature> = group.iter().map(|(_, signature)| *signature).collect();
+            let results = self.process_transactions(&signatures, progress).await;
+
+            for ((sig_info, _), result) in group.iter().zip(results) {
+                let block_time = sig_info.block_time.and_then(|block_time| DateTime::from_timestamp(block_time, 0));
+                match result {
+                    Ok(transfers) => {
+                        progress.record(sig_info.slot, block_time, transfers.len());
+                        self.count(|counts| counts.processed += 1);
+                        if let Some(sender) = sender {
+                            // A dropped receiver just stops listening
+                            for transfer in transfers.iter().filter(|transfer| sent.insert(transfer.key())) {
+                                let _ = sender.send(transfer.clone());
+                            }
+                        }
+                        found.extend(transfers);
+                    }
+                    Err(e) => {
+                        progress.record(sig_info.slot, block_time, 0);
+                        self.count(|counts| counts.errored += 1);
+                        self.unprocessed.lock().expect("unprocessed lock poisoned").push(sig_info.signature.clone());
+                        progress.println(format!("⚠️ Giving up on transaction {}: {}", sig_info.signature, e));
+                    }
+                }
+            }
+        }
+
+        found
+    }
+
+    /// One page of the wallet's signatures, retried with exponential backoff
+    /// on RPC errors so a single transient failure doesn't end the backfill
+    async fn fetch_signatures(
+        &self,
+        before: Option<Signature>,
+        until: Option<Signature>,
+        limit: usize,
+        progress: &Progress,
+    ) -> IndexerResult<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        retry_with_backoff(progress, "Fetching signatures", || async move {
+            self.count(|counts| counts.signature_calls += 1);
+            self.timed(|timings| &mut timings.signatures, || {
+                self.rpc.get_signatures_for_address_with_config(
+                    &self.wallet_pubkey,
+                    solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config {
+                        limit: Some(limit),
+                        before,
+                        until,
+                        commitment: Some(CommitmentConfig::confirmed()),
+                    },
+                )
+            })
+        })
+        .await
+    }
+
+    /// Split `from..=to` into `self.chunks` slot ranges and backfill each on
+    /// its own thread, all paging backwards concurrently.
+    ///
+    /// `getSignaturesForAddress` starts from the slot of its `before`
+    /// signature, and that signature doesn't have to involve the wallet. So a
+    /// chunk's starting point is any transaction in the first non-empty block
+    /// after its upper slot; see `signature_after_slot`.
+    fn backfill_chunks(
+        &self,
+        from: u64,
+        to: u64,
+        until: Option<Signature>,
+        sender: Option<&UnboundedSender<UsdcTransfer>>,
+        progress: &Progress,
+    ) -> IndexerResult<(Vec<UsdcTransfer>, Option<Checkpoint>)> {
+        let chunks = self.chunks as u64;
+        let chunk_len = (to - from + chunks) / chunks; // Rounded up so the last chunk reaches `to`
+        let bounds: Vec<(u64, u64)> = (0..chunks)
+            .map(|i| from + i * chunk_len)
+            .take_while(|start| *start <= to)
+            .map(|start| (start, (start + chunk_len - 1).min(to)))
+            .collect();
+
+        let mut starts = Vec::new();
+        for &(_, end) in &bounds {
+            starts.push(self.signature_after_slot(end)?);
+        }
+
+        println!("🧩 Backfilling {} slot chunks concurrently", bounds.len());
+        let handle = tokio::runtime::Handle::current();
+        let results = tokio::task::block_in_place(|| {
+            std::thread::scope(|scope| {
+                let workers: Vec<_> = bounds
+                    .iter()
+                    .zip(starts)
+                    .map(|(&(start, end), before)| {
+                        let handle = &handle;
+                        scope.spawn(move || {
+                            let bounds = WindowBounds::Slots { from: start, to: Some(end) };
+                            handle.block_on(self.backfill_range(bounds, before, until, sender, progress))
+                        })
+                    })
+                    .collect();
+
+                workers
+                    .into_iter()
+                    .map(|worker| worker.join().expect("backfill chunk panicked"))
+                    .collect::<Vec<_>>()
+            })
+        });
+
+        // Merge newest chunk first to keep the usual newest-first order
+        let mut all_transfers = Vec::new();
+        let mut newest = None;
+        for result in results.into_iter().rev() {
+            let (transfers, checkpoint) = result?;
+            all_transfers.extend(transfers);
+            newest = newest.or(checkpoint);
+        }
+
+        Ok((all_transfers, newest))
+    }
+
+    /// Index `from..=to` by fetching every block, newest first, and processing
+    /// the transactions that mention the wallet. Catches what
+    /// `getSignaturesForAddress` can miss, at the cost of one `getBlock` call
+    /// per slot.
+    async fn scan_blocks(
+        &self,
+        from: u64,
+        to: u64,
+        sender: Option<&UnboundedSender<UsdcTransfer>>,
+        progress: &Progress,
+    ) -> IndexerResult<(Vec<UsdcTransfer>, Option<Checkpoint>)> {
+        let mut all_transfers = Vec::new();
+        let mut sent = HashSet::new();
+        let mut newest: Option<Checkpoint> = None;
+        let mut missing = 0;
+
+        for slot in (from..=to).rev() {
+            if self.interrupted(progress) {
+                break;
+            }
+            let Some(block) = self.fetch_block(slot, progress).await? else {
+                missing += 1;
+                continue;
+            };
+
+            let matching: Vec<RpcConfirmedTransactionStatusWithSignature> = block
+                .transactions
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|transaction| self.mentions_wallet(transaction))
+                .filter_map(|transaction| {
+                    let EncodedTransaction::Accounts(accounts) = transaction.transaction else {
+                        return None;
+                    };
+                    Some(RpcConfirmedTransactionStatusWithSignature {
+                        signature: accounts.signatures.into_iter().next()?,
+                        slot,
+                        err: transaction.meta.and_then(|meta| meta.err),
+                        memo: None,
+                        block_time: block.block_time,
+                        confirmation_status: None,
+                    })
+                })
+                .collect();
+            if matching.is_empty() {
+                continue;
+            }
+            progress.println(format!("🧱 Slot {}: {} transactions mention the wallet", slot, matching.len()));
+
+            let mut pending = Vec::new();
+            let mut capped = false;
+            for sig_info in &matching {
+                if newest.is_none() {
+                    newest = self.resolve_block_time(sig_info.block_time, slot).map(|timestamp| Checkpoint {
+                        signature: sig_info.signature.clone(),
+                        timestamp,
+                        slot: Some(slot),
+                    });
+                }
+
+                if let Some(err) = sig_info.err.as_ref().filter(|_| !self.include_failed) {
+                    progress.println(format!("⚠️ Skipping failed transaction: {:?}", err));
+                    self.count(|counts| counts.skipped_failed += 1);
+                    continue;
+                }
+
+                if !self.claim_transaction() {
+                    progress.println("🛑 Reached --max-transactions cap");
+                    capped = true;
+                    break;
+                }
+
+                pending.push((sig_info, parse_signature(&sig_info.signature)?));
+            }
+
+            all_transfers.extend(self.process_pending(&pending, sender, &mut sent, progress).await);
+            if capped {
+                break;
+            }
+        }
+
+        if missing > 0 {
+            progress.println(format!(
+                "⚠️ {} slots had no block: skipped by the leader, or missing from a node without full history",
+                missing
+            ));
+        }
+        Ok((all_transfers, newest))
+    }
+
+    /// The block at `slot` for --scan-blocks, retried with backoff on RPC
+    /// errors; `None` when the slot has no block
+    async fn fetch_block(&self, slot: u64, progress: &Progress) -> IndexerResult<Option<UiConfirmedBlock>> {
+        let what = format!("Fetching block {}", slot);
+        retry_with_backoff(progress, &what, || async move {
+            self.count(|counts| counts.block_calls += 1);
+            match self.rpc.get_block_with_config(slot, SCAN_BLOCK_CONFIG) {
+                Ok(block) => Ok(Some(block)),
+                Err(IndexerError::Rpc(message)) if message.contains("skipped") || message.contains("not available") => {
+                    Ok(None)
+                }
+                Err(e) => Err(e),
+            }
+        })
+        .await
+    }
+
+    /// Whether a block transaction touches the wallet: as an account, through
+    /// one of its token accounts, or as the owner in a token balance
+    fn mentions_wallet(&self, transaction: &EncodedTransactionWithStatusMeta) -> bool {
+        let wallet = self.wallet_pubkey.to_string();
+        let EncodedTransaction::Accounts(accounts) = &transaction.transaction else {
+            return false;
+        };
+        if accounts
+            .account_keys
+            .iter()
+            .any(|account| account.pubkey == wallet || self.wallet_token_accounts.contains(&account.pubkey))
+        {
+            return true;
+        }
+
+        let Some(meta) = &transaction.meta else {
+            return false;
+        };
+        [&meta.pre_token_balances, &meta.post_token_balances]
+            .into_iter()
+            .filter_map(|balances| match balances {
+                OptionSerializer::Some(balances) => Some(balances),
+                _ => None,
+            })
+            .flatten()
+            .any(|balance| matches!(&balance.owner, OptionSerializer::Some(owner) if *owner == wallet))
+    }
+
+    /// A signature from the first non-empty block after `slot`, used as a
+    /// `before` cursor so pagination starts at `slot`. Returns `None` (start
+    /// from the newest transaction) if no such block is found nearby.
+    fn signature_after_slot(&self, slot: u64) -> IndexerResult<Option<Signature>> {
+        const SCAN_SLOTS: u64 = 100;
+
+        for candidate in slot + 1..=slot + SCAN_SLOTS {
+            let block = self.rpc.get_block_with_config(
+                candidate,
+                RpcBlockConfig {
+                    transaction_details: Some(TransactionDetails::Signatures),
+                    rewards: Some(false),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                    ..RpcBlockConfig::default()
+                },
+            );
+
+            // Skipped slots have no block; keep scanning
+            let Ok(block) = block else {
+                continue;
+            };
+
+            if let Some(signature) = block.signatures.and_then(|signatures| signatures.into_iter().next()) {
+                return Ok(Some(parse_signature(&signature)?));
+            }
+        }
+
+        println!("⚠️ No block found after slot {}; chunk starts from the newest transaction", slot);
+        Ok(None)
+    }
+
+    async fn process_transaction(&self, signature: Signature) -> IndexerResult<Vec<UsdcTransfer>> {
+        self.count(|counts| counts.transaction_calls += 1);
+        let transaction = self.timed(|timings| &mut timings.transactions, || {
+            self.rpc.get_transaction_with_config(&signature, PARSED_TRANSACTION_CONFIG)
+        })?;
+        self.parse_fetched(signature, transaction)
+    }
+
+    /// Parse a fetched transaction, keeping it with --include-raw when it
+    /// produced transfers
+    fn parse_fetched(
+        &self,
+        signature: Signature,
+        transaction: EncodedConfirmedTransactionWithStatusMeta,
+    ) -> IndexerResult<Vec<UsdcTransfer>> {
+        let transfers = self.timed(|timings| &mut timings.parsing, || self.parse_transaction(signature, &transaction))?;
+        if self.include_raw && !transfers.is_empty() {
+            self.raw_transactions
+                .lock()
+                .expect("raw transactions lock poisoned")
+                .insert(signature.to_string(), transaction);
+        }
+        Ok(transfers)
+    }
+
+    /// `process_transaction` for each of `signatures`, fetched with a single
+    /// JSON-RPC batch when --transaction-batch allows. If a batch fails, the
+    /// rest of the backfill goes back to one request per transaction.
+    async fn process_transactions(
+        &self,
+        signatures: &[Signature],
+        progress: &Progress,
+    ) -> Vec<IndexerResult<Vec<UsdcTransfer>>> {
+        if signatures.len() > 1 && !self.batching_failed.load(Ordering::Relaxed) {
+            self.count(|counts| counts.transaction_calls += signatures.len());
+            let fetched = self.timed(|timings| &mut timings.transactions, || {
+                self.rpc.get_transactions_with_config(signatures, PARSED_TRANSACTION_CONFIG)
+            });
+
+            match fetched {
+                Ok(transactions) => {
+                    let mut results = Vec::with_capacity(signatures.len());
+                    for (signature, transaction) in signatures.iter().zip(transactions) {
+                        results.push(match transaction {
+                            Ok(transaction) => self.parse_fetched(*signature, transaction),
+                            // Refetch a transaction the batch failed on by itself
+                            Err(e) if e.is_transient() => self.process_transaction_with_retry(*signature, progress).await,
+                            Err(e) => Err(e),
+                        });
+                    }
+                    return results;
+                }
+                Err(e) => {
+                    progress.println(format!("⚠️ Batch transaction request failed ({}); fetching transactions one at a time", e));
+                    self.batching_failed.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+
+        let mut results = Vec::with_capacity(signatures.len());
+        for signature in signatures {
+            results.push(self.process_transaction_with_retry(*signature, progress).await);
+        }
+        results
+    }
+
+    /// `process_transaction`, retried with exponential backoff on RPC errors
+    /// so a transient failure doesn't drop the transaction's transfers
+    async fn process_transaction_with_retry(&self, signature: Signature, progress: &Progress) -> IndexerResult<Vec<UsdcTransfer>> {
+        let what = format!("Processing transaction {}", signature);
+        retry_with_backoff(progress, &what, || self.process_transaction(signature)).await
+    }
+
+    /// Extract the wallet's USDC transfers from a `JsonParsed` transaction,
+    /// whether just fetched or loaded from a file with --input
+    pub fn parse_transaction(
+        &self,
+        signature: Signature,
+        transaction: &EncodedConfirmedTransactionWithStatusMeta,
+    ) -> IndexerResult<Vec<UsdcTransfer>> {
+        metrics::metrics().transactions_processed.inc();
+
+        let mut transfers = Vec::new();
+        let memo = parse_memo(&transaction.transaction.transaction);
+
+        // No meta means the node couldn't say what the transaction did, which
+        // is different from a transaction that moved no tokens
+        let Some(meta) = &transaction.transaction.meta else {
+            println!("🐛 Transaction {} came back without status meta; skipping", signature);
+            metrics::metrics().transactions_without_meta.inc();
+            return Ok(transfers);
+        };
+
+        if !matches!(meta.pre_token_balances, OptionSerializer::Some(_))
+            || !matches!(meta.post_token_balances, OptionSerializer::Some(_))
+        {
+            println!(
+                "⚠️ Transaction {} came back without token balances; the RPC node may not support this encoding",
+                signature
+            );
+            metrics::metrics().transactions_without_token_balances.inc();
+        }
+
+        let account_keys = transaction_account_keys(&transaction.transaction.transaction, meta);
+        if !self.programs.is_empty()
+            && invoked_programs(&transaction.transaction.transaction, meta, &account_keys).is_disjoint(&self.programs)
+        {
+            return Ok(transfers);
+        }
+
+        if self.validate {
+            self.validate_transaction(signature, &transaction.transaction.transaction, meta, &account_keys);
+        }
+
+        if let Some(timestamp) = self.resolve_block_time(transaction.block_time, transaction.slot) {
+            let changes = owner_balance_changes(meta, &self.wallet_pubkey.to_string());
+            let (kind, counter_mint) = classify_transaction(&changes, &self.mints);
+            let supply_changes = parse_supply_changes(&transaction.transaction.transaction, meta, self.token_program);
+            let cpi_programs = cpi_transfer_programs(&transaction.transaction.transaction, meta, &account_keys);
+            let authorities = transfer_authorities(&transaction.transaction.transaction, meta);
+
+            // Parse token transfers from transaction
+            if let Some(token_transfers) = parse_token_transfers(meta, &account_keys, self.token_program, &supply_changes, &self.mints) {
+                for transfer in token_transfers {
+                    // Check if it's a USDC (or wSOL) transfer involving our wallet
+                    if self.mints.is_indexed(&transfer.mint) {
+                        let from_owner = self.resolve_owner(&transfer.from_owner, &transfer.from_account);
+                        let to_owner = self.resolve_owner(&transfer.to_owner, &transfer.to_account);
+                        // A balance entry without a usable owner can't be attributed;
+                        // drop just this leg and keep the rest of the transaction
+                        let (from_pubkey, to_pubkey) = match (parse_owner(&from_owner), parse_owner(&to_owner)) {
+                            (Ok(from), Ok(to)) => (from, to),
+                            (Err(e), _) | (_, Err(e)) => {
+                                println!("⚠️ Skipping transfer leg in {}: {}", signature, e);
+                                continue;
+                            }
+                        };
+
+                        let (from_wallet, to_wallet) = match self.match_by {
+                            MatchBy::Owner => (from_pubkey == self.wallet_pubkey, to_pubkey == self.wallet_pubkey),
+                            MatchBy::Account => (
+                                self.wallet_token_accounts.contains(&transfer.from_account),
+                                self.wallet_token_accounts.contains(&transfer.to_account),
+                            ),
+                        };
+
+                        let direction = if from_wallet && to_wallet {
+                            Some(TransferDirection::SelfTransfer)
+                        } else if from_wallet {
+                            Some(TransferDirection::Sent)
+                        } else if to_wallet {
+                            Some(TransferDirection::Received)
+                        } else {
+                            None
+                        };
+
+                        if let Some(dir) = direction {
+                            // The recipient only sees what's left after a transfer fee
+                            let amount = match dir {
+                                TransferDirection::Received => transfer.amount - transfer.transfer_fee,
+                                _ => transfer.amount,
+                            };
+
+                            transfers.push(UsdcTransfer {
+                                signature: signature.to_string(),
+                                timestamp,
+                                mint: transfer.mint.clone(),
+                                amount,
+                                decimals: transfer.decimals,
+                                direction: dir,
+                                from: from_owner,
+                                to: to_owner,
+                                from_token_account: Some(transfer.from_account.clone()).filter(|account| !account.is_empty()),
+                                to_token_account: Some(transfer.to_account.clone()).filter(|account| !account.is_empty()),
+                                via_program: cpi_programs
+                                    .get(&(transfer.from_account.clone(), transfer.to_account.clone()))
+                                    .cloned(),
+                                authority: authorities
+                                    .get(&(transfer.from_account.clone(), transfer.to_account.clone()))
+                                    .cloned(),
+                                slot: transaction.slot,
+                                fee_lamports: meta.fee,
+                                usd_value: None,
+                                memo: memo.clone(),
+                                kind,
+                                counter_mint: counter_mint.clone(),
+                                explorer_url: None,
+                                balance_after: None,
+                                failed: meta.err.is_some(),
+                                anomaly: false,
+                                denied: false,
+                            });
+                        }
+                    }
+                }
+            }
+
+            // Issuance into or burns from the wallet's own token accounts
+            for supply in supply_changes.iter().filter(|supply| self.mints.is_usdc(&supply.mint)) {
+                let owner = self.resolve_owner(
+                    &token_account_owner(meta, &account_keys, &supply.account),
+                    &supply.account,
+                );
+                let ours = match self.match_by {
+                    MatchBy::Owner => owner == self.wallet_pubkey.to_string(),
+                    MatchBy::Account => self.wallet_token_accounts.contains(&supply.account),
+                };
+                if !ours {
+                    continue;
+                }
+
+                // A mint comes from the mint authority; a burn goes back to the mint
+                let (from, to) = match supply.direction {
+                    TransferDirection::Mint => (supply.authority.clone(), owner),
+                    _ => (owner, supply.mint.clone()),
+                };
+                let (from_token_account, to_token_account) = match supply.direction {
+                    TransferDirection::Mint => (None, Some(supply.account.clone())),
+                    _ => (Some(supply.account.clone()), None),
+                };
+
+                transfers.push(UsdcTransfer {
+                    signature: signature.to_string(),
+                    timestamp,
+                    mint: supply.mint.clone(),
+                    amount: supply.amount,
+                    decimals: self.mints.decimals(&supply.mint),
+                    direction: supply.direction,
+                    from,
+                    to,
+                    from_token_account,
+                    to_token_account,
+                    via_program: None,
+                    authority: Some(supply.authority.clone()),
+                    slot: transaction.slot,
+                    fee_lamports: meta.fee,
+                    usd_value: None,
+                    memo: memo.clone(),
+                    kind,
+                    counter_mint: counter_mint.clone(),
+                    explorer_url: None,
+                    balance_after: None,
+                    failed: meta.err.is_some(),
+                    anomaly: false,
+                    denied: false,
+                });
+            }
+
+            // The wallet's USDC moved but no leg was matched to a counterparty
+            // (e.g. a swap routed through a pool). Record the movement rather
+            // than dropping it. wSOL is left out here: its unmatched movements
+            // are the wallet wrapping and unwrapping its own SOL.
+            if !transfers.iter().any(|transfer| self.mints.is_usdc(&transfer.mint)) {
+                for (mint, change) in changes.iter().filter(|(mint, _)| self.mints.is_usdc(mint)) {
+                    let wallet = self.wallet_pubkey.to_string();
+                    let (direction, from, to) = if *change < 0 {
+                        (TransferDirection::Sent, wallet, String::new())
+                    } else {
+                        (TransferDirection::Received, String::new(), wallet)
+                    };
+
+                    transfers.push(UsdcTransfer {
+                        signature: signature.to_string(),
+                        timestamp,
+                        mint: mint.clone(),
+                        amount: change.unsigned_abs() as u64,
+                        decimals: self.mints.decimals(mint),
+                        direction,
+                        from,
+                        to,
+                        from_token_account: None,
+                        to_token_account: None,
+                        via_program: None,
+                        authority: None,
+                        slot: transaction.slot,
+                        fee_lamports: meta.fee,
+                        usd_value: None,
+                        memo: memo.clone(),
+                        kind: match kind {
+                            TransactionKind::Swap => TransactionKind::Swap,
+                            _ => TransactionKind::Unknown,
+                        },
+                        counter_mint: counter_mint.clone(),
+                        explorer_url: None,
+                        balance_after: None,
+                        failed: meta.err.is_some(),
+                        anomaly: false,
+                        denied: false,
+                    });
+                }
+            }
+        }
+
+        Ok(transfers)
+    }
+
+    /// Compare the transfers found by diffing token balances with those in
+    /// the transfer instructions, for --validate. Failed transactions are left
+    /// out: their instructions moved nothing.
+    fn validate_transaction(
+        &self,
+        signature: Signature,
+        transaction: &EncodedTransaction,
+        meta: &UiTransactionStatusMeta,
+        account_keys: &[String],
+    ) {
+        if meta.err.is_some() {
+            return;
+        }
+
+        let supply_changes = parse_supply_changes(transaction, meta, self.token_program);
+        let balance = parse_token_transfers(meta, account_keys, self.token_program, &supply_changes, &self.mints).unwrap_or_default();
+        let instruction: Vec<_> = parse_instruction_transfers(transaction, meta, account_keys, self.token_program)
+            .into_iter()
+            .filter(|transfer| self.mints.is_indexed(&transfer.mint))
+            .collect();
+        let (balance_only, instruction_only) = compare_transfer_legs(&balance, &instruction);
+
+        let mut validation = self.validation.lock().expect("validation lock poisoned");
+        validation.checked += 1;
+        if !balance_only.is_empty() || !instruction_only.is_empty() {
+            validation.discrepancies.push(ParserDiscrepancy {
+                signature: signature.to_string(),
+                balance_only,
+                instruction_only,
+            });
+        }
+    }
+
+    /// Owner of a token balance entry, treating any of the wallet's own token
+    /// accounts as owned by the wallet even when the entry's owner is blank
+    /// or shows a delegate
+    fn resolve_owner(&self, owner: &str, token_account: &str) -> String {
+        if self.wallet_token_accounts.contains(token_account) {
+            self.wallet_pubkey.to_string()
+        } else {
+            owner.to_string()
+        }
+    }
+
+    /// Turn a transaction's block time into a timestamp, asking the node for the
+    /// slot's block time when the transaction response doesn't carry one.
+    /// Returns `None` rather than guessing, so undated transactions never
+    /// slip into the time window as "now".
+    fn resolve_block_time(&self, block_time: Option<i64>, slot: u64) -> Option<DateTime<Utc>> {
+        let block_time = match block_time {
+            Some(block_time) => block_time,
+            None => match self.rpc.get_block_time(slot) {
+                Ok(block_time) => block_time,
+                Err(e) => {
+                    println!("⚠️ Could not fetch block time for slot {}: {}", slot, e);
+                    return None;
+                }
+            },
+        };
+
+        DateTime::from_timestamp(block_time, 0)
+    }
+
+    /// Current USDC balance of each of the wallet's USDC token accounts, by address
+    async fn fetch_account_balances(&self) -> IndexerResult<HashMap<String, u64>> {
+        let mut balances = HashMap::new();
+
+        for mint in self.mints.usdc_mints() {
+            let accounts = self.rpc.get_token_accounts_by_owner(&self.wallet_pubkey, &mint)?;
+
+            for account in accounts {
+                let account_pubkey = Pubkey::from_str(&account.pubkey)
+                    .map_err(|_| IndexerError::InvalidPubkey(account.pubkey.clone()))?;
+                let balance = self.rpc.get_token_account_balance(&account_pubkey)?;
+                balances.insert(account.pubkey, balance.amount.parse::<u64>().unwrap_or(0));
+            }
+        }
+
+        Ok(balances)
+    }
+
+    /// Current USDC balance summed across all of the wallet's USDC token accounts
+    pub async fn fetch_usdc_balance(&self) -> IndexerResult<u64> {
+        Ok(self.fetch_account_balances().await?.values().sum())
+    }
+
+    /// USDC balances of the wallet's token accounts right before the given
+    /// transaction executed, by address. Accounts it created start at zero.
+    async fn fetch_balances_before(&self, signature: &str) -> IndexerResult<HashMap<String, u64>> {
+        let signature = parse_signature(signature)?;
+        let transaction = self.rpc.get_transaction_with_config(
+            &signature,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Json),
+                commitment: Some(CommitmentConfig::confirmed()),
+                max_supported_transaction_version: Some(0),
+            },
+        )?;
+
+        let meta = transaction
+            .transaction
+            .meta
+            .ok_or_else(|| IndexerError::ParseTransaction(format!("Transaction {} has no metadata", signature)))?;
+        let account_keys = transaction_account_keys(&transaction.transaction.transaction, &meta);
+        let wallet = self.wallet_pubkey.to_string();
+
+        let (pre, post) = match (&meta.pre_token_balances, &meta.post_token_balances) {
+            (
+                solana_transaction_status::option_serializer::OptionSerializer::Some(pre),
+                solana_transaction_status::option_serializer::OptionSerializer::Some(post),
+            ) => (pre, post),
+            _ => return Err(IndexerError::ParseTransaction(format!("Transaction {} has no token balances", signature))),
+        };
+
+        // Accounts the transaction created have only a post balance
+        let mut balances: HashMap<String, u64> = owner_usdc_balances(post, &account_keys, &wallet, &self.mints)
+            .into_keys()
+            .map(|account| (account, 0))
+            .collect();
+        balances.extend(owner_usdc_balances(pre, &account_keys, &wallet, &self.mints));
+
+        Ok(balances)
+    }
+
+    /// Wallet balance right before the oldest of `transfers`, across all of
+    /// its USDC token accounts, or `None` when there are none. Each account
+    /// counts its balance before the oldest transaction touching it; one no
+    /// transfer touched still holds its current balance.
+    pub async fn starting_balance(&self, transfers: &[UsdcTransfer]) -> IndexerResult<Option<u64>> {
+        let mut usdc: Vec<&UsdcTransfer> = transfers.iter().filter(|transfer| self.mints.is_usdc(&transfer.mint)).collect();
+        if usdc.is_empty() {
+            return Ok(None);
+        }
+        usdc.sort_by_key(|transfer| (transfer.slot, transfer.timestamp));
+
+        let mut balances = self.fetch_account_balances().await?;
+        let mut settled = HashSet::new();
+        let mut fetched = HashSet::new();
+
+        for (i, transfer) in usdc.iter().enumerate() {
+            // The oldest transaction is always looked up, which also covers
+            // transfers whose token accounts aren't known
+            let accounts = transfer.wallet_token_accounts();
+            if i > 0 && accounts.iter().all(|account| settled.contains(*account)) {
+                continue;
+            }
+            if !fetched.insert(transfer.signature.as_str()) {
+                continue;
+            }
+
+            for (account, balance) in self.fetch_balances_before(&transfer.signature).await? {
+                if settled.insert(account.clone()) {
+                    balances.insert(account, balance);
+                }
+            }
+        }
+
+        Ok(Some(balances.values().sum()))
+    }
+
+    /// Check that the balance before the oldest indexed transfer plus the indexed
+    /// net change adds up to the current on-chain balance
+    pub async fn reconcile(&self, transfers: &[UsdcTransfer]) -> IndexerResult<Reconciliation> {
+        println!("🧮 Reconciling indexed transfers against on-chain balance...");
+
+        let ending_balance = self.fetch_usdc_balance().await?;
+
+        let indexed_net: i128 = transfers
+            .iter()
+            .filter(|transfer| self.mints.is_usdc(&transfer.mint))
+            .map(UsdcTransfer::balance_change)
+            .sum();
+
+        let starting_balance = match self.starting_balance(transfers).await? {
+            Some(balance) => balance,
+            None => ending_balance,
+        };
+
+        Ok(Reconciliation {
+            starting_balance,
+            ending_balance,
+            indexed_net,
+            discrepancy: ending_balance as i128 - (starting_balance as i128 + indexed_net),
+        })
+    }
+}
+
+fn parse_signature(signature: &str) -> IndexerResult<Signature> {
+    Signature::from_str(signature)
+        .map_err(|e| IndexerError::ParseTransaction(format!("Invalid signature {}: {}", signature, e)))
+}
+
+fn parse_owner(owner: &str) -> IndexerResult<Pubkey> {
+    Pubkey::from_str(owner)
+        .map_err(|_| IndexerError::ParseTransaction(format!("Invalid token account owner '{}'", owner)))
+}
+
+fn build_indexer(args: &Args, wallet: &str) -> Result<SolanaIndexer<Box<dyn RpcProvider>>> {
+    let provider: Box<dyn RpcProvider> = match (&args.replay, &args.record) {
+        (Some(dir), _) => {
+            println!("📼 Replaying RPC responses from {}", dir.display());
+            Box::new(ReplayProvider::new(dir)?)
+        }
+        (None, Some(dir)) => {
+            println!("⏺️ Recording RPC responses to {}", dir.display());
+            Box::new(RecordingProvider::new(args.rpc_pool()?, dir)?)
+        }
+        (None, None) => Box::new(args.rpc_pool()?),
+    };
+
+    let indexer = SolanaIndexer::new(provider, wallet, &args.mints)?
+        .with_token_program(args.token_program)
+        .with_chunks(args.chunks)
+        .with_transaction_batch(args.transaction_batch as usize)
+        .with_quiet(args.quiet)
+        .with_max_transactions(args.max_transactions)
+        .with_include_failed(args.include_failed)
+        .with_include_raw(args.include_raw)
+        .with_validate(args.validate)
+        .with_scan_blocks(args.scan_blocks)
+        .with_programs(&args.programs)
+        .with_cancellation(shutdown().clone())
+        .with_match_by(args.match_by)?;
+    Ok(indexer)
+}
+
+/// Print the explorer link for one transaction and, with --open, open it in
+/// the default browser
+fn open_signature(args: &Args, signature: &str) -> Result<()> {
+    parse_signature(signature)?;
+    let url = Explorer::new(&args.explorer_url, args.detect_cluster(), &args.rpc_url[0]).transaction_url(signature);
+    println!("🔗 {}", url);
+
+    if args.open {
+        open::that(&url).map_err(|e| anyhow::anyhow!("Cannot open {} in a browser: {}", url, e))?;
+        println!("🌐 Opened in your browser");
+    }
+    Ok(())
+}
+
+/// Warn when an endpoint serves a cluster whose USDC isn't tracked, the usual
+/// reason a run finds nothing: e.g. a devnet --rpc-url with mainnet's mint.
+/// `None` is a local or private cluster, where only an override can match.
+fn warn_on_cluster_mismatch(url: &str, cluster: Option<Cluster>, filter: &MintFilter) {
+    let tracked = filter.usdc_mints().iter().map(ToString::to_string).collect::<Vec<_>>().join(", ");
+
+    match cluster.and_then(|cluster| cluster.usdc_mint().map(|mint| (cluster, mint))) {
+        Some((cluster, mint)) if !filter.is_usdc(mint) => {
+            let flag = clap::ValueEnum::to_possible_value(&cluster).map_or_else(String::new, |value| value.get_name().to_string());
+            eprintln!(
+                "🚨 RPC endpoint {} is on {:?}, but its USDC ({}) isn't tracked (tracking: {}); pass --network {} or nothing will be found",
+                url, cluster, mint, tracked, flag
+            );
+        }
+        Some(_) => {}
+        None => {
+            let well_known = [Cluster::MainnetBeta, Cluster::Devnet].map(|cluster| cluster.usdc_mint());
+            let only_well_known = filter
+                .usdc_mints()
+                .iter()
+                .all(|mint| well_known.contains(&Some(mint.to_string().as_str())));
+            if only_well_known {
+                let name = cluster.map_or_else(|| "a local or private cluster".to_string(), |cluster| format!("{:?}", cluster));
+                eprintln!(
+                    "🚨 RPC endpoint {} is on {}, which has no well-known USDC mint; pass --usdc-mint-override or nothing will be found",
+                    url, name
+                );
+            }
+        }
+    }
+}
+
+/// Check everything that can be checked without indexing and describe the run
+/// that would happen. The endpoints were already probed by `check_endpoints`.
+fn print_dry_run(args: &Args, watch: bool) -> Result<()> {
+    println!("\n🧪 Dry run: nothing will be fetched or written");
+
+    for wallet in &args.wallets {
+        Pubkey::from_str(wallet).map_err(|_| IndexerError::InvalidPubkey(wallet.clone()))?;
+    }
+    println!("✅ {} wallet address(es) are valid", args.wallets.len());
+
+    for mint in args.mints.usdc_mints() {
+        let mint = mint.to_string();
+        println!("🪙 Tracking {} ({}) with {} decimals", args.tokens.symbol(&mint), mint, args.mints.decimals(&mint));
+    }
+
+    if watch {
+        let subscription = if args.watch_accounts { "token account changes" } else { "logs" };
+        println!("📡 Would watch {} over {} ({})", args.wallets.join(", "), args.ws_url(), subscription);
+        return Ok(());
+    }
+
+    match args.max_transactions {
+        Some(max) => println!(
+            "📦 At most {} batch(es) of {} signatures per wallet (--max-transactions {})",
+            (max + SIGNATURE_BATCH_LIMIT - 1) / SIGNATURE_BATCH_LIMIT,
+            SIGNATURE_BATCH_LIMIT,
+            max
+        ),
+        None => println!(
+            "📦 Signatures are fetched {} per batch; the batch count depends on wallet activity",
+            SIGNATURE_BATCH_LIMIT
+        ),
+    }
+    if args.chunks > 1 {
+        println!("🧩 Slot range split into {} concurrent chunks", args.chunks);
+    }
+
+    for wallet in &args.wallets {
+        for format in &args.format {
+            println!("💾 {} → {}", wallet, args.output_path(*format, wallet).display());
+        }
+        if let Some(path) = args.state_path(wallet) {
+            println!("📌 {} checkpoint → {}", wallet, path.display());
+        }
+    }
+
+    if args.service {
+        println!("🔄 Would re-index every {}", humantime::format_duration(args.interval));
+    }
+    Ok(())
+}
+
+/// Parse saved transactions for `wallet` without any RPC access, then report
+/// and export them like a backfill
+async fn run_input(
+    args: &Args,
+    wallet: &str,
+    transactions: &[(Signature, EncodedConfirmedTransactionWithStatusMeta)],
+) -> Result<()> {
+    if args.match_by == MatchBy::Account {
+        println!("⚠️ --match-by account needs RPC access to list token accounts; matching by owner");
+    }
+    let indexer = SolanaIndexer::new(OfflineProvider, wallet, &args.mints)?
+        .with_token_program(args.token_program)
+        .with_programs(&args.programs)
+        .with_validate(args.validate);
+
+    let mut transfers = Vec::new();
+    for (signature, transaction) in transactions {
+        match indexer.parse_transaction(*signature, transaction) {
+            Ok(parsed) => transfers.extend(parsed),
+            Err(e) => println!("⚠️ Error parsing transaction {}: {}", signature, e),
+        }
+    }
+
+    let mut transfers = dedupe_transfers(transfers);
+    args.explorer().annotate(&mut transfers);
+    args.flag_anomalies(&mut transfers)?;
+    let matches = args.lists.apply(&mut transfers);
+    display_results(&transfers, None, args, wallet, false, None).await?;
+    if !args.summary_only {
+        display_list_matches(&matches, &args.lists);
+    }
+    if args.validate {
+        check_validation(&indexer.take_validation(), args)?;
+    }
+    Ok(())
+}
+
+/// Watch for transfers live until the process is stopped
+async fn run_watch(args: &Args, wallet: &str) -> Result<()> {
+    let indexer = build_indexer(args, wallet)?;
+    let explorer = args.explorer();
+    let subscription = if args.watch_accounts {
+        watch::Subscription::Accounts
+    } else {
+        watch::Subscription::Logs
+    };
+
+    // Batches are exported as they arrive, off the subscription's callback
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel::<Vec<UsdcTransfer>>();
+    let export = async {
+        while let Some(transfers) = receiver.recv().await {
+            if let Err(e) = export_transfers(&transfers, args, wallet, true, false).await {
+                eprintln!("❌ Failed to write transfers: {}", e);
+            }
+        }
+    };
+    let ws_url = args.ws_url();
+    let watching = watch::watch(&indexer, &ws_url, subscription, |transfers| {
+        let mut transfers = transfers.to_vec();
+        explorer.annotate(&mut transfers);
+        metrics::metrics().transfers_found.inc_by(transfers.len() as u64);
+        display_transfers(&transfers, &args.tokens, args.decimals_display, args.table, args.address_width);
+        let _ = sender.send(transfers);
+    });
+
+    tokio::join!(watching, export);
+    Ok(())
+}
+
+/// Print stored transfers matching the query with the same table and summary
+/// as a live run
+fn run_query(query: &QueryArgs) -> Result<()> {
+    if !query.db.exists() {
+        anyhow::bail!("No database at {}; index with --format sqlite first", query.db.display());
+    }
+
+    let min_amount = match &query.min_amount {
+        Some(amount) => {
+            let raw = parse_ui_amount(amount, USDC_DECIMALS)
+                .ok_or_else(|| anyhow::anyhow!("Invalid --min-amount: {}", amount))?;
+            Some(u64::try_from(raw)?)
+        }
+        None => None,
+    };
+
+    let filter = TransferFilter {
+        from: query.from.as_deref().map(|from| parse_time_bound(from, false)).transpose()?,
+        to: query.to.as_deref().map(|to| parse_time_bound(to, true)).transpose()?,
+        direction: query.direction,
+        counterparty: query.counterparty.clone(),
+        min_amount,
+    };
+
+    let transfers = TransferStore::open(&query.db)?.query(&filter)?;
+    if transfers.is_empty() {
+        println!("\n📭 No stored transfers match the query.");
+        return Ok(());
+    }
+
+    println!("\n📊 {} stored USDC transfers:", transfers.len());
+    display_transfers(&transfers, &TokenRegistry::default(), None, query.table, query.address_width);
+    display_summary(&report::summarize(&transfers, &MintFilter::default()), None);
+    Ok(())
+}
+
+/// Parse a query time bound. A bare date means the start of that day, or
+/// for an end bound the start of the next day so the whole day is included.
+fn parse_time_bound(value: &str, end: bool) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let date = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| anyhow::anyhow!("Invalid date '{}': expected YYYY-MM-DD or RFC 3339", value))?;
+    let date = if end { date + chrono::Duration::days(1) } else { date };
+    Ok(date.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc())
+}
+
+/// Run one indexing cycle for every wallet, up to --wallet-concurrency at a
+/// time. A failed wallet doesn't stop the others; results follow wallet order.
+///
+/// RPC calls block, so as with slot chunks each worker is a thread driving
+/// its own wallets' futures to completion.
+fn index_wallets(args: &Args, previous: &HashMap<String, Checkpoint>) -> Vec<Result<Option<Checkpoint>>> {
+    let workers = (args.wallet_concurrency as usize).min(args.wallets.len());
+    if workers > 1 {
+        println!("👷 Indexing {} wallets, {} at a time", args.wallets.len(), workers);
+    }
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..args.wallets.len()).map(|_| None).collect::<Vec<_>>());
+    let handle = tokio::runtime::Handle::current();
+    tokio::task::block_in_place(|| {
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(wallet) = args.wallets.get(index) else {
+                        break;
+                    };
+                    let result = handle.block_on(run_indexer_once(args, wallet, previous.get(wallet)));
+                    results.lock().expect("wallet results lock poisoned")[index] = Some(result);
+                });
+            }
+        })
+    });
+
+    results
+        .into_inner()
+        .expect("wallet results lock poisoned")
+        .into_iter()
+        .map(|result| result.expect("wallet worker panicked"))
+        .collect()
+}
+
+/// Run one indexing cycle. `previous` is the checkpoint of the prior cycle in
+/// this process, used to tell which transfers are new when no state file is
+/// kept. Returns this cycle's checkpoint.
+async fn run_indexer_once(args: &Args, wallet: &str, previous: Option<&Checkpoint>) -> Result<Option<Checkpoint>> {
+    let indexer = build_indexer(args, wallet)?;
+    let state_path = args.state_path(wallet);
+    let checkpoint = match &state_path {
+        Some(path) => Checkpoint::load(path)?,
+        None => None,
+    };
+
+    let mut oracle = args.price_source.map(PriceOracle::new);
+    let mut sinks = if args.streams_export() {
+        Some(open_sinks(args, wallet, args.append).await?)
+    } else {
+        None
+    };
+
+    let backfill = match &mut sinks {
+        Some(sinks) => {
+            // Transfers are exported as they are found rather than all at the end
+            let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+            let backfill = async {
+                let sender = sender;
+                indexer
+                    .backfill_usdc_transfers_streaming(args.window(), checkpoint.as_ref(), Some(&sender))
+                    .await
+            };
+            let (backfill, exported) = tokio::join!(backfill, stream_export(args, sinks, oracle.as_mut(), receiver));
+            let backfill = backfill?;
+            exported?;
+            backfill
+        }
+        None => indexer.backfill_usdc_transfers(args.window(), checkpoint.as_ref()).await?,
+    };
+    let mut transfers = backfill.transfers;
+
+    if let Some(oracle) = &mut oracle {
+        oracle.annotate(&mut transfers).await;
+    }
+    args.explorer().annotate(&mut transfers);
+
+    if args.running_balance || args.starting_balance.is_some() {
+        let starting_balance = match &args.starting_balance {
+            Some(amount) => {
+                let raw = parse_ui_amount(amount, args.mints.usdc_decimals())
+                    .ok_or_else(|| anyhow::anyhow!("Invalid --starting-balance: {}", amount))?;
+                u64::try_from(raw)?
+            }
+            None => indexer.starting_balance(&transfers).await?.unwrap_or_default(),
+        };
+        report::running_balance(&mut transfers, starting_balance, &args.mints);
+    }
+
+    args.flag_anomalies(&mut transfers)?;
+    let matches = args.lists.apply(&mut transfers);
+
+    let reconciliation = if args.reconcile {
+        Some(indexer.reconcile(&transfers).await?)
+    } else {
+        None
+    };
+
+    // Display results
+    display_results(&transfers, reconciliation.as_ref(), args, wallet, backfill.partial, sinks).await?;
+    if args.include_raw {
+        save_raw_transactions(args, wallet, &transfers, backfill.raw_transactions)?;
+    }
+    let counts = backfill.counts;
+    if !args.summary_only {
+        println!(
+            "🧾 Transactions: {} processed, {} failed on-chain and skipped, {} errored",
+            counts.processed, counts.skipped_failed, counts.errored
+        );
+        println!(
+            "📞 RPC calls: {} getSignaturesForAddress, {} getTransaction, {} getBlock",
+            counts.signature_calls, counts.transaction_calls, counts.block_calls
+        );
+        display_list_matches(&matches, &args.lists);
+        if let Some(cost_per_million) = args.cost_per_million {
+            println!(
+                "💵 Estimated RPC cost: ${:.4} ({} calls at ${} per million)",
+                counts.billed_calls() as f64 * cost_per_million / 1_000_000.0,
+                counts.billed_calls(),
+                cost_per_million
+            );
+        }
+        if args.timings {
+            display_timings(&backfill.timings);
+        }
+    }
+    // Warnings go to stderr, so --summary-only leaves the totals line alone on stdout
+    if !backfill.unprocessed.is_empty() {
+        eprintln!("⚠️ {} transactions could not be processed, even after retrying; their transfers are missing:", backfill.unprocessed.len());
+        for signature in &backfill.unprocessed {
+            eprintln!("   {}", signature);
+        }
+    }
+    if backfill.interrupted {
+        eprintln!("⚠️ Partial results: indexing was interrupted before the end of the window");
+    } else if backfill.signatures_failed {
+        eprintln!("⚠️ Partial results: fetching signatures failed, so older transactions in the window were not indexed");
+    } else if backfill.partial {
+        eprintln!("⚠️ Partial results: the --max-transactions cap stopped indexing before the end of the window");
+    }
+    if args.validate {
+        check_validation(&backfill.validation, args)?;
+    }
+
+    if let Some(url) = &args.webhook_url {
+        // Anything the last checkpoint saw was already announced
+        let seen = checkpoint.as_ref().or(previous);
+        let new_transfers: Vec<UsdcTransfer> = transfers
+            .iter()
+            .filter(|transfer| !seen.is_some_and(|seen| seen.has_seen(transfer.slot, &transfer.signature)))
+            .cloned()
+            .collect();
+
+        if !new_transfers.is_empty() {
+            webhook::post_transfers(url, wallet, &new_transfers).await?;
+            println!("📣 Sent {} new transfers to webhook", new_transfers.len());
+        }
+
+        let denied: Vec<UsdcTransfer> = new_transfers.iter().filter(|transfer| transfer.denied).cloned().collect();
+        if args.alert_denied && !denied.is_empty() {
+            webhook::post_denied_alert(url, wallet, &denied).await?;
+            println!("⛔ Denied counterparty alert for {} transfers sent to webhook", denied.len());
+        }
+
+        let anomalies: Vec<UsdcTransfer> = new_transfers.into_iter().filter(|transfer| transfer.anomaly).collect();
+        if args.alert_anomalies && !anomalies.is_empty() {
+            webhook::post_anomaly_alert(url, wallet, &anomalies).await?;
+            println!("🚩 Anomaly alert for {} transfers sent to webhook", anomalies.len());
+        }
+    } else {
+        if args.alert_anomalies {
+            println!("⚠️ --alert-anomalies needs --webhook-url; not sending anomaly alerts");
+        }
+        if args.alert_denied {
+            println!("⚠️ --alert-denied needs --webhook-url; not sending denied counterparty alerts");
+        }
+    }
+
+    if let Some(threshold) = &args.alert_outflow {
+        check_outflow(args, wallet, threshold, &transfers).await?;
+    }
+
+    // Only advance the checkpoint once the cycle has fully succeeded
+    if let (Some(path), Some(checkpoint)) = (&state_path, &backfill.checkpoint) {
+        checkpoint.save(path)?;
+        println!("📌 Checkpoint saved to {}", path.display());
+    }
+
+    Ok(backfill.checkpoint)
+}
+
+/// Raise an alert when the wallet's net outflow exceeds `threshold`, given in
+/// whole tokens and converted with the mint's decimals
+async fn check_outflow(args: &Args, wallet: &str, threshold: &str, transfers: &[UsdcTransfer]) -> Result<()> {
+    let summary = report::summarize(transfers, &args.mints);
+    let decimals = summary.decimals;
+    let threshold_raw = parse_ui_amount(threshold, decimals)
+        .ok_or_else(|| anyhow::anyhow!("Invalid --alert-outflow amount: {}", threshold))?;
+
+    let net_outflow = summary.total_sent.saturating_sub(summary.total_received);
+    if net_outflow <= threshold_raw {
+        return Ok(());
+    }
+
+    // Only USDC counts towards the outflow, as in the summary
+    let triggering: Vec<UsdcTransfer> = transfers
+        .iter()
+        .filter(|transfer| transfer.direction == TransferDirection::Sent && args.mints.is_usdc(&transfer.mint))
+        .cloned()
+        .collect();
+
+    eprintln!(
+        "🚨🚨 OUTFLOW ALERT: net outflow of {} USDC exceeds threshold of {} USDC",
+        format_amount(net_outflow as i128, decimals, args.decimals_display),
+        format_amount(threshold_raw as i128, decimals, args.decimals_display)
+    );
+    for transfer in &triggering {
+        eprintln!(
+            "🚨   {} USDC to {} ({})",
+            format_amount(transfer.amount as i128, transfer.decimals, args.decimals_display),
+            transfer.to,
+            transfer.signature
+        );
+    }
+
+    if let Some(url) = &args.webhook_url {
+        webhook::post_outflow_alert(url, wallet, net_outflow, threshold_raw, decimals, &triggering).await?;
+        println!("📣 Outflow alert sent to webhook");
+    }
+
+    Ok(())
+}
+
+fn display_timings(timings: &Timings) {
+    let share = |phase: Duration| match timings.total.as_secs_f64() {
+        total if total > 0.0 => phase.as_secs_f64() / total * 100.0,
+        _ => 0.0,
+    };
+
+    println!("\n⏱️ Timings ({:.2?} total, {} RPC calls):", timings.total, timings.rpc_calls);
+    for (label, phase) in [
+        ("Signature fetching", timings.signatures),
+        ("Transaction fetching", timings.transactions),
+        ("Parsing", timings.parsing),
+    ] {
+        println!("   {:<22} {:>10.2?} ({:.0}%)", label, phase, share(phase));
+    }
+}
+
+async fn display_results(
+    transfers: &[UsdcTransfer],
+    reconciliation: Option<&Reconciliation>,
+    args: &Args,
+    wallet: &str,
+    partial: bool,
+    streamed: Option<Vec<ExportSink>>,
+) -> Result<()> {
+    if args.summary_only {
+        return display_summary_line(&report::summarize(transfers, &args.mints), wallet, args.decimals_display);
+    }
+
+    if transfers.is_empty() {
+        println!("\n📭 No USDC transfers found for {} in the specified time period.", wallet);
+    } else {
+        if args.wallets.len() > 1 {
+            println!("\n📊 USDC Transfer Summary for {}:", wallet);
+        } else {
+            println!("\n📊 USDC Transfer Summary:");
+        }
+        println!("========================");
+
+        display_transfers(transfers, &args.tokens, args.decimals_display, args.table, args.address_width);
+
+        let summary = report::summarize(transfers, &args.mints);
+        let decimals = summary.decimals;
+        display_summary(&summary, args.decimals_display);
+        if args.stats {
+            display_stats(&summary, args.decimals_display);
+        }
+        display_flagged("🚩 Anomalies", transfers.iter().filter(|transfer| transfer.anomaly).collect(), args.decimals_display);
+        display_flagged("⛔ Denied counterparties", transfers.iter().filter(|transfer| transfer.denied).collect(), args.decimals_display);
+
+        // Breakdowns add up amounts, so they only cover USDC like the summary
+        let usdc: Vec<UsdcTransfer> = transfers.iter().filter(|transfer| args.mints.is_usdc(&transfer.mint)).cloned().collect();
+
+        if args.group_by == Some(GroupBy::Counterparty) {
+            display_counterparties(&usdc, decimals, args.decimals_display);
+        }
+
+        if let Some(n) = args.top {
+            display_top_counterparties(&usdc, n as usize, args, wallet, decimals)?;
+        }
+
+        if let Some(period) = args.aggregate {
+            display_aggregate(&usdc, period, args, wallet, decimals)?;
+        }
+
+        if args.recurring {
+            display_recurring(&usdc, decimals, args.decimals_display);
+        }
+
+        if let Some(method) = args.cost_basis {
+            display_cost_basis(&usdc, method, decimals, args.decimals_display);
+        }
+
+        if let Some(reconciliation) = reconciliation {
+            display_reconciliation(reconciliation, args.mints.usdc_decimals(), args.decimals_display);
+        }
+
+        match streamed {
+            Some(sinks) => finish_sinks(sinks, &summary, partial).await?,
+            None if !args.stdout => export_transfers(transfers, args, wallet, args.append, partial).await?,
+            None => {}
+        }
+    }
+
+    if args.stdout {
+        output::write_result(transfers)?;
+    }
+
+    Ok(())
+}
+
+fn display_summary(summary: &Summary, places: Option<u8>) {
+    println!("\n📈 Summary:");
+    println!("📥 Total Received: {} USDC", format_amount(summary.total_received as i128, summary.decimals, places));
+    println!("📤 Total Sent: {} USDC", format_amount(summary.total_sent as i128, summary.decimals, places));
+    if summary.total_minted > 0 || summary.total_burned > 0 {
+        println!("🪙 Total Minted: {} USDC", format_amount(summary.total_minted as i128, summary.decimals, places));
+        println!("🔥 Total Burned: {} USDC", format_amount(summary.total_burned as i128, summary.decimals, places));
+    }
+    println!("💹 Net Change: {} USDC", format_amount(summary.net, summary.decimals, places));
+    if let Some(total_usd) = summary.usd_value {
+        println!("💵 Total USD Moved: ${:.2}", total_usd);
+    }
+    println!("⛽ Fees on Sent Transfers: {} SOL", summary.fees_sol());
+    println!("   (fees on received transfers are paid by the sender and not included)");
+}
+
+/// The totals of `display_summary` on one line, for --summary-only. It goes
+/// to the original stdout while logs go to stderr.
+fn display_summary_line(summary: &Summary, wallet: &str, places: Option<u8>) -> Result<()> {
+    output::write_result_line(&format!(
+        "📈 {} | 📥 Received: {} USDC | 📤 Sent: {} USDC | 💹 Net: {} USDC | 🔢 Transfers: {}",
+        wallet,
+        format_amount(summary.total_received as i128, summary.decimals, places),
+        format_amount(summary.total_sent as i128, summary.decimals, places),
+        format_amount(summary.net, summary.decimals, places),
+        summary.count
+    ))
+}
+
+/// List flagged transfers under `heading`, if any
+fn display_flagged(heading: &str, flagged: Vec<&UsdcTransfer>, places: Option<u8>) {
+    if flagged.is_empty() {
+        return;
+    }
+
+    println!("\n{} ({}):", heading, flagged.len());
+    for transfer in flagged {
+        let (direction, counterparty) = match transfer.direction {
+            TransferDirection::Sent => ("to", &transfer.to),
+            _ => ("from", &transfer.from),
+        };
+        println!(
+            "   {} | {} USDC {} {} | {}",
+            transfer.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+            format_amount(transfer.amount as i128, transfer.decimals, places),
+            direction,
+            short_address(counterparty),
+            transfer.signature
+        );
+    }
+}
+
+/// Report where the parsers disagreed under --validate, failing once more
+/// than --validate-threshold transactions did
+fn check_validation(validation: &Validation, args: &Args) -> Result<()> {
+    println!(
+        "\n🔬 Validation: {} transactions cross-checked, {} with discrepancies",
+        validation.checked,
+        validation.discrepancies.len()
+    );
+    let leg = |prefix: &str, leg: &TransferLeg| {
+        println!(
+            "     {} {} {} | {} → {}",
+            prefix,
+            format_amount(leg.amount as i128, args.mints.decimals(&leg.mint), args.decimals_display),
+            args.tokens.symbol(&leg.mint),
+            short_address(&leg.from_account),
+            short_address(&leg.to_account)
+        );
+    };
+    for discrepancy in &validation.discrepancies {
+        println!("   {}", discrepancy.signature);
+        for balance_only in &discrepancy.balance_only {
+            leg("📊 Balances only:", balance_only);
+        }
+        for instruction_only in &discrepancy.instruction_only {
+            leg("📜 Instructions only:", instruction_only);
+        }
+    }
+
+    if validation.discrepancies.len() > args.validate_threshold {
+        anyhow::bail!(
+            "--validate found discrepancies in {} transactions, more than --validate-threshold {}",
+            validation.discrepancies.len(),
+            args.validate_threshold
+        );
+    }
+    Ok(())
+}
+
+/// How many transfers --allow-file and --deny-file matched
+fn display_list_matches(matches: &ListMatches, lists: &CounterpartyLists) {
+    if lists.allow.is_some() {
+        println!("📋 Allow list: {} transfers kept, {} with other counterparties left out", matches.allowed, matches.dropped);
+    }
+    if lists.deny.is_some() {
+        println!("⛔ Deny list: {} transfers with a denied address", matches.denied);
+    }
+}
+
+fn display_stats(summary: &Summary, places: Option<u8>) {
+    println!("\n📐 Amount statistics (USDC):");
+    println!(
+        "   {:<9} {:>6} {:>18} {:>18} {:>18} {:>18} {:>18}",
+        "", "Count", "Mean", "Median", "Min", "Max", "Std dev"
+    );
+    for (label, stats) in [("Sent", summary.sent_stats), ("Received", summary.received_stats)] {
+        match stats {
+            // Mean, median and deviation are fractional raw amounts; rounding
+            // them to whole raw units loses nothing displayable
+            Some(stats) => println!(
+                "   {:<9} {:>6} {:>18} {:>18} {:>18} {:>18} {:>18}",
+                label,
+                stats.count,
+                format_amount(stats.mean.round() as i128, summary.decimals, places),
+                format_amount(stats.median.round() as i128, summary.decimals, places),
+                format_amount(stats.min as i128, summary.decimals, places),
+                format_amount(stats.max as i128, summary.decimals, places),
+                format_amount(stats.std_dev.round() as i128, summary.decimals, places),
+            ),
+            None => println!("   {:<9} {:>6}", label, 0),
+        }
+    }
+}
+
+/// A table on a terminal or with --table, one line per transfer otherwise
+fn display_transfers(transfers: &[UsdcTransfer], tokens: &TokenRegistry, places: Option<u8>, force_table: bool, address_width: usize) {
+    let is_terminal = std::io::stdout().is_terminal();
+
+    if force_table || is_terminal {
+        println!("{}", table::render(transfers, tokens, places, address_width, table::use_color(is_terminal)));
+    } else {
+        for transfer in transfers {
+            display_transfer(transfer, tokens, places);
+        }
+    }
+}
+
+fn display_transfer(transfer: &UsdcTransfer, tokens: &TokenRegistry, places: Option<u8>) {
+    let direction_symbol = match transfer.direction {
+        TransferDirection::Sent => "📤",
+        TransferDirection::Received => "📥",
+        TransferDirection::SelfTransfer => "🔁",
+        TransferDirection::Mint => "🪙",
+        TransferDirection::Burn => "🔥",
+    };
+
+    println!(
+        "{} {} | {} {} | {} | {}{}{}{}{}{}{}{}",
+        direction_symbol,
+        transfer.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+        format_amount(transfer.amount as i128, transfer.decimals, places),
+        tokens.symbol(&transfer.mint),
+        match transfer.direction {
+            TransferDirection::Sent => format!("To: {}", short_address(&transfer.to)),
+            TransferDirection::Received => format!("From: {}", short_address(&transfer.from)),
+            TransferDirection::SelfTransfer => "Self".to_string(),
+            TransferDirection::Mint => format!("Minted by: {}", short_address(&transfer.from)),
+            TransferDirection::Burn => "Burned".to_string(),
+        },
+        transfer.explorer_url.as_deref().unwrap_or(&transfer.signature),
+        match (&transfer.kind, &transfer.counter_mint) {
+            (TransactionKind::Swap, Some(mint)) => format!(" | 🔀 Swap ↔ {}", tokens.symbol(mint)),
+            (TransactionKind::Unknown, _) => " | ❔ Unmatched".to_string(),
+            _ => String::new(),
+        },
+        match &transfer.via_program {
+            Some(program) => format!(" | 🔗 Via: {}", short_address(program)),
+            None => String::new(),
+        },
+        // Only worth showing when someone other than the owner signed
+        match &transfer.authority {
+            Some(authority) if *authority != transfer.from => format!(" | ✍️ Signed by: {}", short_address(authority)),
+            _ => String::new(),
+        },
+        if transfer.failed { " | ❌ Failed tx" } else { "" },
+        if transfer.anomaly { " | 🚩 Anomaly" } else { "" },
+        if transfer.denied { " | ⛔ Denied" } else { "" },
+        match transfer.balance_after {
+            Some(balance) => format!(" | 🏦 Balance: {}", format_amount(balance as i128, transfer.decimals, places)),
+            None => String::new(),
+        }
+    );
+}
+
+/// An open output of one format, ready for transfers
+type ExportSink = (OutputFormat, PathBuf, Box<dyn OutputSink>);
+
+/// Write transfers to every configured output format. `partial` outputs get a
+/// `.partial` marker next to them.
+async fn export_transfers(transfers: &[UsdcTransfer], args: &Args, wallet: &str, append: bool, partial: bool) -> Result<()> {
+    let mut sinks = open_sinks(args, wallet, append).await?;
+    for (format, path, sink) in &mut sinks {
+        // The JSON array is kept newest first, so it gets them in that order
+        let mut ordered: Vec<&UsdcTransfer> = transfers.iter().collect();
+        if *format == OutputFormat::Json {
+            ordered.sort_by_key(|transfer| std::cmp::Reverse(transfer.timestamp));
+        }
+        for transfer in ordered {
+            sink.write_transfer(transfer)
+                .await
+                .with_context(|| format!("Cannot write transfers to {}", path.display()))?;
+        }
+    }
+    finish_sinks(sinks, &report::summarize(transfers, &args.mints), partial).await
+}
+
+/// Open a sink for every configured output format, first checking appended
+/// outputs against their checksum with --verify
+async fn open_sinks(args: &Args, wallet: &str, append: bool) -> Result<Vec<ExportSink>> {
+    let mut sinks = Vec::new();
+
+    for &format in &args.format {
+        let path = args.output_path(format, wallet);
+
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Cannot create output directory {}", parent.display()))?;
+        }
+
+        if append && args.verify && format.checksummed() {
+            output::verify_checksum(&path).await?;
+            println!("🔐 {} matches its checksum", path.display());
+        }
+
+        let sink = output::open_sink(format, &path, append, wallet)
+            .await
+            .with_context(|| format!("Cannot write transfers to {}", path.display()))?;
+        sinks.push((format, path, sink));
+    }
+
+    Ok(sinks)
+}
+
+/// Complete every sink with the final `summary`
+async fn finish_sinks(sinks: Vec<ExportSink>, summary: &Summary, partial: bool) -> Result<()> {
+    for (_, path, mut sink) in sinks {
+        let message = sink
+            .finish(summary)
+            .await
+            .with_context(|| format!("Cannot write transfers to {}", path.display()))?;
+        println!("\n💾 {}", message);
+
+        output::mark_partial(&path, partial)
+            .with_context(|| format!("Cannot mark {} as partial", path.display()))?;
+        if partial {
+            println!("⚠️ Marked as partial: {}", output::partial_path(&path).display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Write each transfer from `receiver` to `sinks` as the backfill finds it,
+/// with the annotations that don't need the whole window
+async fn stream_export(
+    args: &Args,
+    sinks: &mut [ExportSink],
+    mut oracle: Option<&mut PriceOracle>,
+    mut receiver: UnboundedReceiver<UsdcTransfer>,
+) -> Result<()> {
+    let explorer = args.explorer();
+
+    while let Some(transfer) = receiver.recv().await {
+        let mut transfers = vec![transfer];
+        if let Some(oracle) = oracle.as_deref_mut() {
+            oracle.annotate(&mut transfers).await;
+        }
+        explorer.annotate(&mut transfers);
+        args.flag_anomalies(&mut transfers)?;
+        args.lists.apply(&mut transfers);
+
+        for transfer in &transfers {
+            for (_, path, sink) in sinks.iter_mut() {
+                sink.write_transfer(transfer)
+                    .await
+                    .with_context(|| format!("Cannot write transfers to {}", path.display()))?;
+            }
+        }
+    }
 
-use transfer::{UsdcTransfer, TransferDirection};
-use utils::{parse_token_transfers, is_usdc_mint};
+    Ok(())
+}
 
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// Wallet address to index
-    #[arg(short, long)]
-    wallet: String,
+/// Save the raw transactions behind `transfers` for --include-raw, leaving
+/// out any whose transfers were filtered away
+fn save_raw_transactions(
+    args: &Args,
+    wallet: &str,
+    transfers: &[UsdcTransfer],
+    mut raw_transactions: BTreeMap<String, EncodedConfirmedTransactionWithStatusMeta>,
+) -> Result<()> {
+    let signatures: HashSet<&str> = transfers.iter().map(|transfer| transfer.signature.as_str()).collect();
+    raw_transactions.retain(|signature, _| signatures.contains(signature.as_str()));
 
-    /// RPC endpoint URL
-    #[arg(short, long, default_value = "https://api.mainnet-beta.solana.com")]
-    rpc_url: String,
+    let path = args.raw_path(wallet);
+    output::write_raw_transactions(&path, &raw_transactions)
+        .with_context(|| format!("Cannot write raw transactions to {}", path.display()))?;
+    println!("🧬 {} raw transactions saved to: {}", raw_transactions.len(), path.display());
+    Ok(())
+}
 
-    /// Hours to look back (default: 24)
-    #[arg(long, default_value_t = 24)]
-    hours: u64,
+fn display_counterparties(transfers: &[UsdcTransfer], decimals: u8, places: Option<u8>) {
+    println!("\n👥 By Counterparty:");
 
-    /// Run as a service (keep running and re-index every hour)
-    #[arg(long, default_value_t = false)]
-    service: bool,
+    for totals in report::by_counterparty(transfers) {
+        println!(
+            "  {} | 📤 Sent: {} USDC | 📥 Received: {} USDC | 💹 Net: {} USDC",
+            totals.counterparty,
+            format_amount(totals.sent as i128, decimals, places),
+            format_amount(totals.received as i128, decimals, places),
+            format_amount(totals.net(), decimals, places),
+        );
+    }
 }
 
-pub struct SolanaIndexer {
-    client: RpcClient,
-    wallet_pubkey: Pubkey,
-}
+fn display_top_counterparties(transfers: &[UsdcTransfer], n: usize, args: &Args, wallet: &str, decimals: u8) -> Result<()> {
+    let top = report::top_counterparties(transfers, wallet, n);
 
-impl SolanaIndexer {
-    pub fn new(rpc_url: &str, wallet_address: &str) -> Result<Self> {
-        let client = RpcClient::new_with_commitment(
-            rpc_url.to_string(),
-            CommitmentConfig::confirmed(),
+    println!("\n🏆 Top {} Counterparties by Volume:", n);
+    for (rank, totals) in top.iter().enumerate() {
+        println!(
+            "  {}. {} | 🔁 Volume: {} USDC | 📤 {} USDC | 📥 {} USDC | 💹 Net: {} USDC",
+            rank + 1,
+            totals.counterparty,
+            format_amount(totals.volume() as i128, decimals, args.decimals_display),
+            format_amount(totals.sent as i128, decimals, args.decimals_display),
+            format_amount(totals.received as i128, decimals, args.decimals_display),
+            format_amount(totals.net(), decimals, args.decimals_display),
         );
-        
-        let wallet_pubkey = Pubkey::from_str(wallet_address)
-            .map_err(|_| anyhow!("Invalid wallet address: {}", wallet_address))?;
-
-        Ok(Self {
-            client,
-            wallet_pubkey,
-        })
     }
 
-    pub async fn backfill_usdc_transfers(&self, hours_back: u64) -> Result<Vec<UsdcTransfer>> {
-        println!("🔍 Starting USDC transfer indexing for wallet: {}", self.wallet_pubkey);
-        println!("📅 Looking back {} hours", hours_back);
-
-        let mut all_transfers = Vec::new();
-        let mut before_signature: Option<Signature> = None;
-        let limit = 1000; // Maximum allowed by Solana RPC
-        let target_time = Utc::now() - Duration::hours(hours_back as i64);
+    // --stdout writes no files
+    if !args.stdout {
+        let path = args.report_path("top_counterparties", wallet);
+        output::write_counterparties_csv(&path, &top, decimals)?;
+        println!("💾 Top counterparties saved to: {}", path.display());
+    }
+    Ok(())
+}
 
-        loop {
-            println!("📡 Fetching transaction batch...");
-            
-            let signatures = self.client.get_signatures_for_address_with_config(
-                &self.wallet_pubkey,
-                solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config {
-                    limit: Some(limit),
-                    before: before_signature,
-                    until: None,
-                    commitment: Some(CommitmentConfig::confirmed()),
-                },
-            )?;
+fn display_aggregate(transfers: &[UsdcTransfer], period: Period, args: &Args, wallet: &str, decimals: u8) -> Result<()> {
+    let totals = report::aggregate(transfers, period);
 
-            if signatures.is_empty() {
-                println!("✅ No more transactions found");
-                break;
-            }
+    println!("\n🗓️ {} Totals:", capitalize(period.name()));
+    for bucket in &totals {
+        println!(
+            "  {} | {} transfers | 📥 {} USDC | 📤 {} USDC | 💹 {} USDC",
+            bucket.start,
+            bucket.count,
+            format_amount(bucket.received as i128, decimals, args.decimals_display),
+            format_amount(bucket.sent as i128, decimals, args.decimals_display),
+            format_amount(bucket.net(), decimals, args.decimals_display),
+        );
+    }
 
-            println!("🔄 Processing {} signatures...", signatures.len());
-            let mut batch_transfers = Vec::new();
-            let mut oldest_time = Utc::now();
+    if !args.stdout {
+        let path = args.report_path(&format!("{}_totals", period.name()), wallet);
+        output::write_aggregate_csv(&path, &totals, decimals)?;
+        println!("💾 {} totals saved to: {}", capitalize(period.name()), path.display());
+    }
+    Ok(())
+}
 
-            for sig_info in &signatures {
-                // Check if we've gone back far enough
-                if let Some(block_time) = sig_info.block_time {
-                    let tx_time = DateTime::from_timestamp(block_time, 0)
-                        .unwrap_or(Utc::now());
-                    
-                    oldest_time = oldest_time.min(tx_time);
-                    
-                    if tx_time < target_time {
-                        println!("⏰ Reached target time: {}", target_time);
-                        break;
-                    }
-                }
+fn display_recurring(transfers: &[UsdcTransfer], decimals: u8, places: Option<u8>) {
+    let recurring = report::detect_recurring(transfers);
 
-                if let Some(err) = &sig_info.err {
-                    println!("⚠️ Skipping failed transaction: {:?}", err);
-                    continue;
-                }
+    if recurring.is_empty() {
+        println!("\n🔁 No recurring payments detected");
+        return;
+    }
 
-                let signature = Signature::from_str(&sig_info.signature)?;
-                
-                match self.process_transaction(signature).await {
-                    Ok(transfers) => {
-                        batch_transfers.extend(transfers);
-                    }
-                    Err(e) => {
-                        println!("⚠️ Error processing transaction {}: {}", sig_info.signature, e);
-                        continue;
-                    }
-                }
-            }
+    println!("\n🔁 Recurring payments detected:");
+    for payment in recurring {
+        println!(
+            "  {} | {} USDC | every ~{:.0} days | {} payments, last on {}",
+            payment.counterparty,
+            format_amount(payment.amount as i128, decimals, places),
+            payment.interval_days,
+            payment.occurrences,
+            payment.last_paid.format("%Y-%m-%d"),
+        );
+    }
+}
 
-            all_transfers.extend(batch_transfers);
-            
-            // Check if we should continue
-            if oldest_time < target_time {
-                println!("✅ Reached target time window");
-                break;
-            }
+fn display_cost_basis(transfers: &[UsdcTransfer], method: CostBasis, decimals: u8, places: Option<u8>) {
+    let disposals = report::cost_basis(transfers, method);
 
-            // Set up for next batch
-            before_signature = signatures.last().map(|s| Signature::from_str(&s.signature).unwrap());
-            
-            if signatures.len() < limit {
-                println!("✅ Fetched all available transactions");
-                break;
-            }
+    if disposals.is_empty() {
+        println!("\n🧾 No sent transfers to match against lots");
+        return;
+    }
 
-            // Small delay to avoid rate limiting
-            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+    println!("\n🧾 Cost basis ({:?}):", method);
+    let mut realized = Some(0.0);
+    for disposal in &disposals {
+        let gain = disposal.gain_usd();
+        println!(
+            "  {} | 📤 {} USDC | {}{}",
+            disposal.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            format_amount(disposal.amount as i128, decimals, places),
+            short_address(&disposal.signature),
+            gain.map_or_else(String::new, |gain| format!(" | 💵 Realized: ${:.2}", gain)),
+        );
+        for lot in &disposal.lots {
+            println!(
+                "      ↳ {} USDC from lot {} acquired {}{}",
+                format_amount(lot.amount as i128, decimals, places),
+                short_address(&lot.signature),
+                lot.acquired.format("%Y-%m-%d %H:%M:%S"),
+                lot.cost_usd.map_or_else(String::new, |cost| format!(" (cost ${:.2})", cost)),
+            );
         }
+        if disposal.uncovered > 0 {
+            println!(
+                "      ↳ {} USDC not covered by lots acquired in the window",
+                format_amount(disposal.uncovered as i128, decimals, places)
+            );
+        }
+        realized = realized.zip(gain).map(|(total, gain)| total + gain);
+    }
 
-        // Filter transfers to only include those within the time window
-        let filtered_transfers: Vec<UsdcTransfer> = all_transfers
-            .into_iter()
-            .filter(|transfer| transfer.timestamp >= target_time)
-            .collect();
-
-        println!("🎯 Found {} USDC transfers in the last {} hours", filtered_transfers.len(), hours_back);
-        Ok(filtered_transfers)
+    if let Some(realized) = realized {
+        println!("💵 Total realized: ${:.2}", realized);
     }
+}
 
-    async fn process_transaction(&self, signature: Signature) -> Result<Vec<UsdcTransfer>> {
-        let transaction = self.client.get_transaction_with_config(
-            &signature,
-            RpcTransactionConfig {
-                encoding: Some(UiTransactionEncoding::Json),
-                commitment: Some(CommitmentConfig::confirmed()),
-                max_supported_transaction_version: Some(0),
-            },
-        )?;
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
 
-        let mut transfers = Vec::new();
+fn display_reconciliation(reconciliation: &Reconciliation, decimals: u8, places: Option<u8>) {
+    println!("\n🧮 Reconciliation:");
+    println!("🏦 Starting Balance: {} USDC", format_amount(reconciliation.starting_balance as i128, decimals, places));
+    println!("🏦 Ending Balance: {} USDC", format_amount(reconciliation.ending_balance as i128, decimals, places));
+    println!("💹 Indexed Net: {} USDC", format_amount(reconciliation.indexed_net, decimals, places));
 
-        if let Some(meta) = &transaction.transaction.meta {
-            if let Some(block_time) = transaction.block_time {
-                let timestamp = DateTime::from_timestamp(block_time, 0)
-                    .unwrap_or(Utc::now());
-
-                // Parse token transfers from transaction
-                if let Some(token_transfers) = parse_token_transfers(meta) {
-                    for transfer in token_transfers {
-                        // Check if it's a USDC transfer involving our wallet
-                        if is_usdc_mint(&transfer.mint) {
-                            let from_pubkey = Pubkey::from_str(&transfer.from_owner)?;
-                            let to_pubkey = Pubkey::from_str(&transfer.to_owner)?;
-
-                            let direction = if from_pubkey == self.wallet_pubkey {
-                                Some(TransferDirection::Sent)
-                            } else if to_pubkey == self.wallet_pubkey {
-                                Some(TransferDirection::Received)
-                            } else {
-                                None
-                            };
+    if reconciliation.is_balanced() {
+        println!("✅ Indexed net matches on-chain balance");
+    } else {
+        eprintln!("🚨 RECONCILIATION MISMATCH: {} USDC unaccounted for", format_amount(reconciliation.discrepancy, decimals, places));
+        eprintln!("🚨 Some transfers were likely missed while parsing token balances");
+    }
+}
 
-                            if let Some(dir) = direction {
-                                transfers.push(UsdcTransfer {
-                                    signature: signature.to_string(),
-                                    timestamp,
-                                    amount: transfer.amount,
-                                    direction: dir,
-                                    from: transfer.from_owner,
-                                    to: transfer.to_owner,
-                                });
-                            }
-                        }
-                    }
-                }
-            }
-        }
+/// The process-wide shutdown token, shared by every cycle and wallet
+static SHUTDOWN: OnceLock<CancellationToken> = OnceLock::new();
 
-        Ok(transfers)
-    }
+/// Cancelled on Ctrl-C; running backfills stop and keep what they found
+fn shutdown() -> &'static CancellationToken {
+    SHUTDOWN.get_or_init(CancellationToken::new)
 }
 
-async fn run_indexer_once(args: &Args) -> Result<()> {
-    let indexer = SolanaIndexer::new(&args.rpc_url, &args.wallet)?;
-    let transfers = indexer.backfill_usdc_transfers(args.hours).await?;
+/// The first Ctrl-C cancels `shutdown()` so the current cycle can write its
+/// partial results; a second one exits straight away
+fn listen_for_shutdown() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            eprintln!("⚠️ Cannot listen for Ctrl-C");
+            return;
+        }
+        println!("\n👋 Shutdown requested; finishing up (Ctrl-C again to quit now)");
+        shutdown().cancel();
 
-    // Display results
-    display_results(&transfers).await?;
-    Ok(())
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
 }
 
-async fn display_results(transfers: &[UsdcTransfer]) -> Result<()> {
-    if transfers.is_empty() {
-        println!("\n📭 No USDC transfers found in the specified time period.");
-    } else {
-        println!("\n📊 USDC Transfer Summary:");
-        println!("========================");
-        
-        let mut total_sent = 0u64;
-        let mut total_received = 0u64;
-        
-        for transfer in transfers {
-            let direction_symbol = match transfer.direction {
-                TransferDirection::Sent => "📤",
-                TransferDirection::Received => "📥",
-            };
-            
-            let amount_usdc = transfer.amount as f64 / 1_000_000.0; // USDC has 6 decimals
-            
-            match transfer.direction {
-                TransferDirection::Sent => total_sent += transfer.amount,
-                TransferDirection::Received => total_received += transfer.amount,
+/// Print a hint for `error` and exit with its status code: 2 for a bad wallet,
+/// 3-4 and 7 for network problems, 5 for unparseable transactions, 6 for I/O
+fn exit_with(error: &anyhow::Error) -> ! {
+    let code = match error.downcast_ref::<IndexerError>() {
+        Some(error) => {
+            match error {
+                IndexerError::InvalidPubkey(_) => eprintln!("📋 Check the wallet address"),
+                IndexerError::Rpc(_) => eprintln!("📋 Check network connectivity and the RPC endpoint"),
+                IndexerError::RateLimited(_) => eprintln!("📋 Try again later or add fallback --rpc-url endpoints"),
+                IndexerError::Timeout(_) => eprintln!("📋 Raise --rpc-timeout or add fallback --rpc-url endpoints"),
+                IndexerError::ParseTransaction(_) | IndexerError::Io(_) => {}
             }
-            
-            println!(
-                "{} {} | {} USDC | {} | {}",
-                direction_symbol,
-                transfer.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
-                amount_usdc,
-                match transfer.direction {
-                    TransferDirection::Sent => format!("To: {}", &transfer.to[..8]),
-                    TransferDirection::Received => format!("From: {}", &transfer.from[..8]),
-                },
-                transfer.signature
-            );
+            error.exit_code()
         }
-        
-        println!("\n📈 Summary:");
-        println!("📥 Total Received: {} USDC", total_received as f64 / 1_000_000.0);
-        println!("📤 Total Sent: {} USDC", total_sent as f64 / 1_000_000.0);
-        println!("💹 Net Change: {} USDC", 
-            (total_received as i64 - total_sent as i64) as f64 / 1_000_000.0
-        );
-        
-        // Export to JSON
-        let json_output = serde_json::to_string_pretty(&transfers)?;
-        std::fs::write("usdc_transfers.json", json_output)?;
-        println!("\n💾 Results saved to: usdc_transfers.json");
-    }
-
-    Ok(())
+        None => 1,
+    };
+    std::process::exit(code);
 }
 
 #[tokio::main]
@@ -275,75 +3151,471 @@ async fn main() -> Result<()> {
         }
     }));
 
+    // Bad arguments exit with clap's usage error (status 2); --help and
+    // --version exit 0
+    let cli = Cli::try_parse().unwrap_or_else(|e| e.exit());
+
+    // With --stdout or --summary-only, move logging to stderr before anything
+    // is printed
+    if cli.wants_stdout() {
+        output::divert_stdout().context("Cannot redirect logs to stderr")?;
+    }
+
     println!("🚀 Solana USDC Indexer Starting...");
-    
-    let args = match Args::try_parse() {
-        Ok(args) => {
-            println!("✅ Arguments parsed successfully");
-            args
+    println!("✅ Arguments parsed successfully");
+
+    let (watch, mut args) = match cli.command {
+        Some(Command::Query(query)) => return run_query(&query),
+        Some(Command::Watch(args)) => (true, args),
+        Some(Command::Backfill(args)) => (false, args),
+        None => (false, cli.args),
+    };
+
+    if let Some(path) = &args.emit_schema {
+        if let Err(e) = output::write_schema(path) {
+            eprintln!("❌ Cannot write schema to {}: {}", path.display(), e);
+            exit_with(&e);
         }
+        println!("📐 JSON Schema (version {}) written to {}", output::SCHEMA_VERSION, path.display());
+        return Ok(());
+    }
+
+    if args.rpc_url.is_empty() {
+        let network = args.network.unwrap_or(Cluster::MainnetBeta);
+        args.rpc_url.push(network.default_rpc_url().to_string());
+    }
+
+    if let Some(signature) = &args.open_signature {
+        if let Err(e) = open_signature(&args, signature) {
+            eprintln!("❌ {}", e);
+            exit_with(&e);
+        }
+        return Ok(());
+    }
+
+    args.wallets = match args.resolve_wallets() {
+        Ok(wallets) => wallets,
         Err(e) => {
-            eprintln!("❌ Failed to parse arguments: {}", e);
-            // If argument parsing fails, run with default values
-            Args {
-                wallet: "7cMEhpt9y3inBNVv8fNnuaEbx7hKHZnLvR1KWKKxuDDU".to_string(),
-                rpc_url: "https://api.mainnet-beta.solana.com".to_string(),
-                hours: 24,
-                service: false,
-            }
+            eprintln!("❌ {}", e);
+            std::process::exit(e.downcast_ref::<IndexerError>().map_or(1, IndexerError::exit_code));
         }
     };
+
+    // Before anything consults the mint filter or the token registry
+    let mut mint_filter = MintFilter::usdc(args.network);
+    if let Some(mint) = args.usdc_mint_override {
+        println!("🪙 Also treating {} as USDC", mint);
+        mint_filter = mint_filter.with_usdc_mint(mint);
+    }
+    if args.include_wsol {
+        mint_filter = mint_filter.with_wsol();
+    }
+    for program in &args.programs {
+        println!("🎛️ Only indexing transactions that invoke {}", program);
+    }
+    if let (true, Some(from), Some(to)) = (args.scan_blocks, args.from_slot, args.to_slot) {
+        println!(
+            "🧱 Scanning every block in slots {}..={}: {} getBlock calls, which is slow and costly and needs an archival node for old slots",
+            from,
+            to,
+            (to + 1).saturating_sub(from)
+        );
+    }
+    for decimals_override in &args.decimals {
+        match decimals_override.mint {
+            Some(mint) => println!("🔢 Forcing {} decimals for {}", decimals_override.decimals, mint),
+            None => println!("🔢 Forcing {} decimals for USDC", decimals_override.decimals),
+        }
+        mint_filter = mint_filter.with_decimals(*decimals_override);
+    }
+    if let (Some(network), true) = (args.network, mint_filter.usdc_mints().is_empty()) {
+        eprintln!("⚠️ {:?} has no well-known USDC mint; pass --usdc-mint-override to find any transfers", network);
+    }
+    args.mints = mint_filter;
+
+    args.tokens = match &args.token_list {
+        Some(path) => TokenRegistry::bundled(&args.mints).with_token_list(path).unwrap_or_else(|e| {
+            eprintln!("⚠️ Could not load token list {}: {}; using bundled tokens", path.display(), e);
+            TokenRegistry::bundled(&args.mints)
+        }),
+        None => TokenRegistry::bundled(&args.mints),
+    };
+
+    // Compliance lists are loaded once; a bad address fails the run rather than being skipped
+    if let Some(path) = &args.allow_file {
+        let allow = AddressList::load(path)?;
+        println!("📋 Only keeping transfers with the {} addresses in {}", allow.len(), path.display());
+        args.lists.allow = Some(allow);
+    }
+    if let Some(path) = &args.deny_file {
+        let deny = AddressList::load(path)?;
+        println!("⛔ Flagging transfers with the {} addresses in {}", deny.len(), path.display());
+        args.lists.deny = Some(deny);
+    }
+
+    if args.wallets.len() == 1 {
+        println!("💰 Target wallet: {}", args.wallets[0]);
+    } else {
+        println!("💰 Target wallets ({}): {}", args.wallets.len(), args.wallets.join(", "));
+    }
+    println!("🌐 RPC endpoints: {}", args.rpc_url.join(", "));
+    if args.hours.is_some() {
+        println!("⚠️ --hours is deprecated; use --lookback (e.g. --lookback 24h)");
+    }
+    println!("⏰ Window to index: {}", args.window());
+
+    if let Err(e) = args.check_endpoints(watch) {
+        eprintln!("❌ {}", e);
+        std::process::exit(e.exit_code());
+    }
+
+    if args.dry_run {
+        return print_dry_run(&args, watch);
+    }
     
-    println!("💰 Target wallet: {}", args.wallet);
-    println!("🌐 RPC endpoint: {}", args.rpc_url);
-    println!("⏰ Hours to index: {}", args.hours);
-    
+    if let Some(port) = args.metrics_port {
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(port).await {
+                eprintln!("❌ Metrics server failed: {}", e);
+            }
+        });
+    }
+
+    if let Some(path) = &args.input {
+        let transactions = match fixtures::load_transactions(path) {
+            Ok(transactions) => transactions,
+            Err(e) => {
+                eprintln!("❌ Could not load {}: {}", path.display(), e);
+                std::process::exit(e.exit_code());
+            }
+        };
+        println!("📂 Loaded {} saved transactions from {}", transactions.len(), path.display());
+        for wallet in &args.wallets {
+            if let Err(e) = run_input(&args, wallet, &transactions).await {
+                eprintln!("❌ Parsing saved transactions for {} failed: {}", wallet, e);
+                exit_with(&e);
+            }
+        }
+        return Ok(());
+    }
+
+    if watch {
+        println!("📡 Watching for new transfers in real time");
+        let watchers = args.wallets.iter().map(|wallet| run_watch(&args, wallet));
+        for result in futures::future::join_all(watchers).await {
+            result?;
+        }
+        return Ok(());
+    }
+
+    listen_for_shutdown();
+
     if args.service {
-        println!("🔄 Running as a service - will re-index every hour");
+        println!("🔄 Running as a service - will re-index every {}", humantime::format_duration(args.interval));
+        let mut last_checkpoints: HashMap<String, Checkpoint> = HashMap::new();
         loop {
-            match run_indexer_once(&args).await {
-                Ok(()) => println!("✅ Indexing cycle completed successfully at {}", Utc::now().format("%Y-%m-%d %H:%M:%S UTC")),
-                Err(e) => {
-                    eprintln!("❌ Indexing cycle failed: {}", e);
-                    eprintln!("🔄 Will retry in next cycle...");
+            let results = index_wallets(&args, &last_checkpoints);
+            for (wallet, result) in args.wallets.iter().zip(results) {
+                match result {
+                    Ok(checkpoint) => {
+                        if let Some(checkpoint) = checkpoint {
+                            last_checkpoints.insert(wallet.clone(), checkpoint);
+                        }
+                        metrics::metrics().last_success_timestamp.set(Utc::now().timestamp());
+                        println!("✅ Indexing cycle for {} completed successfully at {}", wallet, Utc::now().format("%Y-%m-%d %H:%M:%S UTC"));
+                    }
+                    Err(e) => {
+                        eprintln!("❌ Indexing cycle for {} failed: {}", wallet, e);
+                        eprintln!("🔄 Will retry in next cycle...");
+                    }
                 }
             }
+
+            if shutdown().is_cancelled() {
+                println!("👋 Stopping service");
+                return Ok(());
+            }
             
-            println!("😴 Sleeping for 1 hour before next indexing cycle...");
-            tokio::time::sleep(tokio::time::Duration::from_secs(3600)).await;
+            println!("😴 Sleeping for {} before next indexing cycle...", humantime::format_duration(args.interval));
+            tokio::select! {
+                _ = tokio::time::sleep(args.interval) => {}
+                _ = shutdown().cancelled() => {
+                    println!("👋 Stopping service");
+                    return Ok(());
+                }
+            }
         }
     } else {
-        // Run once and keep alive for hosting platforms
         println!("🎯 Running single indexing cycle...");
         
-        match run_indexer_once(&args).await {
-            Ok(()) => {
-                println!("🏁 Indexing completed successfully!");
-            }
-            Err(e) => {
-                eprintln!("❌ Indexing failed: {}", e);
-                eprintln!("📋 This might be due to:");
-                eprintln!("  • Network connectivity issues");
-                eprintln!("  • RPC rate limiting");
-                eprintln!("  • Invalid wallet address");
-                eprintln!("  • Solana RPC endpoint issues");
+        // Index every wallet, then fail with the first error if any did
+        let mut first_error = None;
+        let results = index_wallets(&args, &HashMap::new());
+        if args.wallets.len() > 1 {
+            println!("\n👛 Wallet results:");
+        }
+        for (wallet, result) in args.wallets.iter().zip(results) {
+            match result {
+                Ok(_) if args.wallets.len() > 1 => println!("✅ {}", wallet),
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("❌ Indexing {} failed: {}", wallet, e);
+                    first_error.get_or_insert(e);
+                }
             }
         }
-        
-        println!("🔄 Keeping service alive for hosting platform...");
-        println!("📝 To run as a continuous service, use --service flag");
-        
-        // Keep the service alive with more frequent heartbeats
-        let mut counter = 0;
-        loop {
-            counter += 1;
-            tokio::time::sleep(tokio::time::Duration::from_secs(60)).await; // Sleep 1 minute
-            println!("💓 Service heartbeat #{} - {}", counter, Utc::now().format("%Y-%m-%d %H:%M:%S UTC"));
-            
-            // Every 10 minutes, show memory info
-            if counter % 10 == 0 {
-                println!("📊 Service has been alive for {} minutes", counter);
+
+        match first_error {
+            None => {
+                metrics::metrics().last_success_timestamp.set(Utc::now().timestamp());
+                println!("🏁 Indexing completed successfully!");
             }
+            // A failed one-shot run exits so orchestrators can see why
+            Some(e) => exit_with(&e),
+        }
+
+        if !args.keep_alive {
+            return Ok(());
+        }
+
+        // Idle without waking up; /metrics, if enabled, stays served meanwhile
+        println!("🔄 Keeping the process alive for the hosting platform (Ctrl-C to stop)");
+        shutdown().cancelled().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{usdc_receipt, MockProvider};
+
+    const HOUR: i64 = 3600;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000, 0).unwrap()
+    }
+
+    /// A mock holding one receipt per `(slot, hours ago)`, newest first, and
+    /// the signatures of those receipts
+    fn mock(wallet: &Pubkey, receipts: &[(u64, i64)]) -> (MockProvider, Vec<Signature>) {
+        let mut provider = MockProvider::new(now());
+        let mut signatures = Vec::new();
+        for &(slot, hours_ago) in receipts {
+            let transaction = usdc_receipt(wallet, slot, now().timestamp() - hours_ago * HOUR, 1_000_000);
+            signatures.push(transaction_signature(&transaction));
+            provider = provider.with_transaction(transaction);
+        }
+        (provider, signatures)
+    }
+
+    fn transaction_signature(transaction: &EncodedConfirmedTransactionWithStatusMeta) -> Signature {
+        match &transaction.transaction.transaction {
+            EncodedTransaction::Json(ui) => parse_signature(&ui.signatures[0]).unwrap(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn indexer(provider: MockProvider, wallet: &Pubkey, page_size: usize) -> SolanaIndexer<MockProvider> {
+        SolanaIndexer::new(provider, &wallet.to_string(), &MintFilter::default())
+            .unwrap()
+            .with_quiet(true)
+            .with_page_size(page_size)
+    }
+
+    fn lookback(hours: u64) -> IndexWindow {
+        IndexWindow::Lookback(Duration::from_secs(hours * HOUR as u64))
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn backfill_follows_the_before_cursor_across_pages() {
+        let wallet = Pubkey::new_unique();
+        let (provider, signatures) = mock(&wallet, &[(500, 1), (400, 2), (300, 3), (200, 4), (100, 5)]);
+        let indexer = indexer(provider, &wallet, 2);
+
+        let backfill = indexer.backfill_usdc_transfers(lookback(24), None).await.unwrap();
+
+        assert_eq!(backfill.transfers.len(), 5);
+        assert_eq!(backfill.counts.processed, 5);
+        assert_eq!(backfill.counts.signature_calls, 3);
+        assert_eq!(
+            indexer.rpc.cursors(),
+            vec![(None, None), (Some(signatures[1]), None), (Some(signatures[3]), None)]
+        );
+        assert_eq!(backfill.checkpoint.unwrap().signature, signatures[0].to_string());
+        assert!(!backfill.partial);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn backfill_stops_at_the_checkpoint_with_until() {
+        let wallet = Pubkey::new_unique();
+        let (provider, signatures) = mock(&wallet, &[(500, 1), (400, 2), (300, 3), (200, 4)]);
+        let indexer = indexer(provider, &wallet, 2);
+        let checkpoint = Checkpoint {
+            signature: signatures[2].to_string(),
+            timestamp: now() - chrono::Duration::hours(3),
+            slot: Some(300),
+        };
+
+        let backfill = indexer.backfill_usdc_transfers(lookback(24), Some(&checkpoint)).await.unwrap();
+
+        assert_eq!(backfill.transfers.len(), 2);
+        let cursors = indexer.rpc.cursors();
+        assert!(cursors.iter().all(|(_, until)| *until == Some(signatures[2])));
+        assert_eq!(cursors[0].0, None);
+        assert_eq!(backfill.checkpoint.unwrap().signature, signatures[0].to_string());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn backfill_keeps_the_checkpoint_when_nothing_is_newer() {
+        let wallet = Pubkey::new_unique();
+        let (provider, signatures) = mock(&wallet, &[(500, 1), (400, 2)]);
+        let indexer = indexer(provider, &wallet, 2);
+        let checkpoint = Checkpoint {
+            signature: signatures[0].to_string(),
+            timestamp: now() - chrono::Duration::hours(1),
+            slot: Some(500),
+        };
+
+        let backfill = indexer.backfill_usdc_transfers(lookback(24), Some(&checkpoint)).await.unwrap();
+
+        assert!(backfill.transfers.is_empty());
+        assert_eq!(backfill.checkpoint.unwrap().signature, signatures[0].to_string());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn backfill_skips_a_boundary_signature_repeated_on_the_next_page() {
+        let wallet = Pubkey::new_unique();
+        let (provider, signatures) = mock(&wallet, &[(500, 1)]);
+        let indexer = indexer(provider.with_repeated_boundary(), &wallet, 1);
+
+        let backfill = indexer.backfill_usdc_transfers(lookback(24), None).await.unwrap();
+
+        assert_eq!(backfill.transfers.len(), 1);
+        assert_eq!(backfill.counts.processed, 1);
+        assert_eq!(indexer.rpc.cursors(), vec![(None, None), (Some(signatures[0]), None)]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn backfill_stops_paging_at_the_window_start() {
+        let wallet = Pubkey::new_unique();
+        let (provider, _) = mock(&wallet, &[(500, 1), (400, 2), (300, 30), (200, 40), (100, 50)]);
+        let indexer = indexer(provider, &wallet, 2);
+
+        let backfill = indexer.backfill_usdc_transfers(lookback(24), None).await.unwrap();
+
+        assert_eq!(backfill.transfers.len(), 2);
+        assert_eq!(backfill.counts.processed, 2);
+        // The second page crossed the window start, so no third one is fetched
+        assert_eq!(indexer.rpc.cursors().len(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn backfill_cuts_a_page_straddling_the_window_start() {
+        let wallet = Pubkey::new_unique();
+        let (provider, signatures) = mock(&wallet, &[(500, 1), (400, 2), (300, 30), (200, 40)]);
+        let indexer = indexer(provider, &wallet, 3);
+
+        let backfill = indexer.backfill_usdc_transfers(lookback(24), None).await.unwrap();
+
+        let found: Vec<String> = backfill.transfers.iter().map(|transfer| transfer.signature.clone()).collect();
+        assert_eq!(found, vec![signatures[0].to_string(), signatures[1].to_string()]);
+        assert_eq!(backfill.counts.processed, 2);
+        // The page was full, but it reached past the window start
+        assert_eq!(indexer.rpc.cursors().len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn backfill_range_keeps_only_slots_inside_the_window() {
+        let wallet = Pubkey::new_unique();
+        let (provider, signatures) = mock(&wallet, &[(500, 1), (400, 2), (300, 3), (200, 4), (100, 5)]);
+        let indexer = indexer(provider, &wallet, 2);
+        let progress = Progress::new(true, None);
+
+        let (transfers, newest) = indexer
+            .backfill_range(WindowBounds::Slots { from: 300, to: Some(400) }, None, None, None, &progress)
+            .await
+            .unwrap();
+
+        let slots: Vec<u64> = transfers.iter().map(|transfer| transfer.slot).collect();
+        assert_eq!(slots, vec![400, 300]);
+        assert_eq!(newest.unwrap().signature, signatures[0].to_string());
+        // Slot 200 on the second page is before the window; nothing older is requested
+        assert_eq!(indexer.rpc.cursors().len(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn backfill_range_starts_from_the_before_cursor() {
+        let wallet = Pubkey::new_unique();
+        let (provider, signatures) = mock(&wallet, &[(500, 1), (400, 2), (300, 3), (200, 4)]);
+        let indexer = indexer(provider, &wallet, 2);
+        let progress = Progress::new(true, None);
+
+        let (transfers, newest) = indexer
+            .backfill_range(WindowBounds::Slots { from: 0, to: None }, Some(signatures[1]), None, None, &progress)
+            .await
+            .unwrap();
+
+        let slots: Vec<u64> = transfers.iter().map(|transfer| transfer.slot).collect();
+        assert_eq!(slots, vec![300, 200]);
+        assert_eq!(newest.unwrap().signature, signatures[2].to_string());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reconcile_counts_token_accounts_no_transfer_touched() {
+        let wallet = Pubkey::new_unique();
+        let receipts = [
+            usdc_receipt(&wallet, 500, now().timestamp() - HOUR, 1_000_000),
+            usdc_receipt(&wallet, 400, now().timestamp() - 2 * HOUR, 2_000_000),
+        ];
+        let credited = |transaction: &EncodedConfirmedTransactionWithStatusMeta| match &transaction.transaction.transaction {
+            EncodedTransaction::Json(ui) => match &ui.message {
+                solana_transaction_status::UiMessage::Parsed(message) => message.account_keys[2].pubkey.clone(),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        let mut provider = MockProvider::new(now())
+            .with_token_account(&credited(&receipts[0]), 1_000_000)
+            .with_token_account(&credited(&receipts[1]), 2_000_000)
+            .with_token_account(&Pubkey::new_unique().to_string(), 10_000_000); // Untouched in the window
+        for receipt in receipts {
+            provider = provider.with_transaction(receipt);
         }
+        let indexer = indexer(provider, &wallet, 10);
+
+        let backfill = indexer.backfill_usdc_transfers(lookback(24), None).await.unwrap();
+        let reconciliation = indexer.reconcile(&backfill.transfers).await.unwrap();
+
+        assert_eq!(reconciliation.starting_balance, 10_000_000);
+        assert_eq!(reconciliation.ending_balance, 13_000_000);
+        assert_eq!(reconciliation.indexed_net, 3_000_000);
+        assert!(reconciliation.is_balanced());
+    }
+
+    #[test]
+    fn parse_skips_a_leg_whose_owner_cannot_be_resolved() {
+        let wallet = Pubkey::new_unique();
+        let receipt = usdc_receipt(&wallet, 100, now().timestamp(), 1_000_000);
+        let signature = transaction_signature(&receipt);
+        // Neither balance entry of the sender's token account names an owner,
+        // and that account isn't one of the wallet's
+        let mut json = serde_json::to_value(&receipt).unwrap();
+        for balances in ["preTokenBalances", "postTokenBalances"] {
+            json["meta"][balances][0]["owner"] = serde_json::Value::Null;
+        }
+        let orphaned: EncodedConfirmedTransactionWithStatusMeta = serde_json::from_value(json).unwrap();
+        let indexer = indexer(MockProvider::new(now()), &wallet, SIGNATURE_BATCH_LIMIT);
+
+        let matched = indexer.parse_transaction(signature, &receipt).unwrap();
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].kind, TransactionKind::Transfer);
+
+        // The leg is dropped without failing the transaction; the wallet's
+        // balance change is still recorded, unmatched and without a sender
+        let parsed = indexer.parse_transaction(signature, &orphaned).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].kind, TransactionKind::Unknown);
+        assert_eq!((parsed[0].from.as_str(), parsed[0].amount), ("", 1_000_000));
     }
-}
\ No newline at end of file
+}
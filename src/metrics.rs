@@ -0,0 +1,142 @@
+use anyhow::Result;
+use prometheus::{Encoder, IntCounter, IntGauge, Registry, TextEncoder};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Process-wide Prometheus metrics for the indexer
+pub struct Metrics {
+    registry: Registry,
+    pub transactions_processed: IntCounter,
+    pub transfers_found: IntCounter,
+    pub rpc_errors: IntCounter,
+    pub rpc_requests: IntCounter,
+    pub transactions_without_meta: IntCounter,
+    pub transactions_without_token_balances: IntCounter,
+    pub last_success_timestamp: IntGauge,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let transactions_processed = IntCounter::new(
+            "usdc_indexer_transactions_processed_total",
+            "Transactions fetched and inspected for USDC transfers",
+        )
+        .expect("valid metric");
+        let transfers_found = IntCounter::new(
+            "usdc_indexer_transfers_found_total",
+            "USDC transfers found for the wallet",
+        )
+        .expect("valid metric");
+        let rpc_errors = IntCounter::new(
+            "usdc_indexer_rpc_errors_total",
+            "Failed RPC calls, including ones retried on another endpoint",
+        )
+        .expect("valid metric");
+        let rpc_requests = IntCounter::new(
+            "usdc_indexer_rpc_requests_total",
+            "RPC calls sent, counting each retry and failover attempt",
+        )
+        .expect("valid metric");
+        let transactions_without_meta = IntCounter::new(
+            "usdc_indexer_transactions_without_meta_total",
+            "Transactions the RPC node returned without status meta",
+        )
+        .expect("valid metric");
+        let transactions_without_token_balances = IntCounter::new(
+            "usdc_indexer_transactions_without_token_balances_total",
+            "Transactions returned with meta but no pre/post token balances",
+        )
+        .expect("valid metric");
+        let last_success_timestamp = IntGauge::new(
+            "usdc_indexer_last_success_timestamp_seconds",
+            "Unix time of the last successful indexing cycle",
+        )
+        .expect("valid metric");
+
+        for collector in [
+            Box::new(transactions_processed.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(transfers_found.clone()),
+            Box::new(rpc_errors.clone()),
+            Box::new(rpc_requests.clone()),
+            Box::new(transactions_without_meta.clone()),
+            Box::new(transactions_without_token_balances.clone()),
+            Box::new(last_success_timestamp.clone()),
+        ] {
+            registry.register(collector).expect("metric registered once");
+        }
+
+        Self {
+            registry,
+            transactions_processed,
+            transfers_found,
+            rpc_errors,
+            rpc_requests,
+            transactions_without_meta,
+            transactions_without_token_balances,
+            last_success_timestamp,
+        }
+    }
+
+    /// Render all metrics in the text exposition format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("text encoding never fails");
+        String::from_utf8(buffer).expect("text encoding is utf-8")
+    }
+}
+
+/// Where one backfill spent its time, for --timings. Phases are summed over
+/// every call, so with --chunks they can add up to more than `total`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timings {
+    pub total: Duration,
+    pub signatures: Duration,   // getSignaturesForAddress batches
+    pub transactions: Duration, // getTransaction for each signature
+    pub parsing: Duration,      // Extracting transfers from fetched transactions
+    pub rpc_calls: u64,         // Process-wide, so it includes other wallets indexed meanwhile
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+/// Serve `/metrics` over plain HTTP until the process exits
+pub async fn serve(port: u16) -> Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    println!("📈 Serving Prometheus metrics on http://0.0.0.0:{}/metrics", port);
+
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+
+        tokio::spawn(async move {
+            let mut request = [0u8; 1024];
+            let Ok(read) = stream.read(&mut request).await else {
+                return;
+            };
+
+            let request_line = String::from_utf8_lossy(&request[..read]);
+            let path = request_line.split_whitespace().nth(1).unwrap_or("");
+
+            let response = if path == "/metrics" {
+                let body = metrics().render();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    TextEncoder::new().format_type(),
+                    body.len(),
+                    body
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+            };
+
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
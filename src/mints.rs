@@ -0,0 +1,144 @@
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::explorer::Cluster;
+use crate::utils::USDC_DECIMALS;
+
+// USDC mint addresses for different networks
+pub const USDC_MAINNET: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+pub const USDC_DEVNET: &str = "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU"; // For testing
+pub const WSOL_MINT: &str = "So11111111111111111111111111111111111111112";
+
+const WSOL_DECIMALS: u8 = 9;
+
+/// Most decimals `--decimals` accepts; more would overflow u64 amounts quickly
+const MAX_DECIMALS: u8 = 18;
+
+/// A `--decimals` value: `N` forces the decimals of every USDC mint,
+/// `MINT:N` those of one mint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecimalsOverride {
+    pub mint: Option<Pubkey>,
+    pub decimals: u8,
+}
+
+/// `--decimals` value parser
+pub fn parse_decimals_override(value: &str) -> Result<DecimalsOverride, String> {
+    let (mint, decimals) = match value.rsplit_once(':') {
+        Some((mint, decimals)) => {
+            let mint = Pubkey::from_str(mint).map_err(|_| format!("invalid mint address: {}", mint))?;
+            (Some(mint), decimals)
+        }
+        None => (None, value),
+    };
+
+    let decimals: u8 = decimals.parse().map_err(|_| format!("invalid decimals: {}", decimals))?;
+    if decimals > MAX_DECIMALS {
+        return Err(format!("decimals must be at most {}", MAX_DECIMALS));
+    }
+    Ok(DecimalsOverride { mint, decimals })
+}
+
+/// The mints whose transfers are indexed, with their decimals. USDC mints
+/// count towards totals and balances; the rest (wSOL) are only listed, since
+/// their amounts are in other units.
+#[derive(Debug, Clone)]
+pub struct MintFilter {
+    usdc: HashSet<Pubkey>,
+    others: HashSet<Pubkey>,
+    decimals: HashMap<Pubkey, u8>,
+    overrides: HashMap<Pubkey, u8>, // Forced with --decimals; win over on-chain values
+}
+
+impl MintFilter {
+    /// USDC on `network`, or on mainnet and devnet when none was chosen
+    pub fn usdc(network: Option<Cluster>) -> Self {
+        let mints = match network {
+            Some(network) => network.usdc_mint().into_iter().collect(),
+            None => vec![USDC_MAINNET, USDC_DEVNET],
+        };
+
+        let mut filter = Self {
+            usdc: HashSet::new(),
+            others: HashSet::new(),
+            decimals: HashMap::new(),
+            overrides: HashMap::new(),
+        };
+        for mint in mints {
+            filter = filter.with_usdc_mint(Pubkey::from_str(mint).expect("valid mint"));
+        }
+        filter
+    }
+
+    /// Also treat `mint` as USDC, e.g. a mint created on a local test validator
+    pub fn with_usdc_mint(mut self, mint: Pubkey) -> Self {
+        self.usdc.insert(mint);
+        self.decimals.insert(mint, USDC_DECIMALS);
+        self
+    }
+
+    /// Index wSOL transfers alongside USDC
+    pub fn with_wsol(mut self) -> Self {
+        let mint = Pubkey::from_str(WSOL_MINT).expect("valid mint");
+        self.others.insert(mint);
+        self.decimals.insert(mint, WSOL_DECIMALS);
+        self
+    }
+
+    /// Force the decimals of one mint, or of every USDC mint added so far
+    pub fn with_decimals(mut self, decimals_override: DecimalsOverride) -> Self {
+        match decimals_override.mint {
+            Some(mint) => {
+                self.overrides.insert(mint, decimals_override.decimals);
+            }
+            None => {
+                for mint in &self.usdc {
+                    self.overrides.insert(*mint, decimals_override.decimals);
+                }
+            }
+        }
+        self
+    }
+
+    pub fn is_usdc(&self, mint: &str) -> bool {
+        Pubkey::from_str(mint).is_ok_and(|mint| self.usdc.contains(&mint))
+    }
+
+    /// Whether transfers of `mint` are indexed at all
+    pub fn is_indexed(&self, mint: &str) -> bool {
+        Pubkey::from_str(mint).is_ok_and(|mint| self.usdc.contains(&mint) || self.others.contains(&mint))
+    }
+
+    /// Decimals of an indexed mint; USDC's for any other
+    pub fn decimals(&self, mint: &str) -> u8 {
+        self.decimals_override(mint)
+            .or_else(|| Pubkey::from_str(mint).ok().and_then(|mint| self.decimals.get(&mint).copied()))
+            .unwrap_or(USDC_DECIMALS)
+    }
+
+    /// Decimals forced for `mint` with --decimals, if any
+    pub fn decimals_override(&self, mint: &str) -> Option<u8> {
+        Pubkey::from_str(mint).ok().and_then(|mint| self.overrides.get(&mint).copied())
+    }
+
+    /// Decimals for amounts that sum up USDC, such as totals and balances
+    pub fn usdc_decimals(&self) -> u8 {
+        self.usdc_mints()
+            .first()
+            .map_or(USDC_DECIMALS, |mint| self.decimals(&mint.to_string()))
+    }
+
+    /// Every mint treated as USDC, in a stable order
+    pub fn usdc_mints(&self) -> Vec<Pubkey> {
+        let mut mints: Vec<Pubkey> = self.usdc.iter().copied().collect();
+        mints.sort();
+        mints
+    }
+}
+
+impl Default for MintFilter {
+    fn default() -> Self {
+        Self::usdc(None)
+    }
+}
@@ -0,0 +1,874 @@
+use anyhow::{bail, Result};
+use async_compression::tokio::write::GzipEncoder;
+use async_trait::async_trait;
+use flate2::read::MultiGzDecoder;
+use serde::Deserialize;
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use sha2::{Digest, Sha256};
+use std::sync::{Mutex, OnceLock};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::report::{CounterpartyTotals, PeriodTotals, Summary};
+use crate::store::TransferStore;
+use crate::transfer::{TransferDirection, TransferKey, UsdcTransfer};
+use crate::utils::{exact_amount, format_amount, short_address};
+
+/// File format for exported transfers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// A single pretty-printed JSON array
+    Json,
+    /// One standalone JSON object per line
+    Ndjson,
+    /// A GitHub-flavored Markdown report for sharing
+    Markdown,
+    /// One CSV row per transfer
+    Csv,
+    /// A SQLite database that accumulates transfers across runs
+    Sqlite,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Sqlite => "db",
+        }
+    }
+
+    pub fn default_path(&self) -> PathBuf {
+        PathBuf::from(format!("usdc_transfers.{}", self.extension()))
+    }
+
+    /// Whether the format is written as a stream that gzip can wrap
+    pub fn compressible(&self) -> bool {
+        matches!(self, OutputFormat::Json | OutputFormat::Ndjson)
+    }
+
+    /// Whether a `.sha256` checksum is kept alongside the output
+    pub fn checksummed(&self) -> bool {
+        matches!(self, OutputFormat::Json | OutputFormat::Csv)
+    }
+}
+
+/// A file written under a temporary name next to its target and renamed over
+/// it by `commit`, so a crash mid-write never leaves a truncated target. The
+/// temporary file is removed if it is dropped uncommitted.
+pub struct AtomicFile {
+    pub temp: PathBuf,
+    target: PathBuf,
+    committed: bool,
+}
+
+impl AtomicFile {
+    pub fn new(target: &Path) -> Self {
+        let name = target.file_name().map_or_else(Default::default, |name| name.to_string_lossy());
+        Self {
+            temp: target.with_file_name(format!(".{}.{}.tmp", name, std::process::id())),
+            target: target.to_path_buf(),
+            committed: false,
+        }
+    }
+
+    /// Start from a copy of the target, for appending
+    pub async fn copy_target(&self) -> std::io::Result<()> {
+        if tokio::fs::try_exists(&self.target).await? {
+            tokio::fs::copy(&self.target, &self.temp).await?;
+        }
+        Ok(())
+    }
+
+    /// Flush the temporary file to disk and move it into place
+    pub fn commit(&mut self) -> std::io::Result<()> {
+        File::open(&self.temp)?.sync_all()?;
+        std::fs::rename(&self.temp, &self.target)?;
+        self.committed = true;
+        Ok(())
+    }
+
+    /// `commit` without blocking the runtime, for async writers
+    pub async fn commit_async(&mut self) -> std::io::Result<()> {
+        tokio::fs::File::open(&self.temp).await?.sync_all().await?;
+        tokio::fs::rename(&self.temp, &self.target).await?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// Replace `path` with `contents` in one step, like `std::fs::write` but
+/// never leaving a partly written file behind
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let mut file = AtomicFile::new(path);
+    std::fs::write(&file.temp, contents)?;
+    file.commit()
+}
+
+/// The `.sha256` file kept alongside `path`
+pub fn checksum_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+/// The `.partial` marker kept alongside `path` while it holds an
+/// interrupted or capped backfill
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".partial");
+    PathBuf::from(name)
+}
+
+/// Create or clear the `.partial` marker of `path`
+pub fn mark_partial(path: &Path, partial: bool) -> std::io::Result<()> {
+    let marker = partial_path(path);
+    if partial {
+        write_atomic(&marker, "Indexing stopped before the end of the window; transfers may be missing\n")
+    } else if marker.exists() {
+        std::fs::remove_file(marker)
+    } else {
+        Ok(())
+    }
+}
+
+/// Write the raw transactions behind a run's transfers as one JSON object
+/// keyed by signature, which --input can parse again
+pub fn write_raw_transactions(
+    path: &Path,
+    transactions: &BTreeMap<String, EncodedConfirmedTransactionWithStatusMeta>,
+) -> Result<()> {
+    write_atomic(path, serde_json::to_vec_pretty(transactions)?)?;
+    Ok(())
+}
+
+/// SHA-256 of the file at `path`, read in chunks without blocking the runtime
+async fn sha256_hex(path: &Path) -> Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Record the SHA-256 of `path` in `sha256sum` format, so `sha256sum -c`
+/// can check it too
+async fn write_checksum(path: &Path) -> Result<()> {
+    let name = path.file_name().map_or_else(Default::default, |name| name.to_string_lossy());
+    let contents = format!("{}  {}\n", sha256_hex(path).await?, name);
+    let mut file = AtomicFile::new(&checksum_path(path));
+    tokio::fs::write(&file.temp, contents).await?;
+    file.commit_async().await?;
+    Ok(())
+}
+
+/// Check an existing output file against its `.sha256` before it is appended
+/// to. A missing output is fine; a missing or mismatching checksum is not.
+pub async fn verify_checksum(path: &Path) -> Result<()> {
+    if !tokio::fs::try_exists(path).await? {
+        return Ok(());
+    }
+
+    let checksum_path = checksum_path(path);
+    let recorded = match tokio::fs::read_to_string(&checksum_path).await {
+        Ok(recorded) => recorded,
+        Err(e) => bail!("Cannot read checksum {}: {}", checksum_path.display(), e),
+    };
+    let expected = recorded.split_whitespace().next().unwrap_or_default();
+    let actual = sha256_hex(path).await?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        bail!("{} does not match its checksum (expected {}, found {})", path.display(), expected, actual);
+    }
+    Ok(())
+}
+
+/// The process's original stdout once `divert_stdout` has moved logging off it
+static RESULT_STDOUT: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Send everything printed to stdout from here on to stderr, keeping the
+/// original stdout for `write_result`, so a pipe only ever sees the result
+#[cfg(unix)]
+pub fn divert_stdout() -> std::io::Result<()> {
+    use std::os::fd::{AsFd, OwnedFd};
+
+    std::io::stdout().flush()?;
+    let original: OwnedFd = std::io::stdout().as_fd().try_clone_to_owned()?;
+    // SAFETY: both are open descriptors of this process; dup2 only rebinds fd 1
+    if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let _ = RESULT_STDOUT.set(Mutex::new(File::from(original)));
+    Ok(())
+}
+
+/// Logs can't be moved off stdout here; the result is still written to it
+#[cfg(not(unix))]
+pub fn divert_stdout() -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Write `transfers` as a JSON array to the original stdout, for --stdout
+pub fn write_result(transfers: &[UsdcTransfer]) -> Result<()> {
+    write_to_result_stdout(|out| {
+        serde_json::to_writer_pretty(&mut *out, transfers)?;
+        writeln!(out)?;
+        Ok(())
+    })
+}
+
+/// Write one line to the original stdout, for --summary-only
+pub fn write_result_line(line: &str) -> Result<()> {
+    write_to_result_stdout(|out| Ok(writeln!(out, "{}", line)?))
+}
+
+fn write_to_result_stdout(write: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
+    let write = |out: &mut dyn Write| -> Result<()> {
+        write(out)?;
+        out.flush()?;
+        Ok(())
+    };
+
+    match RESULT_STDOUT.get() {
+        Some(out) => write(&mut *out.lock().unwrap_or_else(|e| e.into_inner())),
+        None => write(&mut std::io::stdout().lock()),
+    }
+}
+
+/// Whether `path` names a gzip file, going by its `.gz` extension
+pub fn is_gzip(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "gz")
+}
+
+/// A buffered async output file, gzip-compressed when its path ends in `.gz`
+enum FileWriter {
+    Plain(tokio::io::BufWriter<tokio::fs::File>),
+    Gzip(GzipEncoder<tokio::io::BufWriter<tokio::fs::File>>),
+}
+
+impl FileWriter {
+    /// Open `path` for writing, compressed when `target` (the file `path`
+    /// will become, for a temporary one) is a gzip file. Appending to a gzip
+    /// file adds a new gzip member, which decompressors read as a
+    /// continuation of the stream.
+    async fn open(path: &Path, target: &Path, append: bool) -> Result<Self> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .await?;
+
+        let writer = tokio::io::BufWriter::new(file);
+        Ok(if is_gzip(target) {
+            FileWriter::Gzip(GzipEncoder::new(writer))
+        } else {
+            FileWriter::Plain(writer)
+        })
+    }
+
+    async fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        match self {
+            FileWriter::Plain(writer) => writer.write_all(buf).await,
+            FileWriter::Gzip(encoder) => encoder.write_all(buf).await,
+        }
+    }
+
+    /// Flush everything, including the gzip trailer
+    async fn finish(&mut self) -> std::io::Result<()> {
+        match self {
+            FileWriter::Plain(writer) => writer.flush().await,
+            FileWriter::Gzip(encoder) => encoder.shutdown().await,
+        }
+    }
+}
+
+/// A destination for exported transfers. Each transfer is written out as it
+/// is handed over with `write_transfer`; `finish` completes the output and
+/// describes where it went.
+#[async_trait]
+pub trait OutputSink: Send {
+    async fn write_transfer(&mut self, transfer: &UsdcTransfer) -> Result<()>;
+
+    async fn finish(&mut self, summary: &Summary) -> Result<String>;
+}
+
+/// Open the sink for `format` at `path`. With `append`, existing output is
+/// extended rather than replaced, where the format allows it.
+pub async fn open_sink(
+    format: OutputFormat,
+    path: &Path,
+    append: bool,
+    wallet: &str,
+) -> Result<Box<dyn OutputSink>> {
+    Ok(match format {
+        OutputFormat::Json => Box::new(JsonSink::open(path, append).await?),
+        OutputFormat::Ndjson => Box::new(NdjsonSink::open(path, append).await?),
+        OutputFormat::Markdown => Box::new(MarkdownSink::open(path, wallet).await?),
+        OutputFormat::Csv => Box::new(CsvSink::open(path, append).await?),
+        OutputFormat::Sqlite => Box::new(SqliteSink::open(path)?),
+    })
+}
+
+/// A pretty-printed JSON array (gzipped for a `.gz` path), written element
+/// by element under a temporary name and recorded in its checksum. Transfers
+/// are expected newest first. With `append`, the transfers already in the
+/// file are merged in by timestamp, and new ones already present are skipped.
+struct JsonSink {
+    path: PathBuf,
+    file: AtomicFile,
+    writer: FileWriter,
+    existing: VecDeque<UsdcTransfer>, // Not written yet; newest first
+    seen: HashSet<TransferKey>,
+    written: usize,
+    append: bool,
+}
+
+impl JsonSink {
+    async fn open(path: &Path, append: bool) -> Result<Self> {
+        let mut existing: Vec<UsdcTransfer> = if append && tokio::fs::try_exists(path).await? {
+            let contents = tokio::fs::read(path).await?;
+            if is_gzip(path) {
+                serde_json::from_reader(MultiGzDecoder::new(contents.as_slice()))?
+            } else {
+                serde_json::from_slice(&contents)?
+            }
+        } else {
+            Vec::new()
+        };
+        existing.sort_by_key(|transfer| std::cmp::Reverse(transfer.timestamp));
+
+        let file = AtomicFile::new(path);
+        let mut writer = FileWriter::open(&file.temp, path, false).await?;
+        writer.write_all(b"[").await?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            writer,
+            seen: existing.iter().map(UsdcTransfer::key).collect(),
+            existing: existing.into(),
+            written: 0,
+            append,
+        })
+    }
+
+    /// Write one element, indented like `serde_json::to_writer_pretty` does
+    async fn write_element(&mut self, transfer: &UsdcTransfer) -> Result<()> {
+        let element = serde_json::to_string_pretty(transfer)?.replace('\n', "\n  ");
+        let separator = if self.written == 0 { "\n  " } else { ",\n  " };
+        self.writer.write_all(format!("{}{}", separator, element).as_bytes()).await?;
+        self.written += 1;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl OutputSink for JsonSink {
+    async fn write_transfer(&mut self, transfer: &UsdcTransfer) -> Result<()> {
+        if !self.seen.insert(transfer.key()) {
+            return Ok(());
+        }
+        while let Some(existing) = self.existing.front().filter(|existing| existing.timestamp >= transfer.timestamp) {
+            let existing = existing.clone();
+            self.existing.pop_front();
+            self.write_element(&existing).await?;
+        }
+        self.write_element(transfer).await
+    }
+
+    async fn finish(&mut self, _summary: &Summary) -> Result<String> {
+        while let Some(existing) = self.existing.pop_front() {
+            self.write_element(&existing).await?;
+        }
+        let end: &[u8] = if self.written == 0 { b"]" } else { b"\n]" };
+        self.writer.write_all(end).await?;
+        self.writer.finish().await?;
+        self.file.commit_async().await?;
+        write_checksum(&self.path).await?;
+
+        Ok(if self.append {
+            format!("Results merged into: {} ({} transfers total)", self.path.display(), self.written)
+        } else {
+            format!("Results saved to: {}", self.path.display())
+        })
+    }
+}
+
+
+/// Version of the output schema. The minor number goes up when fields are
+/// added, the major one when fields change meaning or go away.
+pub const SCHEMA_VERSION: &str = "1.2.0";
+
+/// Write the JSON Schema of the `--format json` output, an array of
+/// transfers. Each `--format ndjson` line is one of its items.
+pub fn write_schema(path: &Path) -> Result<()> {
+    let mut schema = schemars::schema_for!(Vec<UsdcTransfer>);
+    schema.schema.metadata().title = Some("USDC transfers".to_string());
+    schema
+        .schema
+        .extensions
+        .insert("version".to_string(), serde_json::Value::from(SCHEMA_VERSION));
+
+    write_atomic(path, serde_json::to_string_pretty(&schema)?)?;
+    Ok(())
+}
+
+/// Each transfer as its own JSON line through a buffered writer, gzipped for
+/// a `.gz` path. A new file is written under a temporary name and moved into
+/// place on `finish`. With `append`, lines are added to the end of an existing
+/// file directly, skipping transfers already in it; an interrupted append can
+/// leave at most one partial last line.
+struct NdjsonSink {
+    path: PathBuf,
+    file: Option<AtomicFile>, // None when appending in place
+    writer: FileWriter,
+    seen: HashSet<TransferKey>,
+    written: usize,
+}
+
+/// The keys of the transfers on the lines of existing NDJSON output
+fn read_ndjson_keys(contents: impl BufRead) -> Result<HashSet<TransferKey>> {
+    let mut seen = HashSet::new();
+    for line in contents.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            seen.insert(serde_json::from_str::<KeyFields>(&line)?.key());
+        }
+    }
+    Ok(seen)
+}
+
+impl NdjsonSink {
+    async fn open(path: &Path, append: bool) -> Result<Self> {
+        let in_place = append && tokio::fs::try_exists(path).await?;
+        let seen = if in_place {
+            let contents = tokio::fs::read(path).await?;
+            if is_gzip(path) {
+                read_ndjson_keys(BufReader::new(MultiGzDecoder::new(contents.as_slice())))?
+            } else {
+                read_ndjson_keys(contents.as_slice())?
+            }
+        } else {
+            HashSet::new()
+        };
+
+        let file = (!in_place).then(|| AtomicFile::new(path));
+        let writer = match &file {
+            Some(file) => FileWriter::open(&file.temp, path, false).await?,
+            None => FileWriter::open(path, path, true).await?,
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            writer,
+            seen,
+            written: 0,
+        })
+    }
+}
+
+#[async_trait]
+impl OutputSink for NdjsonSink {
+    async fn write_transfer(&mut self, transfer: &UsdcTransfer) -> Result<()> {
+        if !self.seen.insert(transfer.key()) {
+            return Ok(());
+        }
+        let mut line = serde_json::to_vec(transfer)?;
+        line.push(b'\n');
+        self.writer.write_all(&line).await?;
+        self.written += 1;
+        Ok(())
+    }
+
+    async fn finish(&mut self, _summary: &Summary) -> Result<String> {
+        self.writer.finish().await?;
+        if let Some(file) = &mut self.file {
+            file.commit_async().await?;
+        }
+        Ok(format!("{} transfers written to: {}", self.written, self.path.display()))
+    }
+}
+
+/// A Markdown report: a header, one table row per transfer with its signature
+/// linked to the explorer, and the summary totals. The report describes just
+/// the transfers of this run, so it is always rewritten.
+struct MarkdownSink {
+    path: PathBuf,
+    file: AtomicFile,
+    writer: FileWriter,
+}
+
+impl MarkdownSink {
+    async fn open(path: &Path, wallet: &str) -> Result<Self> {
+        let file = AtomicFile::new(path);
+        let mut writer = FileWriter::open(&file.temp, path, false).await?;
+
+        let header = format!(
+            "# USDC Transfers for `{}`\n\n\
+             | Timestamp (UTC) | Direction | Amount (USDC) | Counterparty | Signature |\n\
+             |---|---|---:|---|---|\n",
+            wallet
+        );
+        writer.write_all(header.as_bytes()).await?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            writer,
+        })
+    }
+}
+
+#[async_trait]
+impl OutputSink for MarkdownSink {
+    async fn write_transfer(&mut self, transfer: &UsdcTransfer) -> Result<()> {
+        let (direction, counterparty) = match transfer.direction {
+            TransferDirection::Sent => ("Sent", short_address(&transfer.to)),
+            TransferDirection::Received => ("Received", short_address(&transfer.from)),
+            TransferDirection::SelfTransfer => ("Self", "—"),
+            TransferDirection::Mint => ("Mint", short_address(&transfer.from)),
+            TransferDirection::Burn => ("Burn", "—"),
+        };
+
+        let signature = match &transfer.explorer_url {
+            Some(url) => format!("[{}]({})", short_address(&transfer.signature), url),
+            None => format!("`{}`", short_address(&transfer.signature)),
+        };
+
+        let row = format!(
+            "| {} | {} | {} | `{}` | {} |\n",
+            transfer.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            direction,
+            format_amount(transfer.amount as i128, transfer.decimals, None),
+            counterparty,
+            signature,
+        );
+        self.writer.write_all(row.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn finish(&mut self, summary: &Summary) -> Result<String> {
+        let mut footer = String::new();
+        writeln!(footer)?;
+        writeln!(footer, "## Summary")?;
+        writeln!(footer)?;
+        writeln!(footer, "- **Transfers:** {}", summary.count)?;
+        writeln!(footer, "- **Total Received:** {} USDC", summary.ui_amount(summary.total_received as i128))?;
+        writeln!(footer, "- **Total Sent:** {} USDC", summary.ui_amount(summary.total_sent as i128))?;
+        if summary.total_minted > 0 || summary.total_burned > 0 {
+            writeln!(footer, "- **Total Minted:** {} USDC", summary.ui_amount(summary.total_minted as i128))?;
+            writeln!(footer, "- **Total Burned:** {} USDC", summary.ui_amount(summary.total_burned as i128))?;
+        }
+        writeln!(footer, "- **Net Change:** {} USDC", summary.ui_amount(summary.net))?;
+        if let Some(total_usd) = summary.usd_value {
+            writeln!(footer, "- **Total USD Moved:** ${:.2}", total_usd)?;
+        }
+        writeln!(footer, "- **Fees on Sent Transfers:** {} SOL", summary.fees_sol())?;
+
+        self.writer.write_all(footer.as_bytes()).await?;
+        self.writer.finish().await?;
+        self.file.commit_async().await?;
+        Ok(format!("Report saved to: {}", self.path.display()))
+    }
+}
+
+/// One CSV row per transfer with the same fields as the JSON export. Rows are
+/// encoded in memory and written out one by one. With `append`, rows are
+/// added to a copy of the existing file without repeating the header, and
+/// transfers already in it are skipped; the copy replaces the file once
+/// complete. A file written with other columns is left alone.
+struct CsvSink {
+    path: PathBuf,
+    file: AtomicFile,
+    writer: FileWriter,
+    existing_header: Option<csv::StringRecord>, // Of the file being appended to
+    seen: HashSet<TransferKey>,
+    written: usize,
+}
+
+/// The fields of a stored transfer that make up its `TransferKey`
+#[derive(Deserialize)]
+struct KeyFields {
+    signature: String,
+    from: String,
+    to: String,
+    amount: u64,
+    direction: TransferDirection,
+}
+
+impl KeyFields {
+    fn key(self) -> TransferKey {
+        (self.signature, self.from, self.to, self.amount, self.direction)
+    }
+}
+
+/// The header of existing CSV output, unless it is empty, and the keys of its rows
+fn read_csv_keys(contents: impl std::io::Read) -> Result<(Option<csv::StringRecord>, HashSet<TransferKey>)> {
+    let mut reader = csv::Reader::from_reader(contents);
+    let header = reader.headers()?.clone();
+    if header.is_empty() {
+        return Ok((None, HashSet::new()));
+    }
+    let seen = reader
+        .deserialize()
+        .map(|row| row.map(KeyFields::key))
+        .collect::<Result<_, _>>()?;
+    Ok((Some(header), seen))
+}
+
+impl CsvSink {
+    async fn open(path: &Path, append: bool) -> Result<Self> {
+        let (existing_header, seen) = if append && tokio::fs::try_exists(path).await? {
+            let contents = tokio::fs::read(path).await?;
+            if is_gzip(path) {
+                read_csv_keys(MultiGzDecoder::new(contents.as_slice()))?
+            } else {
+                read_csv_keys(contents.as_slice())?
+            }
+        } else {
+            (None, HashSet::new())
+        };
+
+        let file = AtomicFile::new(path);
+        if existing_header.is_some() {
+            file.copy_target().await?;
+        }
+        let writer = FileWriter::open(&file.temp, path, existing_header.is_some()).await?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            writer,
+            existing_header,
+            seen,
+            written: 0,
+        })
+    }
+}
+
+#[async_trait]
+impl OutputSink for CsvSink {
+    async fn write_transfer(&mut self, transfer: &UsdcTransfer) -> Result<()> {
+        if !self.seen.insert(transfer.key()) {
+            return Ok(());
+        }
+        let mut encoder = csv::Writer::from_writer(Vec::new());
+        encoder.serialize(transfer)?;
+        let encoded = encoder.into_inner().map_err(|e| e.into_error())?;
+        // The header holds only field names, so it ends at the first newline
+        let header_end = encoded.iter().position(|&byte| byte == b'\n').map_or(0, |end| end + 1);
+        let (header, row) = encoded.split_at(header_end);
+
+        if self.written == 0 {
+            match &self.existing_header {
+                None => self.writer.write_all(header).await?,
+                Some(existing) if csv::Reader::from_reader(header).headers()? != existing => {
+                    bail!("its columns differ from the ones this version writes; export to a new file instead")
+                }
+                Some(_) => {}
+            }
+        }
+        self.writer.write_all(row).await?;
+        self.written += 1;
+        Ok(())
+    }
+
+    async fn finish(&mut self, _summary: &Summary) -> Result<String> {
+        self.writer.finish().await?;
+        self.file.commit_async().await?;
+        write_checksum(&self.path).await?;
+        Ok(format!("{} transfers written to: {}", self.written, self.path.display()))
+    }
+}
+
+/// Inserts into a `TransferStore`. The database always accumulates; transfers
+/// already stored are skipped, so `append` makes no difference. SQLite is
+/// synchronous, so its calls run with `block_in_place`.
+struct SqliteSink {
+    path: PathBuf,
+    store: TransferStore,
+    inserted: usize,
+}
+
+impl SqliteSink {
+    fn open(path: &Path) -> Result<Self> {
+        let store = tokio::task::block_in_place(|| -> Result<TransferStore> {
+            let store = TransferStore::open(path)?;
+            store.begin()?;
+            Ok(store)
+        })?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            store,
+            inserted: 0,
+        })
+    }
+}
+
+#[async_trait]
+impl OutputSink for SqliteSink {
+    async fn write_transfer(&mut self, transfer: &UsdcTransfer) -> Result<()> {
+        if tokio::task::block_in_place(|| self.store.insert(transfer))? {
+            self.inserted += 1;
+        }
+        Ok(())
+    }
+
+    async fn finish(&mut self, _summary: &Summary) -> Result<String> {
+        tokio::task::block_in_place(|| self.store.commit())?;
+        Ok(format!("{} new transfers stored in: {}", self.inserted, self.path.display()))
+    }
+}
+
+/// Write counterparty totals as CSV, ranked in the order given
+pub fn write_counterparties_csv(path: &Path, totals: &[CounterpartyTotals], decimals: u8) -> Result<()> {
+    let mut file = AtomicFile::new(path);
+    let mut writer = BufWriter::new(std::fs::File::create(&file.temp)?);
+    writeln!(writer, "rank,counterparty,volume,sent,received,net")?;
+
+    for (rank, totals) in totals.iter().enumerate() {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{}",
+            rank + 1,
+            totals.counterparty,
+            exact_amount(totals.volume() as i128, decimals),
+            exact_amount(totals.sent as i128, decimals),
+            exact_amount(totals.received as i128, decimals),
+            exact_amount(totals.net(), decimals),
+        )?;
+    }
+
+    writer.flush()?;
+    drop(writer);
+    file.commit()?;
+    Ok(())
+}
+
+/// Write period totals as CSV, with a final grand-total row
+pub fn write_aggregate_csv(path: &Path, totals: &[PeriodTotals], decimals: u8) -> Result<()> {
+    let mut file = AtomicFile::new(path);
+    let mut writer = BufWriter::new(std::fs::File::create(&file.temp)?);
+    writeln!(writer, "period_start,count,received,sent,net")?;
+
+    let (mut count, mut received, mut sent) = (0usize, 0u128, 0u128);
+    for bucket in totals {
+        writeln!(
+            writer,
+            "{},{},{},{},{}",
+            bucket.start,
+            bucket.count,
+            exact_amount(bucket.received as i128, decimals),
+            exact_amount(bucket.sent as i128, decimals),
+            exact_amount(bucket.net(), decimals),
+        )?;
+        count += bucket.count;
+        received += bucket.received;
+        sent += bucket.sent;
+    }
+
+    writeln!(
+        writer,
+        "total,{},{},{},{}",
+        count,
+        exact_amount(received as i128, decimals),
+        exact_amount(sent as i128, decimals),
+        exact_amount(received as i128 - sent as i128, decimals),
+    )?;
+
+    writer.flush()?;
+    drop(writer);
+    file.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_covers_every_transfer_field() {
+        let path = std::env::temp_dir().join(format!("usdc_schema_{}.json", std::process::id()));
+        write_schema(&path).unwrap();
+        let schema: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(schema["version"], SCHEMA_VERSION);
+        let sample = serde_json::to_value(UsdcTransfer::sample("a", crate::transfer::TransferDirection::Sent, 1)).unwrap();
+        let properties = &schema["definitions"]["UsdcTransfer"]["properties"];
+        for field in sample.as_object().unwrap().keys() {
+            assert!(properties.get(field).is_some(), "{} is missing from the schema", field);
+        }
+    }
+
+    async fn export(format: OutputFormat, path: &Path, transfers: &[UsdcTransfer], append: bool) -> Result<String> {
+        let summary = crate::report::summarize(transfers, &crate::mints::MintFilter::default());
+        let mut sink = open_sink(format, path, append, "wallet").await?;
+        for transfer in transfers {
+            sink.write_transfer(transfer).await?;
+        }
+        sink.finish(&summary).await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn csv_append_skips_rows_already_in_the_file() {
+        let path = std::env::temp_dir().join(format!("usdc_append_{}.csv", std::process::id()));
+        let first = UsdcTransfer::sample("a", TransferDirection::Sent, 1);
+        let second = UsdcTransfer::sample("b", TransferDirection::Received, 2);
+        export(OutputFormat::Csv, &path, std::slice::from_ref(&first), false).await.unwrap();
+        export(OutputFormat::Csv, &path, &[second, first], true).await.unwrap();
+
+        let rows: Vec<UsdcTransfer> = csv::Reader::from_path(&path).unwrap().deserialize().map(Result::unwrap).collect();
+        assert_eq!(rows.iter().map(|row| row.signature.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+
+        std::fs::write(&path, "signature,from,to,amount,direction\na,x,y,1,Sent\n").unwrap();
+        let refused = export(OutputFormat::Csv, &path, &[UsdcTransfer::sample("c", TransferDirection::Sent, 3)], true).await;
+        assert!(refused.is_err());
+        assert!(std::fs::read_to_string(&path).unwrap().ends_with("a,x,y,1,Sent\n"));
+
+        std::fs::remove_file(&path).unwrap();
+        let _ = std::fs::remove_file(checksum_path(&path));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ndjson_append_skips_lines_already_in_the_file() {
+        let path = std::env::temp_dir().join(format!("usdc_append_{}.ndjson.gz", std::process::id()));
+        let first = UsdcTransfer::sample("a", TransferDirection::Sent, 1);
+        let second = UsdcTransfer::sample("b", TransferDirection::Received, 2);
+        export(OutputFormat::Ndjson, &path, std::slice::from_ref(&first), false).await.unwrap();
+        export(OutputFormat::Ndjson, &path, &[second, first], true).await.unwrap();
+
+        let contents = std::fs::read(&path).unwrap();
+        let lines: Vec<UsdcTransfer> = BufReader::new(MultiGzDecoder::new(contents.as_slice()))
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect();
+        assert_eq!(lines.iter().map(|line| line.signature.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
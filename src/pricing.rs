@@ -0,0 +1,109 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use std::collections::HashMap;
+
+use crate::transfer::UsdcTransfer;
+
+const COINGECKO_API: &str = "https://api.coingecko.com/api/v3";
+
+/// Where USD prices come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PriceSource {
+    Coingecko,
+}
+
+/// Looks up historical USD prices, caching them per (mint, day) so a backfill
+/// makes at most one request per token per day it covers
+pub struct PriceOracle {
+    http: reqwest::Client,
+    source: PriceSource,
+    coin_ids: HashMap<String, Option<String>>,
+    prices: HashMap<(String, NaiveDate), Option<f64>>,
+}
+
+impl PriceOracle {
+    pub fn new(source: PriceSource) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            source,
+            coin_ids: HashMap::new(),
+            prices: HashMap::new(),
+        }
+    }
+
+    /// Fill in `usd_value` for every transfer whose price can be resolved
+    pub async fn annotate(&mut self, transfers: &mut [UsdcTransfer]) {
+        println!("💵 Looking up USD prices via {:?}...", self.source);
+
+        for transfer in transfers.iter_mut() {
+            let date = transfer.timestamp.date_naive();
+
+            match self.price_on(&transfer.mint, date).await {
+                Ok(Some(price)) => {
+                    let amount = transfer.amount as f64 / 10f64.powi(transfer.decimals as i32);
+                    transfer.usd_value = Some(amount * price);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    println!("⚠️ Price lookup failed for {} on {}: {}", transfer.mint, date, e);
+                }
+            }
+        }
+    }
+
+    async fn price_on(&mut self, mint: &str, date: NaiveDate) -> Result<Option<f64>> {
+        let key = (mint.to_string(), date);
+        if let Some(price) = self.prices.get(&key) {
+            return Ok(*price);
+        }
+
+        let price = match self.coin_id(mint).await? {
+            Some(coin_id) => self.fetch_price(&coin_id, date).await?,
+            None => None,
+        };
+
+        self.prices.insert(key, price);
+        Ok(price)
+    }
+
+    /// CoinGecko keys prices by its own coin id, so resolve it from the mint once
+    async fn coin_id(&mut self, mint: &str) -> Result<Option<String>> {
+        if let Some(coin_id) = self.coin_ids.get(mint) {
+            return Ok(coin_id.clone());
+        }
+
+        let url = format!("{}/coins/solana/contract/{}", COINGECKO_API, mint);
+        let response = self.http.get(&url).send().await?;
+
+        let coin_id = if response.status() == reqwest::StatusCode::NOT_FOUND {
+            None
+        } else {
+            let body: serde_json::Value = response.error_for_status()?.json().await?;
+            body["id"].as_str().map(str::to_string)
+        };
+
+        self.coin_ids.insert(mint.to_string(), coin_id.clone());
+        Ok(coin_id)
+    }
+
+    async fn fetch_price(&self, coin_id: &str, date: NaiveDate) -> Result<Option<f64>> {
+        let url = format!(
+            "{}/coins/{}/history?date={}&localization=false",
+            COINGECKO_API,
+            coin_id,
+            date.format("%d-%m-%Y")
+        );
+
+        let body: serde_json::Value = self
+            .http
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()
+            .map_err(|e| anyhow!("CoinGecko request failed: {}", e))?
+            .json()
+            .await?;
+
+        Ok(body["market_data"]["current_price"]["usd"].as_f64())
+    }
+}
@@ -0,0 +1,122 @@
+use chrono::{DateTime, Utc};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often to log progress when stderr isn't a terminal
+const LOG_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Extent of a backfill, measured backwards from its newest end. Pagination
+/// runs newest first, so the distance from that end is how far along we are.
+#[derive(Debug, Clone, Copy)]
+pub enum Span {
+    Time { end: DateTime<Utc>, seconds: u64 },
+    Slots { to: u64, slots: u64 },
+}
+
+enum Display {
+    Hidden,
+    Bar(ProgressBar),
+    Log(Mutex<Instant>),
+}
+
+/// Backfill progress: a bar with an ETA on a terminal, periodic log lines
+/// when scripted, nothing with `--quiet`. Shared by chunk worker threads.
+pub struct Progress {
+    display: Display,
+    span: Option<Span>,
+    transactions: AtomicU64,
+    transfers: AtomicU64,
+}
+
+impl Progress {
+    pub fn new(quiet: bool, span: Option<Span>) -> Self {
+        let display = if quiet {
+            Display::Hidden
+        } else if std::io::stderr().is_terminal() {
+            Display::Bar(Self::bar(span))
+        } else {
+            Display::Log(Mutex::new(Instant::now()))
+        };
+
+        Self {
+            display,
+            span,
+            transactions: AtomicU64::new(0),
+            transfers: AtomicU64::new(0),
+        }
+    }
+
+    fn bar(span: Option<Span>) -> ProgressBar {
+        match span {
+            Some(Span::Time { seconds: length, .. } | Span::Slots { slots: length, .. }) => {
+                let bar = ProgressBar::new(length);
+                bar.set_style(
+                    ProgressStyle::with_template("{bar:30} {percent:>3}% [{elapsed_precise} ETA {eta}] {msg}")
+                        .expect("valid progress template"),
+                );
+                bar
+            }
+            None => {
+                let bar = ProgressBar::new_spinner();
+                bar.set_style(
+                    ProgressStyle::with_template("{spinner} [{elapsed_precise}] {msg}").expect("valid progress template"),
+                );
+                bar.enable_steady_tick(Duration::from_millis(200));
+                bar
+            }
+        }
+    }
+
+    /// Record one processed transaction and the transfers found in it
+    pub fn record(&self, slot: u64, block_time: Option<DateTime<Utc>>, transfers: usize) {
+        let transactions = self.transactions.fetch_add(1, Ordering::Relaxed) + 1;
+        let found = self.transfers.fetch_add(transfers as u64, Ordering::Relaxed) + transfers as u64;
+
+        let date = block_time.map_or_else(|| format!("slot {}", slot), |time| time.format("%Y-%m-%d").to_string());
+        let message = format!("{} transactions | at {} | {} transfers found", transactions, date, found);
+
+        match &self.display {
+            Display::Hidden => {}
+            Display::Bar(bar) => {
+                if let Some(position) = self.position(slot, block_time) {
+                    bar.set_position(position);
+                }
+                bar.set_message(message);
+            }
+            Display::Log(last) => {
+                let mut last = last.lock().expect("progress lock poisoned");
+                if last.elapsed() >= LOG_INTERVAL {
+                    *last = Instant::now();
+                    println!("⏳ {}", message);
+                }
+            }
+        }
+    }
+
+    fn position(&self, slot: u64, block_time: Option<DateTime<Utc>>) -> Option<u64> {
+        match self.span? {
+            Span::Time { end, seconds } => {
+                let elapsed = (end - block_time?).num_seconds().max(0) as u64;
+                Some(elapsed.min(seconds))
+            }
+            Span::Slots { to, slots } => Some(to.saturating_sub(slot).min(slots)),
+        }
+    }
+
+    /// Print a line without tearing the bar
+    pub fn println(&self, line: impl AsRef<str>) {
+        match &self.display {
+            Display::Bar(bar) => bar.println(line),
+            _ => println!("{}", line.as_ref()),
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Display::Bar(bar) = &self.display {
+            bar.finish_and_clear();
+        }
+    }
+}
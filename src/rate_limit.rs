@@ -0,0 +1,59 @@
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Token bucket shared by every RPC call. Holds at most one second's worth of
+/// requests, so short bursts are allowed but the average never exceeds the
+/// configured rate.
+pub struct RateLimiter {
+    rate: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+static SHARED: OnceLock<Arc<RateLimiter>> = OnceLock::new();
+
+/// The process-wide limiter, created at the first caller's rate, so wallets
+/// indexed in parallel stay under one `--rps` budget together
+pub fn shared(requests_per_second: u32) -> Arc<RateLimiter> {
+    SHARED.get_or_init(|| Arc::new(RateLimiter::new(requests_per_second))).clone()
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: u32) -> Self {
+        let rate = requests_per_second.max(1) as f64;
+        Self {
+            rate,
+            bucket: Mutex::new(Bucket {
+                tokens: rate,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Block until a request may be sent. The RPC client itself is blocking,
+    /// so this waits on the calling thread the same way the request will.
+    pub fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+                let now = Instant::now();
+                let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.rate);
+                bucket.last_refill = now;
+
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate)
+            };
+
+            std::thread::sleep(wait);
+        }
+    }
+}
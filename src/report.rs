@@ -0,0 +1,583 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use crate::mints::MintFilter;
+use crate::transfer::{TransferDirection, UsdcTransfer};
+use crate::utils::{exact_amount, format_amount};
+
+/// Number of transfers in each direction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DirectionCounts {
+    pub sent: usize,
+    pub received: usize,
+    pub self_transfer: usize,
+    pub mint: usize,
+    pub burn: usize,
+}
+
+/// Descriptive statistics over transfer amounts, in raw token units
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct AmountStats {
+    pub count: usize,
+    pub mean: f64,
+    pub median: f64, // Mean of the middle two for an even count
+    pub min: u64,
+    pub max: u64,
+    pub std_dev: f64, // Population standard deviation
+}
+
+impl AmountStats {
+    /// Statistics over `amounts`, or `None` when there are none
+    pub fn compute(mut amounts: Vec<u64>) -> Option<Self> {
+        if amounts.is_empty() {
+            return None;
+        }
+        amounts.sort_unstable();
+
+        let count = amounts.len();
+        let mean = amounts.iter().map(|&amount| amount as f64).sum::<f64>() / count as f64;
+        let median = match count % 2 {
+            0 => (amounts[count / 2 - 1] as f64 + amounts[count / 2] as f64) / 2.0,
+            _ => amounts[count / 2] as f64,
+        };
+        let variance = amounts
+            .iter()
+            .map(|&amount| (amount as f64 - mean).powi(2))
+            .sum::<f64>()
+            / count as f64;
+
+        Some(Self {
+            count,
+            mean,
+            median,
+            min: amounts[0],
+            max: amounts[count - 1],
+            std_dev: variance.sqrt(),
+        })
+    }
+}
+
+/// Headline totals over a list of transfers, in raw token units
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    pub count: usize,
+    pub total_sent: u128,
+    pub total_received: u128,
+    pub total_minted: u128, // Issuance and burns aren't peer-to-peer, so they're kept
+    pub total_burned: u128, // out of the sent/received totals but do count towards net
+    pub net: i128,
+    pub by_direction: DirectionCounts,
+    pub fees_lamports: u64,     // Fees on sent transfers, each transaction counted once
+    pub usd_value: Option<f64>, // Only set when some transfer was priced
+    pub sent_stats: Option<AmountStats>,
+    pub received_stats: Option<AmountStats>,
+    pub decimals: u8,
+}
+
+impl Summary {
+    /// Render a raw amount from this summary exactly, with the mint's decimals
+    pub fn ui_amount(&self, raw: i128) -> String {
+        format_amount(raw, self.decimals, None)
+    }
+
+    pub fn fees_sol(&self) -> String {
+        exact_amount(self.fees_lamports as i128, SOL_DECIMALS)
+    }
+}
+
+const SOL_DECIMALS: u8 = 9;
+
+/// Total up the transfers of the mints `mints` treats as USDC. Self
+/// transfers are counted but move no funds; wSOL ones are in other units and
+/// left out.
+pub fn summarize(transfers: &[UsdcTransfer], mints: &MintFilter) -> Summary {
+    let transfers: Vec<&UsdcTransfer> = transfers.iter().filter(|transfer| mints.is_usdc(&transfer.mint)).collect();
+
+    // Accumulate in u128 so large volumes can't overflow
+    let mut total_sent = 0u128;
+    let mut total_received = 0u128;
+    let mut total_minted = 0u128;
+    let mut total_burned = 0u128;
+    let mut by_direction = DirectionCounts::default();
+    let mut fee_signatures = HashSet::new();
+    let mut fees_lamports = 0u64;
+
+    for transfer in &transfers {
+        match transfer.direction {
+            TransferDirection::Sent => {
+                total_sent += transfer.amount as u128;
+                by_direction.sent += 1;
+                // Count each transaction's fee once, even if it has several legs
+                if fee_signatures.insert(&transfer.signature) {
+                    fees_lamports += transfer.fee_lamports;
+                }
+            }
+            TransferDirection::Received => {
+                total_received += transfer.amount as u128;
+                by_direction.received += 1;
+            }
+            TransferDirection::SelfTransfer => by_direction.self_transfer += 1,
+            TransferDirection::Mint => {
+                total_minted += transfer.amount as u128;
+                by_direction.mint += 1;
+            }
+            TransferDirection::Burn => {
+                total_burned += transfer.amount as u128;
+                by_direction.burn += 1;
+            }
+        }
+    }
+
+    let usd_value = transfers
+        .iter()
+        .any(|transfer| transfer.usd_value.is_some())
+        .then(|| transfers.iter().filter_map(|transfer| transfer.usd_value).sum());
+
+    let amounts = |direction: TransferDirection| {
+        transfers
+            .iter()
+            .filter(|transfer| transfer.direction == direction)
+            .map(|transfer| transfer.amount)
+            .collect::<Vec<_>>()
+    };
+
+    Summary {
+        count: transfers.len(),
+        total_sent,
+        total_received,
+        total_minted,
+        total_burned,
+        net: (total_received + total_minted) as i128 - (total_sent + total_burned) as i128,
+        by_direction,
+        fees_lamports,
+        usd_value,
+        sent_stats: AmountStats::compute(amounts(TransferDirection::Sent)),
+        received_stats: AmountStats::compute(amounts(TransferDirection::Received)),
+        decimals: transfers.first().map_or_else(|| mints.usdc_decimals(), |transfer| transfer.decimals),
+    }
+}
+
+/// Put `transfers` in chronological order and set each one's `balance_after`
+/// by applying it to the balance left by the one before, starting from
+/// `starting_balance`. The result reads as a ledger. Only USDC transfers
+/// carry a balance; wSOL ones are left without.
+pub fn running_balance(transfers: &mut [UsdcTransfer], starting_balance: u64, mints: &MintFilter) {
+    transfers.sort_by_key(|transfer| (transfer.slot, transfer.timestamp));
+
+    let mut balance = starting_balance as i128;
+    for transfer in transfers.iter_mut().filter(|transfer| mints.is_usdc(&transfer.mint)) {
+        balance += transfer.balance_change();
+        // A wrong starting balance can drive this negative; don't wrap around
+        transfer.balance_after = Some(u64::try_from(balance).unwrap_or(0));
+    }
+}
+
+/// Mark USDC transfers as anomalies when their amount is more than `sigma`
+/// standard deviations above the mean of their direction, or above
+/// `threshold` raw units. Returns how many were flagged.
+pub fn flag_anomalies(transfers: &mut [UsdcTransfer], mints: &MintFilter, sigma: Option<f64>, threshold: Option<u64>) -> usize {
+    let summary = summarize(transfers, mints);
+    let mut flagged = 0;
+
+    for transfer in transfers.iter_mut().filter(|transfer| mints.is_usdc(&transfer.mint)) {
+        let stats = match transfer.direction {
+            TransferDirection::Sent => summary.sent_stats,
+            TransferDirection::Received => summary.received_stats,
+            _ => None,
+        };
+        let unusual = matches!((sigma, stats), (Some(sigma), Some(stats))
+            if stats.std_dev > 0.0 && transfer.amount as f64 > stats.mean + sigma * stats.std_dev);
+        let too_large = threshold.is_some_and(|threshold| transfer.amount > threshold);
+
+        transfer.anomaly = unusual || too_large;
+        flagged += transfer.anomaly as usize;
+    }
+
+    flagged
+}
+
+/// Extra breakdowns of the transfer list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GroupBy {
+    Counterparty,
+}
+
+/// Totals exchanged with a single counterparty
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CounterpartyTotals {
+    pub counterparty: String,
+    pub sent: u128,     // Sent by the wallet to this counterparty
+    pub received: u128, // Received by the wallet from this counterparty
+}
+
+impl CounterpartyTotals {
+    pub fn volume(&self) -> u128 {
+        self.sent + self.received
+    }
+
+    pub fn net(&self) -> i128 {
+        self.received as i128 - self.sent as i128
+    }
+}
+
+/// The other party of a transfer from the wallet's point of view. Self
+/// transfers and legs without a known counterparty have none.
+pub fn counterparty(transfer: &UsdcTransfer) -> Option<&str> {
+    let other = match transfer.direction {
+        TransferDirection::Sent => &transfer.to,
+        TransferDirection::Received => &transfer.from,
+        TransferDirection::SelfTransfer | TransferDirection::Mint | TransferDirection::Burn => return None,
+    };
+
+    if other.is_empty() {
+        None
+    } else {
+        Some(other)
+    }
+}
+
+/// Per-counterparty totals, largest volume first
+pub fn by_counterparty(transfers: &[UsdcTransfer]) -> Vec<CounterpartyTotals> {
+    let mut totals: HashMap<&str, CounterpartyTotals> = HashMap::new();
+
+    for transfer in transfers {
+        let Some(other) = counterparty(transfer) else {
+            continue;
+        };
+
+        let entry = totals.entry(other).or_insert_with(|| CounterpartyTotals {
+            counterparty: other.to_string(),
+            sent: 0,
+            received: 0,
+        });
+
+        match transfer.direction {
+            TransferDirection::Sent => entry.sent += transfer.amount as u128,
+            TransferDirection::Received => entry.received += transfer.amount as u128,
+            TransferDirection::SelfTransfer | TransferDirection::Mint | TransferDirection::Burn => {}
+        }
+    }
+
+    let mut totals: Vec<CounterpartyTotals> = totals.into_values().collect();
+    totals.sort_by(|a, b| b.volume().cmp(&a.volume()).then_with(|| a.counterparty.cmp(&b.counterparty)));
+    totals
+}
+
+/// The `n` counterparties with the largest volume. Legs whose other side is
+/// `wallet` itself, e.g. between two of its token accounts, are left out.
+pub fn top_counterparties(transfers: &[UsdcTransfer], wallet: &str, n: usize) -> Vec<CounterpartyTotals> {
+    let mut totals = by_counterparty(transfers);
+    totals.retain(|totals| totals.counterparty != wallet);
+    totals.truncate(n);
+    totals
+}
+
+/// Bucket size for period aggregation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Period {
+    Daily,
+    Weekly, // Weeks start on Monday
+    Monthly,
+}
+
+impl Period {
+    /// First day of the bucket containing `date`
+    pub fn truncate(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Daily => date,
+            Period::Weekly => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            Period::Monthly => date.with_day(1).expect("day 1 exists in every month"),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Period::Daily => "daily",
+            Period::Weekly => "weekly",
+            Period::Monthly => "monthly",
+        }
+    }
+}
+
+/// Totals for one aggregation bucket
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeriodTotals {
+    pub start: NaiveDate,
+    pub sent: u128,
+    pub received: u128,
+    pub count: usize,
+}
+
+impl PeriodTotals {
+    pub fn net(&self) -> i128 {
+        self.received as i128 - self.sent as i128
+    }
+}
+
+/// Bucket transfers by `period`, oldest bucket first. Self transfers are
+/// counted but don't affect the totals.
+pub fn aggregate(transfers: &[UsdcTransfer], period: Period) -> Vec<PeriodTotals> {
+    let mut buckets: BTreeMap<NaiveDate, PeriodTotals> = BTreeMap::new();
+
+    for transfer in transfers {
+        let start = period.truncate(transfer.timestamp.date_naive());
+        let bucket = buckets.entry(start).or_insert_with(|| PeriodTotals {
+            start,
+            ..Default::default()
+        });
+
+        bucket.count += 1;
+        match transfer.direction {
+            TransferDirection::Sent => bucket.sent += transfer.amount as u128,
+            TransferDirection::Received => bucket.received += transfer.amount as u128,
+            TransferDirection::SelfTransfer | TransferDirection::Mint | TransferDirection::Burn => {}
+        }
+    }
+
+    buckets.into_values().collect()
+}
+
+/// A payment of the same amount to the same counterparty on a regular cadence
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecurringPayment {
+    pub counterparty: String,
+    pub amount: u64,
+    pub occurrences: usize,
+    pub interval_days: f64, // Median gap between payments
+    pub last_paid: DateTime<Utc>,
+}
+
+/// Minimum number of payments before a series counts as recurring
+const MIN_OCCURRENCES: usize = 3;
+
+/// Find sent transfers that repeat with the same `(to, amount)` roughly every
+/// N days. A series is regular when every gap is within 20% (and at least two
+/// days) of the median gap, which tolerates weekends and month lengths.
+pub fn detect_recurring(transfers: &[UsdcTransfer]) -> Vec<RecurringPayment> {
+    let mut series: HashMap<(&str, u64), Vec<DateTime<Utc>>> = HashMap::new();
+
+    for transfer in transfers {
+        if let (TransferDirection::Sent, Some(to)) = (&transfer.direction, counterparty(transfer)) {
+            series.entry((to, transfer.amount)).or_default().push(transfer.timestamp);
+        }
+    }
+
+    let mut recurring: Vec<RecurringPayment> = series
+        .into_iter()
+        .filter(|(_, times)| times.len() >= MIN_OCCURRENCES)
+        .filter_map(|((to, amount), mut times)| {
+            times.sort();
+
+            let mut gaps: Vec<f64> = times
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0]).num_seconds() as f64 / 86_400.0)
+                .collect();
+            gaps.sort_by(|a, b| a.total_cmp(b));
+
+            let median = gaps[gaps.len() / 2];
+            if median < 1.0 {
+                return None;
+            }
+
+            let tolerance = (median * 0.2).max(2.0);
+            if gaps.iter().any(|gap| (gap - median).abs() > tolerance) {
+                return None;
+            }
+
+            Some(RecurringPayment {
+                counterparty: to.to_string(),
+                amount,
+                occurrences: times.len(),
+                interval_days: median,
+                last_paid: *times.last().expect("at least MIN_OCCURRENCES entries"),
+            })
+        })
+        .collect();
+
+    recurring.sort_by(|a, b| a.counterparty.cmp(&b.counterparty).then(a.amount.cmp(&b.amount)));
+    recurring
+}
+
+/// How sent amounts are matched against earlier receipts for --cost-basis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CostBasis {
+    /// Oldest lots are used up first
+    Fifo,
+}
+
+/// The part of a lot used up by one disposal
+#[derive(Debug, Clone, PartialEq)]
+pub struct LotUse {
+    pub signature: String, // Transfer that acquired the lot
+    pub acquired: DateTime<Utc>,
+    pub amount: u64,
+    pub cost_usd: Option<f64>, // Share of the lot's USD value; None if it wasn't priced
+}
+
+/// A sent (or burned) transfer matched against the lots it used up
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disposal {
+    pub signature: String,
+    pub timestamp: DateTime<Utc>,
+    pub amount: u64,
+    pub proceeds_usd: Option<f64>,
+    pub lots: Vec<LotUse>,
+    pub uncovered: u64, // Sent beyond every lot acquired in the window
+}
+
+impl Disposal {
+    /// Cost of the lots used, when every one of them was priced
+    pub fn cost_usd(&self) -> Option<f64> {
+        self.lots.iter().map(|lot| lot.cost_usd).sum()
+    }
+
+    /// Realized gain (negative for a loss); only known when the disposal and
+    /// all of its lots were priced and nothing was uncovered
+    pub fn gain_usd(&self) -> Option<f64> {
+        match (self.proceeds_usd, self.cost_usd()) {
+            (Some(proceeds), Some(cost)) if self.uncovered == 0 => Some(proceeds - cost),
+            _ => None,
+        }
+    }
+}
+
+/// A receipt not yet fully used up
+struct Lot {
+    signature: String,
+    acquired: DateTime<Utc>,
+    remaining: u64,
+    unit_cost: Option<f64>, // USD per raw unit
+}
+
+/// Match every disposal against the lots acquired before it, in the order
+/// `method` dictates. Receipts and mints acquire lots; sends and burns use
+/// them up. Transfers are taken in chronological order whatever the input
+/// order; failed and self transfers move nothing and are skipped.
+pub fn cost_basis(transfers: &[UsdcTransfer], method: CostBasis) -> Vec<Disposal> {
+    let mut ordered: Vec<&UsdcTransfer> = transfers.iter().filter(|transfer| transfer.balance_change() != 0).collect();
+    ordered.sort_by_key(|transfer| (transfer.timestamp, transfer.slot));
+
+    let unit_price = |transfer: &UsdcTransfer| transfer.usd_value.map(|value| value / transfer.amount as f64);
+
+    let mut lots: VecDeque<Lot> = VecDeque::new();
+    let mut disposals = Vec::new();
+
+    for transfer in ordered {
+        if transfer.balance_change() > 0 {
+            lots.push_back(Lot {
+                signature: transfer.signature.clone(),
+                acquired: transfer.timestamp,
+                remaining: transfer.amount,
+                unit_cost: unit_price(transfer),
+            });
+            continue;
+        }
+
+        let mut needed = transfer.amount;
+        let mut used = Vec::new();
+        while needed > 0 {
+            let lot = match method {
+                CostBasis::Fifo => lots.front_mut(),
+            };
+            let Some(lot) = lot else {
+                break;
+            };
+
+            let amount = needed.min(lot.remaining);
+            used.push(LotUse {
+                signature: lot.signature.clone(),
+                acquired: lot.acquired,
+                amount,
+                cost_usd: lot.unit_cost.map(|unit_cost| unit_cost * amount as f64),
+            });
+            lot.remaining -= amount;
+            needed -= amount;
+            if lot.remaining == 0 {
+                lots.pop_front();
+            }
+        }
+
+        disposals.push(Disposal {
+            signature: transfer.signature.clone(),
+            timestamp: transfer.timestamp,
+            amount: transfer.amount,
+            proceeds_usd: transfer.usd_value,
+            lots: used,
+            uncovered: needed,
+        });
+    }
+
+    disposals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarize_sums_past_u64_without_overflow() {
+        let half = u64::MAX / 2;
+        let transfers = vec![
+            UsdcTransfer::sample("a", TransferDirection::Received, half),
+            UsdcTransfer::sample("b", TransferDirection::Received, half),
+            UsdcTransfer::sample("c", TransferDirection::Received, half),
+            UsdcTransfer::sample("d", TransferDirection::Sent, half),
+        ];
+
+        let summary = summarize(&transfers, &MintFilter::default());
+
+        assert_eq!(summary.total_received, 3 * half as u128);
+        assert_eq!(summary.total_sent, half as u128);
+        assert_eq!(summary.net, 2 * half as i128);
+        assert_eq!(summary.by_direction.received, 3);
+        assert_eq!(summary.received_stats.unwrap().max, half);
+    }
+
+    #[test]
+    fn summarize_counts_only_the_filter_usdc_mints() {
+        let local_mint = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+        let transfers = vec![
+            UsdcTransfer::sample("a", TransferDirection::Received, 5),
+            UsdcTransfer {
+                mint: local_mint.to_string(),
+                ..UsdcTransfer::sample("b", TransferDirection::Received, 7)
+            },
+        ];
+
+        let default = summarize(&transfers, &MintFilter::default());
+        let overridden = summarize(
+            &transfers,
+            &MintFilter::default().with_usdc_mint(local_mint.parse().unwrap()),
+        );
+
+        assert_eq!((default.count, default.total_received), (1, 5));
+        assert_eq!((overridden.count, overridden.total_received), (2, 12));
+    }
+
+    #[test]
+    fn fifo_uses_the_oldest_lots_first() {
+        let at = |hours: i64| DateTime::from_timestamp(1_700_000_000 + hours * 3600, 0).unwrap();
+        let transfer = |signature: &str, direction, amount: u64, usd_value: f64, hours: i64| UsdcTransfer {
+            timestamp: at(hours),
+            usd_value: Some(usd_value),
+            ..UsdcTransfer::sample(signature, direction, amount)
+        };
+        // Listed out of order: lots follow the timestamps
+        let transfers = vec![
+            transfer("sell", TransferDirection::Sent, 150_000_000, 165.0, 2),
+            transfer("buy-1", TransferDirection::Received, 100_000_000, 100.0, 0),
+            transfer("buy-2", TransferDirection::Received, 100_000_000, 110.0, 1),
+        ];
+
+        let disposals = cost_basis(&transfers, CostBasis::Fifo);
+
+        assert_eq!(disposals.len(), 1);
+        let disposal = &disposals[0];
+        let lots: Vec<(&str, u64)> = disposal.lots.iter().map(|lot| (lot.signature.as_str(), lot.amount)).collect();
+        assert_eq!(lots, vec![("buy-1", 100_000_000), ("buy-2", 50_000_000)]);
+        assert_eq!(disposal.uncovered, 0);
+        assert!((disposal.cost_usd().unwrap() - 155.0).abs() < 1e-9);
+        assert!((disposal.gain_usd().unwrap() - 10.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,561 @@
+use chrono::{DateTime, Utc};
+use solana_account_decoder::parse_token::UiTokenAmount;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient, RpcClientConfig};
+use solana_client::rpc_config::{RpcBlockConfig, RpcTransactionConfig};
+use solana_client::rpc_request::TokenAccountsFilter;
+use solana_client::rpc_response::{RpcConfirmedTransactionStatusWithSignature, RpcKeyedAccount};
+use solana_rpc_client::http_sender::HttpSender;
+use solana_sdk::{
+    clock::{Slot, UnixTimestamp},
+    commitment_config::CommitmentConfig,
+    pubkey::Pubkey,
+    signature::Signature,
+};
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, UiConfirmedBlock};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::{IndexerError, IndexerResult};
+use crate::rate_limit::RateLimiter;
+
+/// The RPC calls `SolanaIndexer` depends on. Implemented by `RpcPool` for
+/// live endpoints; other implementations can serve responses offline.
+/// Providers are shared across worker threads by chunked backfills.
+pub trait RpcProvider: Send + Sync {
+    fn get_signatures_for_address_with_config(
+        &self,
+        address: &Pubkey,
+        config: GetConfirmedSignaturesForAddress2Config,
+    ) -> IndexerResult<Vec<RpcConfirmedTransactionStatusWithSignature>>;
+
+    fn get_transaction_with_config(
+        &self,
+        signature: &Signature,
+        config: RpcTransactionConfig,
+    ) -> IndexerResult<EncodedConfirmedTransactionWithStatusMeta>;
+
+    /// Fetch several transactions at once, one result per signature in the
+    /// same order. Providers that can't batch fetch them one by one; an error
+    /// for the whole call means the batch itself failed or was rejected.
+    fn get_transactions_with_config(
+        &self,
+        signatures: &[Signature],
+        config: RpcTransactionConfig,
+    ) -> IndexerResult<Vec<IndexerResult<EncodedConfirmedTransactionWithStatusMeta>>> {
+        Ok(signatures
+            .iter()
+            .map(|signature| self.get_transaction_with_config(signature, config))
+            .collect())
+    }
+
+    fn get_block_time(&self, slot: Slot) -> IndexerResult<UnixTimestamp>;
+
+    fn get_block_with_config(&self, slot: Slot, config: RpcBlockConfig) -> IndexerResult<UiConfirmedBlock>;
+
+    /// Token accounts owned by `owner` that hold `mint`
+    fn get_token_accounts_by_owner(&self, owner: &Pubkey, mint: &Pubkey) -> IndexerResult<Vec<RpcKeyedAccount>>;
+
+    fn get_token_account_balance(&self, account: &Pubkey) -> IndexerResult<UiTokenAmount>;
+
+    /// Whether `signature` is known to the cluster, searching its whole
+    /// history rather than just the recent status cache. A signature whose
+    /// block was dropped by a fork reads as unknown.
+    fn signature_exists(&self, signature: &Signature) -> IndexerResult<bool>;
+
+    /// Human-readable name of whatever served the most recent call
+    fn last_endpoint(&self) -> &str;
+
+    /// Reference point for time windows. Live providers use the wall clock;
+    /// replayed fixtures use the time they were recorded.
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+impl<T: RpcProvider + ?Sized> RpcProvider for Box<T> {
+    fn get_signatures_for_address_with_config(
+        &self,
+        address: &Pubkey,
+        config: GetConfirmedSignaturesForAddress2Config,
+    ) -> IndexerResult<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        (**self).get_signatures_for_address_with_config(address, config)
+    }
+
+    fn get_transaction_with_config(
+        &self,
+        signature: &Signature,
+        config: RpcTransactionConfig,
+    ) -> IndexerResult<EncodedConfirmedTransactionWithStatusMeta> {
+        (**self).get_transaction_with_config(signature, config)
+    }
+
+    fn get_transactions_with_config(
+        &self,
+        signatures: &[Signature],
+        config: RpcTransactionConfig,
+    ) -> IndexerResult<Vec<IndexerResult<EncodedConfirmedTransactionWithStatusMeta>>> {
+        (**self).get_transactions_with_config(signatures, config)
+    }
+
+    fn get_block_time(&self, slot: Slot) -> IndexerResult<UnixTimestamp> {
+        (**self).get_block_time(slot)
+    }
+
+    fn get_block_with_config(&self, slot: Slot, config: RpcBlockConfig) -> IndexerResult<UiConfirmedBlock> {
+        (**self).get_block_with_config(slot, config)
+    }
+
+    fn get_token_accounts_by_owner(&self, owner: &Pubkey, mint: &Pubkey) -> IndexerResult<Vec<RpcKeyedAccount>> {
+        (**self).get_token_accounts_by_owner(owner, mint)
+    }
+
+    fn get_token_account_balance(&self, account: &Pubkey) -> IndexerResult<UiTokenAmount> {
+        (**self).get_token_account_balance(account)
+    }
+
+    fn signature_exists(&self, signature: &Signature) -> IndexerResult<bool> {
+        (**self).signature_exists(signature)
+    }
+
+    fn last_endpoint(&self) -> &str {
+        (**self).last_endpoint()
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        (**self).now()
+    }
+}
+
+/// Rounds of backoff after every endpoint answered 429, and the first delay
+const RATE_LIMIT_RETRIES: u32 = 3;
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(2);
+
+/// How long the startup probe waits for each endpoint
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Extra attempts for a request that timed out on every endpoint
+const TIMEOUT_RETRIES: u32 = 2;
+
+/// A set of RPC endpoints used round-robin. A call that fails with a
+/// transient error (network, HTTP status, node-side RPC error) is retried on
+/// the next endpoint before giving up.
+pub struct RpcPool {
+    endpoints: Vec<Endpoint>,
+    next: AtomicUsize,
+    last_served: AtomicUsize,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    headers: HeaderMap,
+    timeout: Duration,
+}
+
+struct Endpoint {
+    url: String,
+    client: Arc<RpcClient>,
+    http: reqwest::Client, // The client's own transport, for requests it can't make
+}
+
+impl RpcPool {
+    /// `headers` are sent with every request, e.g. for providers that
+    /// authenticate with an API key header. A request still unanswered after
+    /// `timeout`, however it hangs, fails over like any other transient error.
+    pub fn new(rpc_urls: &[String], headers: HeaderMap, timeout: Duration) -> IndexerResult<Self> {
+        if rpc_urls.is_empty() {
+            return Err(IndexerError::Rpc("At least one RPC endpoint is required".to_string()));
+        }
+
+        for url in rpc_urls {
+            check_url(url, &["http", "https"])?;
+        }
+
+        let endpoints = rpc_urls
+            .iter()
+            .map(|url| {
+                let http = build_http(url, &headers, timeout)?;
+                Ok(Endpoint {
+                    url: url.clone(),
+                    client: Arc::new(build_client(url, http.clone())),
+                    http,
+                })
+            })
+            .collect::<IndexerResult<_>>()?;
+
+        Ok(Self {
+            endpoints,
+            next: AtomicUsize::new(0),
+            last_served: AtomicUsize::new(0),
+            rate_limiter: None,
+            headers,
+            timeout,
+        })
+    }
+
+    /// Throttle every request (including failover attempts) through `limiter`
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Run `f` against the endpoints, backing off when every one of them is
+    /// rate limiting us and retrying when every one of them timed out.
+    ///
+    /// `Retry-After` is already honored one layer down: solana_client's
+    /// `HttpSender` sleeps for the header's value (when under 120s) and retries
+    /// a 429 up to five times. Only once those are used up does the 429 reach
+    /// us, as a `reqwest::Error` that keeps the status but drops the response
+    /// headers. With no header left to read, back off exponentially here.
+    ///
+    /// The requests and the backoff both block, so the loop runs under
+    /// `block_in_place` to keep the runtime's other tasks moving.
+    pub fn call<T, F>(&self, f: F) -> IndexerResult<T>
+    where
+        T: Send + 'static,
+        F: Fn(&RpcClient) -> Result<T, ClientError> + Send + Sync + 'static,
+    {
+        let f = Arc::new(f);
+        let mut backoff = RATE_LIMIT_BACKOFF;
+        let mut rate_limit_retries = 0;
+        let mut timeout_retries = 0;
+
+        tokio::task::block_in_place(|| loop {
+            match self.call_once(&f) {
+                Err(IndexerError::RateLimited(message)) if rate_limit_retries < RATE_LIMIT_RETRIES => {
+                    println!("⏳ Rate limited ({}), backing off for {}s", message, backoff.as_secs());
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                    rate_limit_retries += 1;
+                }
+                Err(IndexerError::Timeout(message)) if timeout_retries < TIMEOUT_RETRIES => {
+                    println!("⏱️ RPC request timed out ({}), retrying", message);
+                    timeout_retries += 1;
+                }
+                result => return result,
+            }
+        })
+    }
+
+    /// Run `f` against the next endpoint in rotation, failing over to the
+    /// others on transient errors
+    fn call_once<T, F>(&self, f: &Arc<F>) -> IndexerResult<T>
+    where
+        T: Send + 'static,
+        F: Fn(&RpcClient) -> Result<T, ClientError> + Send + Sync + 'static,
+    {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let count = self.endpoints.len();
+        let mut last_error = None;
+
+        for attempt in 0..count {
+            let index = (start + attempt) % count;
+            let Endpoint { url, client, .. } = &self.endpoints[index];
+
+            if let Some(limiter) = &self.rate_limiter {
+                limiter.acquire();
+            }
+
+            crate::metrics::metrics().rpc_requests.inc();
+            let (client, f) = (Arc::clone(client), Arc::clone(f));
+            let result = with_timeout(url, self.timeout, move || f(&client));
+            if !matches!(result, Ok(Ok(_))) {
+                crate::metrics::metrics().rpc_errors.inc();
+            }
+
+            match result {
+                Ok(Ok(value)) => {
+                    self.last_served.store(index, Ordering::Relaxed);
+                    return Ok(value);
+                }
+                Ok(Err(e)) if is_transient(&e) && attempt + 1 < count => {
+                    println!("⚠️ RPC endpoint {} failed ({}), trying next endpoint", url, e);
+                    last_error = Some(e.into());
+                }
+                Err(e @ IndexerError::Timeout(_)) if attempt + 1 < count => {
+                    println!("⚠️ RPC endpoint {} failed ({}), trying next endpoint", url, e);
+                    last_error = Some(e);
+                }
+                Ok(Err(e)) => return Err(e.into()),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| IndexerError::Rpc("No RPC endpoint available".to_string())))
+    }
+
+    /// Ask every endpoint for its version so an unreachable or mistyped one
+    /// fails before indexing starts rather than deep into the first page.
+    /// Returns each endpoint's genesis hash, which identifies its cluster;
+    /// `None` if the endpoint wouldn't say.
+    pub fn check_health(&self) -> IndexerResult<Vec<(String, Option<String>)>> {
+        let mut genesis_hashes = Vec::new();
+
+        for Endpoint { url, .. } in &self.endpoints {
+            let probe = Arc::new(build_client(url, build_http(url, &self.headers, HEALTH_CHECK_TIMEOUT)?));
+            let version = Arc::clone(&probe);
+            let version = with_timeout(url, HEALTH_CHECK_TIMEOUT, move || version.get_version())
+                .map_err(|e| e.to_string())
+                .and_then(|version| version.map_err(|e| e.to_string()));
+            match version {
+                Ok(version) => println!("✅ RPC endpoint {} is up (solana-core {})", url, version.solana_core),
+                Err(e) => {
+                    return Err(IndexerError::Rpc(format!(
+                        "RPC endpoint {} is unreachable: {} (use --skip-health-check to bypass)",
+                        url, e
+                    )))
+                }
+            }
+
+            let genesis_hash = with_timeout(url, HEALTH_CHECK_TIMEOUT, move || probe.get_genesis_hash())
+                .ok()
+                .and_then(Result::ok)
+                .map(|hash| hash.to_string());
+            genesis_hashes.push((url.clone(), genesis_hash));
+        }
+        Ok(genesis_hashes)
+    }
+
+    /// Genesis hash of the cluster the endpoints serve
+    pub fn genesis_hash(&self) -> IndexerResult<String> {
+        self.call(|client| client.get_genesis_hash()).map(|hash| hash.to_string())
+    }
+}
+
+impl RpcProvider for RpcPool {
+    fn get_signatures_for_address_with_config(
+        &self,
+        address: &Pubkey,
+        config: GetConfirmedSignaturesForAddress2Config,
+    ) -> IndexerResult<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        let address = *address;
+        self.call(move |client| {
+            client.get_signatures_for_address_with_config(
+                &address,
+                GetConfirmedSignaturesForAddress2Config {
+                    before: config.before,
+                    until: config.until,
+                    limit: config.limit,
+                    commitment: config.commitment,
+                },
+            )
+        })
+    }
+
+    fn get_transaction_with_config(
+        &self,
+        signature: &Signature,
+        config: RpcTransactionConfig,
+    ) -> IndexerResult<EncodedConfirmedTransactionWithStatusMeta> {
+        let signature = *signature;
+        self.call(move |client| client.get_transaction_with_config(&signature, config))
+    }
+
+    /// Sends one JSON-RPC batch to the next endpoint in rotation. There is no
+    /// failover or backoff here: callers fall back to single requests, which
+    /// have both, when the batch fails.
+    fn get_transactions_with_config(
+        &self,
+        signatures: &[Signature],
+        config: RpcTransactionConfig,
+    ) -> IndexerResult<Vec<IndexerResult<EncodedConfirmedTransactionWithStatusMeta>>> {
+        let requests: Vec<Value> = signatures
+            .iter()
+            .enumerate()
+            .map(|(id, signature)| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "method": "getTransaction",
+                    "params": [signature.to_string(), config],
+                })
+            })
+            .collect();
+
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.endpoints.len();
+        let Endpoint { url, http, .. } = &self.endpoints[index];
+
+        if let Some(limiter) = &self.rate_limiter {
+            // Providers meter each call in a batch, not the batch
+            for _ in signatures {
+                limiter.acquire();
+            }
+        }
+
+        // The trait is blocking like RpcClient's, so do what RpcClient does
+        let handle = tokio::runtime::Handle::try_current()
+            .map_err(|e| IndexerError::Rpc(format!("Batch requests need a Tokio runtime: {}", e)))?;
+        crate::metrics::metrics().rpc_requests.inc_by(signatures.len() as u64);
+        let response = tokio::task::block_in_place(|| {
+            handle.block_on(tokio::time::timeout(self.timeout, async {
+                http.post(url).json(&requests).send().await?.error_for_status()?.json::<Value>().await
+            }))
+        });
+        let response = match response {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => {
+                crate::metrics::metrics().rpc_errors.inc();
+                return Err(IndexerError::from(ClientError::from(e)));
+            }
+            Err(_) => {
+                crate::metrics::metrics().rpc_errors.inc();
+                return Err(timed_out(url, self.timeout));
+            }
+        };
+
+        // Providers without batch support answer with a single error object
+        let Value::Array(responses) = response else {
+            crate::metrics::metrics().rpc_errors.inc();
+            return Err(IndexerError::Rpc(format!("{} rejected a batch request: {}", url, response)));
+        };
+        self.last_served.store(index, Ordering::Relaxed);
+
+        let mut by_id: HashMap<u64, Value> = responses
+            .into_iter()
+            .filter_map(|response| Some((response.get("id")?.as_u64()?, response)))
+            .collect();
+
+        Ok((0..signatures.len() as u64)
+            .map(|id| {
+                let mut response = by_id
+                    .remove(&id)
+                    .ok_or_else(|| IndexerError::Rpc("Batch response is missing a transaction".to_string()))?;
+                if let Some(error) = response.get("error") {
+                    return Err(IndexerError::Rpc(error.to_string()));
+                }
+                match response["result"].take() {
+                    Value::Null => Err(IndexerError::Rpc("Transaction not found".to_string())),
+                    result => Ok(serde_json::from_value(result)?),
+                }
+            })
+            .collect())
+    }
+
+    fn get_block_time(&self, slot: Slot) -> IndexerResult<UnixTimestamp> {
+        self.call(move |client| client.get_block_time(slot))
+    }
+
+    fn get_block_with_config(&self, slot: Slot, config: RpcBlockConfig) -> IndexerResult<UiConfirmedBlock> {
+        self.call(move |client| client.get_block_with_config(slot, config))
+    }
+
+    fn get_token_accounts_by_owner(&self, owner: &Pubkey, mint: &Pubkey) -> IndexerResult<Vec<RpcKeyedAccount>> {
+        let (owner, mint) = (*owner, *mint);
+        self.call(move |client| client.get_token_accounts_by_owner(&owner, TokenAccountsFilter::Mint(mint)))
+    }
+
+    fn get_token_account_balance(&self, account: &Pubkey) -> IndexerResult<UiTokenAmount> {
+        let account = *account;
+        self.call(move |client| client.get_token_account_balance(&account))
+    }
+
+    fn signature_exists(&self, signature: &Signature) -> IndexerResult<bool> {
+        let signature = *signature;
+        let status = self.call(move |client| {
+            client.get_signature_status_with_commitment_and_history(&signature, client.commitment(), true)
+        })?;
+        Ok(status.is_some())
+    }
+
+    /// URL of the endpoint that served the most recent successful call
+    fn last_endpoint(&self) -> &str {
+        &self.endpoints[self.last_served.load(Ordering::Relaxed)].url
+    }
+}
+
+/// An HTTP client for `url` whose requests carry `headers` on top of the
+/// ones solana_client always sends
+fn build_http(url: &str, headers: &HeaderMap, timeout: Duration) -> IndexerResult<reqwest::Client> {
+    let mut all_headers = HttpSender::default_headers();
+    all_headers.extend(headers.clone());
+
+    reqwest::Client::builder()
+        .default_headers(all_headers)
+        .timeout(timeout)
+        .pool_idle_timeout(timeout)
+        .build()
+        .map_err(|e| IndexerError::Rpc(format!("Cannot build HTTP client for {}: {}", url, e)))
+}
+
+fn build_client(url: &str, http: reqwest::Client) -> RpcClient {
+    RpcClient::new_sender(
+        HttpSender::new_with_client(url, http),
+        RpcClientConfig::with_commitment(CommitmentConfig::confirmed()),
+    )
+}
+
+/// `--rpc-header` value parser for `Key: Value`
+pub fn parse_header(value: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, header_value) = value
+        .split_once(':')
+        .ok_or_else(|| format!("expected 'Key: Value', got '{}'", value))?;
+    let name = HeaderName::from_str(name.trim()).map_err(|e| format!("invalid header name '{}': {}", name.trim(), e))?;
+    let header_value = HeaderValue::from_str(header_value.trim()).map_err(|e| format!("invalid value for header {}: {}", name, e))?;
+    Ok((name, header_value))
+}
+
+/// Reject anything that isn't a URL with one of `schemes`, so a typo is
+/// reported up front instead of as a connection error mid-run
+pub fn check_url(url: &str, schemes: &[&str]) -> IndexerResult<()> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| IndexerError::Rpc(format!("Invalid URL '{}': {}", url, e)))?;
+    if !schemes.contains(&parsed.scheme()) {
+        return Err(IndexerError::Rpc(format!(
+            "Invalid URL '{}': expected a {} URL",
+            url,
+            schemes.join("/")
+        )));
+    }
+    Ok(())
+}
+
+/// Run the blocking `request` on the runtime's blocking pool and stop
+/// waiting after `timeout`. reqwest's own timer doesn't cover everything (a
+/// hung DNS lookup or TLS handshake), this does; an abandoned request is left
+/// to finish in the background. The outer error is the timeout.
+fn with_timeout<T: Send + 'static>(
+    url: &str,
+    timeout: Duration,
+    request: impl FnOnce() -> Result<T, ClientError> + Send + 'static,
+) -> IndexerResult<Result<T, ClientError>> {
+    let handle = tokio::runtime::Handle::try_current()
+        .map_err(|e| IndexerError::Rpc(format!("RPC requests need a Tokio runtime: {}", e)))?;
+
+    let answer = tokio::task::block_in_place(|| {
+        handle.block_on(tokio::time::timeout(timeout, tokio::task::spawn_blocking(request)))
+    });
+    match answer {
+        Ok(Ok(result)) => Ok(result),
+        Ok(Err(e)) => Err(IndexerError::Rpc(format!("Request to {} did not complete: {}", url, e))),
+        Err(_) => Err(timed_out(url, timeout)),
+    }
+}
+
+fn timed_out(url: &str, timeout: Duration) -> IndexerError {
+    IndexerError::Timeout(format!("no answer from {} within {}s", url, timeout.as_secs()))
+}
+
+fn is_transient(error: &ClientError) -> bool {
+    matches!(
+        error.kind(),
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) | ClientErrorKind::RpcError(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn gives_up_on_a_request_that_never_answers() {
+        let hung = with_timeout("http://hung", Duration::from_millis(50), || {
+            std::thread::sleep(Duration::from_millis(500));
+            Ok(())
+        });
+        assert!(matches!(hung, Err(IndexerError::Timeout(_))));
+
+        let answered = with_timeout("http://up", Duration::from_secs(2), || Ok(7));
+        assert!(matches!(answered, Ok(Ok(7))));
+    }
+}
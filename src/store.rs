@@ -0,0 +1,196 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rusqlite::types::ToSql;
+use rusqlite::{params, Connection, Row};
+use serde::de::DeserializeOwned;
+use std::path::Path;
+
+use crate::transfer::{TransferDirection, UsdcTransfer};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transfers (
+    signature    TEXT    NOT NULL,
+    timestamp    TEXT    NOT NULL,
+    slot         INTEGER NOT NULL,
+    mint         TEXT    NOT NULL,
+    amount       INTEGER NOT NULL,
+    decimals     INTEGER NOT NULL,
+    direction    TEXT    NOT NULL,
+    from_owner   TEXT    NOT NULL,
+    to_owner     TEXT    NOT NULL,
+    fee_lamports INTEGER NOT NULL,
+    usd_value    REAL,
+    memo         TEXT,
+    kind         TEXT    NOT NULL,
+    counter_mint TEXT,
+    from_token_account TEXT,
+    to_token_account   TEXT,
+    via_program        TEXT,
+    authority          TEXT,
+    UNIQUE (signature, from_owner, to_owner, amount, direction)
+);
+CREATE INDEX IF NOT EXISTS transfers_timestamp ON transfers (timestamp);
+";
+
+/// Which stored transfers `TransferStore::query` returns; unset fields match all
+#[derive(Debug, Default)]
+pub struct TransferFilter {
+    pub from: Option<DateTime<Utc>>, // Inclusive
+    pub to: Option<DateTime<Utc>>,   // Exclusive
+    pub direction: Option<TransferDirection>,
+    pub counterparty: Option<String>,
+    pub min_amount: Option<u64>, // Raw units
+}
+
+/// Transfers persisted in SQLite, one row per leg, keyed like `UsdcTransfer::key`
+pub struct TransferStore {
+    conn: Connection,
+}
+
+impl TransferStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+
+        // Databases created before these were recorded lack their columns
+        for column in ["from_token_account", "to_token_account", "via_program", "authority"] {
+            let exists: bool = conn.query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('transfers') WHERE name = ?1",
+                [column],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                conn.execute_batch(&format!("ALTER TABLE transfers ADD COLUMN {} TEXT", column))?;
+            }
+        }
+
+        Ok(Self { conn })
+    }
+
+    pub fn begin(&self) -> Result<()> {
+        self.conn.execute_batch("BEGIN")?;
+        Ok(())
+    }
+
+    pub fn commit(&self) -> Result<()> {
+        self.conn.execute_batch("COMMIT")?;
+        Ok(())
+    }
+
+    /// Store `transfer`; returns false if it was already stored
+    pub fn insert(&self, transfer: &UsdcTransfer) -> Result<bool> {
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO transfers (
+                signature, timestamp, slot, mint, amount, decimals, direction,
+                from_owner, to_owner, fee_lamports, usd_value, memo, kind, counter_mint,
+                from_token_account, to_token_account, via_program, authority
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+            params![
+                transfer.signature,
+                transfer.timestamp.to_rfc3339(),
+                transfer.slot,
+                transfer.mint,
+                transfer.amount,
+                transfer.decimals,
+                format!("{:?}", transfer.direction),
+                transfer.from,
+                transfer.to,
+                transfer.fee_lamports,
+                transfer.usd_value,
+                transfer.memo,
+                format!("{:?}", transfer.kind),
+                transfer.counter_mint,
+                transfer.from_token_account,
+                transfer.to_token_account,
+                transfer.via_program,
+                transfer.authority,
+            ],
+        )?;
+
+        Ok(inserted > 0)
+    }
+
+    /// Stored transfers matching `filter`, newest first
+    pub fn query(&self, filter: &TransferFilter) -> Result<Vec<UsdcTransfer>> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<Box<dyn ToSql>> = Vec::new();
+
+        // Timestamps are stored as RFC 3339 in UTC, so they compare as text
+        if let Some(from) = filter.from {
+            conditions.push("timestamp >= ?");
+            values.push(Box::new(from.to_rfc3339()));
+        }
+        if let Some(to) = filter.to {
+            conditions.push("timestamp < ?");
+            values.push(Box::new(to.to_rfc3339()));
+        }
+        if let Some(direction) = filter.direction {
+            conditions.push("direction = ?");
+            values.push(Box::new(format!("{:?}", direction)));
+        }
+        if let Some(counterparty) = &filter.counterparty {
+            conditions.push("(from_owner = ? OR to_owner = ?)");
+            values.push(Box::new(counterparty.clone()));
+            values.push(Box::new(counterparty.clone()));
+        }
+        if let Some(min_amount) = filter.min_amount {
+            conditions.push("amount >= ?");
+            values.push(Box::new(min_amount));
+        }
+
+        let mut sql = "SELECT signature, timestamp, slot, mint, amount, decimals, direction, from_owner, \
+                       to_owner, fee_lamports, usd_value, memo, kind, counter_mint, from_token_account, \
+                       to_token_account, via_program, authority FROM transfers"
+            .to_string();
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY timestamp DESC");
+
+        let mut statement = self.conn.prepare(&sql)?;
+        let params: Vec<&dyn ToSql> = values.iter().map(|value| value.as_ref()).collect();
+        let rows = statement.query_map(params.as_slice(), |row| Ok(read_row(row)))?;
+
+        let mut transfers = Vec::new();
+        for row in rows {
+            transfers.push(row??);
+        }
+        Ok(transfers)
+    }
+}
+
+fn read_row(row: &Row) -> Result<UsdcTransfer> {
+    let timestamp: String = row.get(1)?;
+
+    Ok(UsdcTransfer {
+        signature: row.get(0)?,
+        timestamp: DateTime::parse_from_rfc3339(&timestamp)?.with_timezone(&Utc),
+        slot: row.get(2)?,
+        mint: row.get(3)?,
+        amount: row.get(4)?,
+        decimals: row.get(5)?,
+        direction: parse_variant(&row.get::<_, String>(6)?)?,
+        from: row.get(7)?,
+        to: row.get(8)?,
+        from_token_account: row.get(14)?,
+        to_token_account: row.get(15)?,
+        via_program: row.get(16)?,
+        authority: row.get(17)?,
+        fee_lamports: row.get(9)?,
+        usd_value: row.get(10)?,
+        memo: row.get(11)?,
+        kind: parse_variant(&row.get::<_, String>(12)?)?,
+        counter_mint: row.get(13)?,
+        explorer_url: None,
+        balance_after: None,
+        failed: false,
+        anomaly: false,
+        denied: false,
+    })
+}
+
+/// Enums are stored by variant name, which is also their serde name
+fn parse_variant<T: DeserializeOwned>(name: &str) -> Result<T> {
+    Ok(serde_json::from_value(serde_json::Value::String(name.to_string()))?)
+}
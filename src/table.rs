@@ -0,0 +1,97 @@
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, CellAlignment, Color, ContentArrangement, Table};
+
+use crate::tokens::TokenRegistry;
+use crate::transfer::{TransactionKind, TransferDirection, UsdcTransfer};
+use crate::utils::format_amount;
+
+/// Whether to color table output: only on a terminal, and never when the
+/// `NO_COLOR` convention (https://no-color.org) asks not to
+pub fn use_color(is_terminal: bool) -> bool {
+    is_terminal && std::env::var_os("NO_COLOR").map_or(true, |value| value.is_empty())
+}
+
+/// Shorten `address` to `width` characters plus an ellipsis; 0 keeps it whole
+fn truncate(address: &str, width: usize) -> String {
+    if width == 0 || address.chars().count() <= width {
+        address.to_string()
+    } else {
+        format!("{}…", address.chars().take(width).collect::<String>())
+    }
+}
+
+/// Render transfers as an aligned table with right-aligned amounts
+/// `places` caps the decimals shown, like --decimals-display
+pub fn render(transfers: &[UsdcTransfer], tokens: &TokenRegistry, places: Option<u8>, address_width: usize, color: bool) -> String {
+    // Running balances are only known when they were asked for
+    let with_balance = transfers.iter().any(|transfer| transfer.balance_after.is_some());
+
+    let mut header = vec!["Time (UTC)", "Direction", "Amount", "Token", "Counterparty", "Signature", "Note"];
+    if with_balance {
+        header.push("Balance");
+    }
+
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(header);
+
+    for index in [Some(2), with_balance.then_some(7)].into_iter().flatten() {
+        if let Some(column) = table.column_mut(index) {
+            column.set_cell_alignment(CellAlignment::Right);
+        }
+    }
+
+    for transfer in transfers {
+        let (direction, counterparty, direction_color) = match transfer.direction {
+            TransferDirection::Sent => ("Sent", truncate(&transfer.to, address_width), Color::Red),
+            TransferDirection::Received => ("Received", truncate(&transfer.from, address_width), Color::Green),
+            TransferDirection::SelfTransfer => ("Self", String::new(), Color::Yellow),
+            TransferDirection::Mint => ("Mint", truncate(&transfer.from, address_width), Color::Cyan),
+            TransferDirection::Burn => ("Burn", String::new(), Color::Magenta),
+        };
+
+        let note = match (&transfer.kind, &transfer.counter_mint) {
+            (TransactionKind::Swap, Some(mint)) => format!("Swap ↔ {}", tokens.symbol(mint)),
+            (TransactionKind::Unknown, _) => "Unmatched".to_string(),
+            _ => transfer.memo.clone().unwrap_or_default(),
+        };
+        let flags = [(transfer.anomaly, "Anomaly"), (transfer.failed, "Failed tx"), (transfer.denied, "Denied")];
+        let note = flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, flag)| flag.to_string())
+            .chain(Some(note).filter(|note| !note.is_empty()))
+            .collect::<Vec<_>>()
+            .join(" · ");
+
+        let mut direction_cell = Cell::new(direction);
+        if color {
+            direction_cell = direction_cell.fg(direction_color);
+        }
+
+        let mut row = vec![
+            Cell::new(transfer.timestamp.format("%Y-%m-%d %H:%M:%S")),
+            direction_cell,
+            Cell::new(format_amount(transfer.amount as i128, transfer.decimals, places)),
+            Cell::new(tokens.symbol(&transfer.mint)),
+            Cell::new(counterparty),
+            Cell::new(truncate(&transfer.signature, address_width)),
+            Cell::new(note),
+        ];
+        if with_balance {
+            let balance = transfer.balance_after.map(|balance| format_amount(balance as i128, transfer.decimals, places));
+            row.push(Cell::new(balance.unwrap_or_default()));
+        }
+        table.add_row(row);
+    }
+
+    if color {
+        table.enforce_styling();
+    } else {
+        table.force_no_tty();
+    }
+
+    table.to_string()
+}
@@ -0,0 +1,103 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::mints::MintFilter;
+use crate::utils::short_address;
+
+/// Display metadata for a mint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenInfo {
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+/// Well-known mints, so common tokens display nicely without a token list
+const BUNDLED_TOKENS: &[(&str, &str, u8)] = &[
+    ("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB", "USDT", 6),
+    ("So11111111111111111111111111111111111111112", "wSOL", 9),
+    ("mSoLzYCxHdYgdzU16g5QSh3i5K3z3KZK7ytfqcJm7So", "mSOL", 9),
+    ("JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN", "JUP", 6),
+    ("DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263", "Bonk", 5),
+    ("2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo", "PYUSD", 6),
+];
+
+/// The subset of the Solana token list format (`solana.tokenlist.json`) we read
+#[derive(Deserialize)]
+struct TokenList {
+    tokens: Vec<TokenListEntry>,
+}
+
+#[derive(Deserialize)]
+struct TokenListEntry {
+    address: String,
+    symbol: String,
+    decimals: u8,
+}
+
+/// Mint metadata keyed by mint address: the bundled tokens, plus any token
+/// list loaded at startup
+#[derive(Debug)]
+pub struct TokenRegistry {
+    tokens: HashMap<String, TokenInfo>,
+}
+
+impl TokenRegistry {
+    /// The bundled tokens, with every mint `mints` treats as USDC labelled so
+    pub fn bundled(mints: &MintFilter) -> Self {
+        let mut tokens = HashMap::new();
+
+        for mint in mints.usdc_mints() {
+            tokens.insert(
+                mint.to_string(),
+                TokenInfo {
+                    symbol: "USDC".to_string(),
+                    decimals: mints.decimals(&mint.to_string()),
+                },
+            );
+        }
+
+        for (mint, symbol, decimals) in BUNDLED_TOKENS {
+            tokens.insert(
+                mint.to_string(),
+                TokenInfo {
+                    symbol: symbol.to_string(),
+                    decimals: *decimals,
+                },
+            );
+        }
+
+        Self { tokens }
+    }
+
+    /// Add every token from a token list file. Bundled entries win, so a stale
+    /// list can't relabel USDC.
+    pub fn with_token_list(mut self, path: &Path) -> Result<Self> {
+        let list: TokenList = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+
+        for entry in list.tokens {
+            self.tokens.entry(entry.address).or_insert(TokenInfo {
+                symbol: entry.symbol,
+                decimals: entry.decimals,
+            });
+        }
+
+        Ok(self)
+    }
+
+    pub fn get(&self, mint: &str) -> Option<&TokenInfo> {
+        self.tokens.get(mint)
+    }
+
+    /// The mint's symbol, or its shortened address when it isn't known
+    pub fn symbol<'a>(&'a self, mint: &'a str) -> &'a str {
+        self.get(mint).map_or_else(|| short_address(mint), |info| info.symbol.as_str())
+    }
+}
+
+impl Default for TokenRegistry {
+    fn default() -> Self {
+        Self::bundled(&MintFilter::default())
+    }
+}
@@ -1,26 +1,310 @@
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::checkpoint::Checkpoint;
+use crate::metrics::Timings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema, clap::ValueEnum)]
 pub enum TransferDirection {
     Sent,
     Received,
+    /// Between two token accounts owned by the wallet; no net effect
+    SelfTransfer,
+    /// Issued into the wallet by the mint authority
+    Mint,
+    /// Destroyed from the wallet's balance
+    Burn,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// What the transaction carrying a transfer was doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum TransactionKind {
+    /// A plain payment
+    Transfer,
+    /// USDC traded against another mint in the same wallet
+    Swap,
+    /// The wallet's USDC changed but no counterparty leg could be matched
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct UsdcTransfer {
     pub signature: String,
     pub timestamp: DateTime<Utc>,
-    pub amount: u64, // Raw amount (multiply by 10^-6 for USDC)
+    pub mint: String,
+    /// Raw amount (multiply by 10^-decimals)
+    pub amount: u64,
+    pub decimals: u8,
     pub direction: TransferDirection,
     pub from: String,
     pub to: String,
+    /// Token account debited, when known
+    #[serde(default)]
+    pub from_token_account: Option<String>,
+    /// Token account credited, when known
+    #[serde(default)]
+    pub to_token_account: Option<String>,
+    /// Program that moved the tokens through CPI; None for direct transfers
+    #[serde(default)]
+    pub via_program: Option<String>,
+    /// Signer of the transfer (owner, delegate or multisig), from its parsed instruction
+    #[serde(default)]
+    pub authority: Option<String>,
+    pub slot: u64,
+    /// Fee of the whole transaction, paid by its fee payer
+    pub fee_lamports: u64,
+    /// Value in USD; only set when a price source is configured
+    pub usd_value: Option<f64>,
+    pub memo: Option<String>,
+    pub kind: TransactionKind,
+    /// The other side of a swap
+    pub counter_mint: Option<String>,
+    /// Transaction link; not stored in the database
+    #[serde(default)]
+    pub explorer_url: Option<String>,
+    /// Wallet balance after this transfer, with --running-balance
+    #[serde(default)]
+    pub balance_after: Option<u64>,
+    /// From a transaction that failed on-chain, kept with --include-failed
+    #[serde(default)]
+    pub failed: bool,
+    /// Unusually large, per --anomaly-sigma or --anomaly-threshold
+    #[serde(default)]
+    pub anomaly: bool,
+    /// From or to an address on --deny-file
+    #[serde(default)]
+    pub denied: bool,
+}
+
+/// Identifies one transfer leg across pagination batches and runs
+pub type TransferKey = (String, String, String, u64, TransferDirection);
+
+impl UsdcTransfer {
+    /// Effect of this transfer on the wallet's balance; none if its
+    /// transaction failed
+    pub fn balance_change(&self) -> i128 {
+        if self.failed {
+            return 0;
+        }
+        match self.direction {
+            TransferDirection::Received | TransferDirection::Mint => self.amount as i128,
+            TransferDirection::Sent | TransferDirection::Burn => -(self.amount as i128),
+            TransferDirection::SelfTransfer => 0,
+        }
+    }
+
+    /// The wallet's side of this transfer: the token accounts it was debited
+    /// from or credited to, where known
+    pub fn wallet_token_accounts(&self) -> Vec<&str> {
+        let accounts = match self.direction {
+            TransferDirection::Sent | TransferDirection::Burn => [self.from_token_account.as_deref(), None],
+            TransferDirection::Received | TransferDirection::Mint => [self.to_token_account.as_deref(), None],
+            TransferDirection::SelfTransfer => [self.from_token_account.as_deref(), self.to_token_account.as_deref()],
+        };
+        accounts.into_iter().flatten().collect()
+    }
+
+    pub fn key(&self) -> TransferKey {
+        (
+            self.signature.clone(),
+            self.from.clone(),
+            self.to.clone(),
+            self.amount,
+            self.direction,
+        )
+    }
+}
+
+#[cfg(test)]
+impl UsdcTransfer {
+    /// A USDC transfer between placeholder parties, for tests to adjust
+    pub fn sample(signature: &str, direction: TransferDirection, amount: u64) -> Self {
+        Self {
+            signature: signature.to_string(),
+            timestamp: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            mint: crate::mints::USDC_MAINNET.to_string(),
+            amount,
+            decimals: 6,
+            direction,
+            from: "wallet".to_string(),
+            to: "counterparty".to_string(),
+            from_token_account: None,
+            to_token_account: None,
+            via_program: None,
+            authority: None,
+            slot: 100,
+            fee_lamports: 5000,
+            usd_value: None,
+            memo: None,
+            kind: TransactionKind::Transfer,
+            counter_mint: None,
+            explorer_url: None,
+            balance_after: None,
+            failed: false,
+            anomaly: false,
+            denied: false,
+        }
+    }
+}
+
+/// Drop repeated transfer legs, keeping the first occurrence of each
+pub fn dedupe_transfers(transfers: Vec<UsdcTransfer>) -> Vec<UsdcTransfer> {
+    let mut seen = std::collections::HashSet::new();
+    transfers
+        .into_iter()
+        .filter(|transfer| seen.insert(transfer.key()))
+        .collect()
 }
 
 #[derive(Debug, Clone)]
 pub struct TokenTransferInfo {
     pub mint: String,
-    pub amount: u64,
+    pub amount: u64, // Amount debited from the sender
+    pub decimals: u8,
+    pub transfer_fee: u64, // Withheld by a Token-2022 transfer-fee mint; 0 otherwise
     pub from_owner: String,
     pub to_owner: String,
-}
\ No newline at end of file
+    pub from_account: String, // Token account addresses; empty if not resolvable
+    pub to_account: String,
+}
+
+/// Tokens created in or destroyed from a token account by a mint or burn
+/// instruction
+#[derive(Debug, Clone)]
+pub struct SupplyChange {
+    pub direction: TransferDirection, // Mint or Burn
+    pub mint: String,
+    pub amount: u64,
+    pub account: String,   // Token account credited or debited
+    pub authority: String, // Mint authority for mints; owner or delegate for burns
+}
+
+/// Amount moved from one token account to another, as one parser saw it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferLeg {
+    pub mint: String,
+    pub from_account: String,
+    pub to_account: String,
+    pub amount: u64,
+}
+
+/// A transaction where the balance-diff and instruction parsers disagree,
+/// found with --validate
+#[derive(Debug, Clone)]
+pub struct ParserDiscrepancy {
+    pub signature: String,
+    /// Found by diffing token balances only
+    pub balance_only: Vec<TransferLeg>,
+    /// Found in transfer instructions only
+    pub instruction_only: Vec<TransferLeg>,
+}
+
+/// Outcome of --validate over the transactions of a run
+#[derive(Debug, Clone, Default)]
+pub struct Validation {
+    pub checked: usize,
+    pub discrepancies: Vec<ParserDiscrepancy>,
+}
+
+/// Result of comparing the indexed net change against on-chain balances
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reconciliation {
+    /// Wallet balance before the oldest indexed transfer
+    pub starting_balance: u64,
+    /// Current on-chain wallet balance
+    pub ending_balance: u64,
+    pub indexed_net: i128,
+    /// ending - (starting + net); non-zero means missed transfers
+    pub discrepancy: i128,
+}
+
+impl Reconciliation {
+    pub fn is_balanced(&self) -> bool {
+        self.discrepancy == 0
+    }
+}
+
+/// How the transactions of one backfill were handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransactionCounts {
+    pub processed: usize,
+    /// Failed on-chain and left out
+    pub skipped_failed: usize,
+    /// Could not be fetched or parsed
+    pub errored: usize,
+    /// getSignaturesForAddress requests, retries included
+    pub signature_calls: usize,
+    /// getTransaction requests
+    pub transaction_calls: usize,
+    /// getBlock requests made by --scan-blocks, retries included
+    pub block_calls: usize,
+}
+
+impl TransactionCounts {
+    /// Requests made for the calls that paid providers bill
+    pub fn billed_calls(&self) -> usize {
+        self.signature_calls + self.transaction_calls + self.block_calls
+    }
+}
+
+/// Outcome of one backfill run
+#[derive(Debug)]
+pub struct Backfill {
+    pub transfers: Vec<UsdcTransfer>,
+    /// Newest transaction seen, for the next run's `until`
+    pub checkpoint: Option<Checkpoint>,
+    /// Stopped early by --max-transactions, a failed signature fetch or an interrupt
+    pub partial: bool,
+    /// A page of signatures couldn't be fetched, even after retrying
+    pub signatures_failed: bool,
+    /// Cancelled, e.g. by Ctrl-C, before reaching the end of the window
+    pub interrupted: bool,
+    /// Signatures that still errored after retrying; their transfers are missing
+    pub unprocessed: Vec<String>,
+    /// Raw transactions behind the transfers, by signature, with --include-raw
+    pub raw_transactions: BTreeMap<String, EncodedConfirmedTransactionWithStatusMeta>,
+    /// Parser cross-check, with --validate
+    pub validation: Validation,
+    pub counts: TransactionCounts,
+    pub timings: Timings,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(signature: &str, to: &str, amount: u64) -> UsdcTransfer {
+        UsdcTransfer {
+            to: to.to_string(),
+            ..UsdcTransfer::sample(signature, TransferDirection::Sent, amount)
+        }
+    }
+
+    #[test]
+    fn dedupe_drops_repeated_legs() {
+        let transfers = vec![transfer("a", "bob", 5), transfer("a", "bob", 5), transfer("b", "bob", 5)];
+
+        let deduped = dedupe_transfers(transfers);
+
+        let signatures: Vec<&str> = deduped.iter().map(|transfer| transfer.signature.as_str()).collect();
+        assert_eq!(signatures, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn dedupe_keeps_distinct_legs_of_one_signature() {
+        let transfers = vec![
+            transfer("a", "bob", 5),
+            transfer("a", "carol", 5),
+            transfer("a", "bob", 7),
+            transfer("a", "carol", 5),
+        ];
+
+        let deduped = dedupe_transfers(transfers);
+
+        let legs: Vec<(&str, u64)> = deduped.iter().map(|transfer| (transfer.to.as_str(), transfer.amount)).collect();
+        assert_eq!(legs, vec![("bob", 5), ("carol", 5), ("bob", 7)]);
+    }
+}
@@ -1,18 +1,536 @@
-use solana_transaction_status::UiTransactionTokenBalance;
-use crate::transfer::TokenTransferInfo;
-use std::collections::HashMap;
+use solana_transaction_status::parse_accounts::ParsedAccountSource;
+use solana_transaction_status::{
+    EncodedTransaction, UiInstruction, UiMessage, UiParsedInstruction, UiTransactionTokenBalance,
+};
+use crate::mints::MintFilter;
+use crate::transfer::{SupplyChange, TokenTransferInfo, TransactionKind, TransferDirection, TransferLeg};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 
-// USDC mint addresses for different networks
-const USDC_MAINNET: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
-const USDC_DEVNET: &str = "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU"; // For testing
+pub const USDC_DECIMALS: u8 = 6;
 
-pub fn is_usdc_mint(mint: &str) -> bool {
-    mint == USDC_MAINNET || mint == USDC_DEVNET
+// Token program ids
+pub const TOKEN_PROGRAM_ID: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+pub const TOKEN_2022_PROGRAM_ID: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+
+/// Which token program's balances to index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TokenProgram {
+    Any,
+    SplToken,
+    Token2022,
+}
+
+impl TokenProgram {
+    pub fn matches(&self, program_id: &str) -> bool {
+        match self {
+            TokenProgram::Any => true,
+            TokenProgram::SplToken => program_id == TOKEN_PROGRAM_ID,
+            TokenProgram::Token2022 => program_id == TOKEN_2022_PROGRAM_ID,
+        }
+    }
+}
+
+/// How transfers are attributed to the wallet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MatchBy {
+    Owner,   // The owner recorded in the transaction's token balances
+    Account, // The wallet's token accounts, fetched with getTokenAccountsByOwner
+}
+
+const ASSOCIATED_TOKEN_PROGRAM_ID: &str = "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL";
+
+/// Associated token account address for `wallet` and `mint` under `token_program`
+pub fn associated_token_address(wallet: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    let ata_program = Pubkey::from_str(ASSOCIATED_TOKEN_PROGRAM_ID).expect("valid program id");
+    Pubkey::find_program_address(
+        &[wallet.as_ref(), token_program.as_ref(), mint.as_ref()],
+        &ata_program,
+    )
+    .0
+}
+
+/// The wallet's USDC associated token accounts under both token programs
+pub fn wallet_usdc_token_accounts(wallet: &Pubkey, mints: &MintFilter) -> HashSet<String> {
+    let mut accounts = HashSet::new();
+
+    for mint in mints.usdc_mints() {
+        for program in [TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID] {
+            let program = Pubkey::from_str(program).expect("valid program id");
+            accounts.insert(associated_token_address(wallet, &mint, &program).to_string());
+        }
+    }
+
+    accounts
+}
+
+/// All account keys of a transaction in index order, including addresses
+/// loaded from lookup tables, so token balance `account_index`es can be
+/// resolved to token account addresses.
+///
+/// For v0 transactions the runtime orders keys as: static keys, then writable
+/// lookup-table addresses, then readonly ones. `meta.loaded_addresses` is the
+/// authoritative list of the latter two, so it is appended to the static keys
+/// whenever present; parsed messages that already carry lookup-table keys are
+/// used as-is only when the node omitted `loaded_addresses`.
+pub fn transaction_account_keys(
+    transaction: &EncodedTransaction,
+    meta: &solana_transaction_status::UiTransactionStatusMeta,
+) -> Vec<String> {
+    let loaded = match &meta.loaded_addresses {
+        solana_transaction_status::option_serializer::OptionSerializer::Some(loaded) => Some(loaded),
+        _ => None,
+    };
+
+    let mut keys: Vec<String> = match transaction {
+        EncodedTransaction::Json(ui_transaction) => match &ui_transaction.message {
+            UiMessage::Parsed(message) => message
+                .account_keys
+                .iter()
+                .filter(|key| loaded.is_none() || key.source != Some(ParsedAccountSource::LookupTable))
+                .map(|key| key.pubkey.clone())
+                .collect(),
+            UiMessage::Raw(message) => message.account_keys.clone(),
+        },
+        _ => return Vec::new(),
+    };
+
+    if let Some(loaded) = loaded {
+        keys.extend(loaded.writable.iter().cloned());
+        keys.extend(loaded.readonly.iter().cloned());
+    }
+
+    keys
+}
+
+// Memo program ids (v2 is current, v1 still shows up in older transactions)
+const MEMO_PROGRAM_ID: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+const MEMO_V1_PROGRAM_ID: &str = "Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo";
+
+fn is_memo_program(program_id: &str) -> bool {
+    program_id == MEMO_PROGRAM_ID || program_id == MEMO_V1_PROGRAM_ID
 }
 
+/// Collect the text of every top-level Memo instruction in a `JsonParsed`
+/// transaction. Several memos are joined with "; ".
+pub fn parse_memo(transaction: &EncodedTransaction) -> Option<String> {
+    let instructions = match transaction {
+        EncodedTransaction::Json(ui_transaction) => match &ui_transaction.message {
+            UiMessage::Parsed(message) => &message.instructions,
+            UiMessage::Raw(_) => return None,
+        },
+        _ => return None,
+    };
+
+    let memos: Vec<String> = instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed)) if is_memo_program(&parsed.program_id) => {
+                parsed.parsed.as_str().map(str::to_string)
+            }
+            // The node only leaves a memo undecoded if it isn't valid UTF-8 by its rules
+            UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(partial)) if is_memo_program(&partial.program_id) => {
+                bs58::decode(&partial.data)
+                    .into_vec()
+                    .ok()
+                    .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            }
+            _ => None,
+        })
+        .collect();
+
+    if memos.is_empty() {
+        None
+    } else {
+        Some(memos.join("; "))
+    }
+}
+
+/// Every mint and burn instruction in a `JsonParsed` transaction, top-level and
+/// inner, for token programs selected by `program`. These are the balance
+/// changes no other account pays for or receives.
+pub fn parse_supply_changes(
+    transaction: &EncodedTransaction,
+    meta: &solana_transaction_status::UiTransactionStatusMeta,
+    program: TokenProgram,
+) -> Vec<SupplyChange> {
+    let mut instructions: Vec<&UiInstruction> = match transaction {
+        EncodedTransaction::Json(ui_transaction) => match &ui_transaction.message {
+            UiMessage::Parsed(message) => message.instructions.iter().collect(),
+            UiMessage::Raw(_) => return Vec::new(),
+        },
+        _ => return Vec::new(),
+    };
+    if let solana_transaction_status::option_serializer::OptionSerializer::Some(inner) = &meta.inner_instructions {
+        instructions.extend(inner.iter().flat_map(|inner| inner.instructions.iter()));
+    }
+
+    instructions
+        .into_iter()
+        .filter_map(|instruction| match instruction {
+            UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed)) if program.matches(&parsed.program_id) => {
+                parse_supply_instruction(&parsed.parsed)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Token transfers made by another program through CPI, keyed by source and
+/// destination token account, with the id of the top-level program whose
+/// instruction invoked them
+pub fn cpi_transfer_programs(
+    transaction: &EncodedTransaction,
+    meta: &solana_transaction_status::UiTransactionStatusMeta,
+    account_keys: &[String],
+) -> HashMap<(String, String), String> {
+    let mut programs = HashMap::new();
+
+    let outer: &[UiInstruction] = match transaction {
+        EncodedTransaction::Json(ui_transaction) => match &ui_transaction.message {
+            UiMessage::Parsed(message) => &message.instructions,
+            UiMessage::Raw(_) => return programs,
+        },
+        _ => return programs,
+    };
+    let solana_transaction_status::option_serializer::OptionSerializer::Some(inner) = &meta.inner_instructions else {
+        return programs;
+    };
+
+    for group in inner {
+        let outer_program = match outer.get(group.index as usize) {
+            Some(instruction) => instruction_program(instruction, account_keys).unwrap_or_default(),
+            None => continue,
+        };
+
+        for instruction in &group.instructions {
+            let UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed)) = instruction else {
+                continue;
+            };
+            if !matches!(parsed.parsed["type"].as_str(), Some("transfer" | "transferChecked")) {
+                continue;
+            }
+            let info = &parsed.parsed["info"];
+            if let (Some(source), Some(destination)) = (info["source"].as_str(), info["destination"].as_str()) {
+                programs.insert((source.to_string(), destination.to_string()), outer_program.clone());
+            }
+        }
+    }
+
+    programs
+}
+
+/// Who signed each token transfer in a `JsonParsed` transaction, keyed by
+/// source and destination token account: the owner, a delegate, or a
+/// multisig account. Only parsed `transfer`/`transferChecked` instructions,
+/// top-level and inner, say so; balance changes alone don't.
+pub fn transfer_authorities(
+    transaction: &EncodedTransaction,
+    meta: &solana_transaction_status::UiTransactionStatusMeta,
+) -> HashMap<(String, String), String> {
+    let mut instructions: Vec<&UiInstruction> = match transaction {
+        EncodedTransaction::Json(ui_transaction) => match &ui_transaction.message {
+            UiMessage::Parsed(message) => message.instructions.iter().collect(),
+            UiMessage::Raw(_) => return HashMap::new(),
+        },
+        _ => return HashMap::new(),
+    };
+    if let solana_transaction_status::option_serializer::OptionSerializer::Some(inner) = &meta.inner_instructions {
+        instructions.extend(inner.iter().flat_map(|inner| inner.instructions.iter()));
+    }
+
+    instructions
+        .into_iter()
+        .filter_map(|instruction| {
+            let UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed)) = instruction else {
+                return None;
+            };
+            if !matches!(parsed.parsed["type"].as_str(), Some("transfer" | "transferChecked")) {
+                return None;
+            }
+            let info = &parsed.parsed["info"];
+            let authority = info["authority"].as_str().or_else(|| info["multisigAuthority"].as_str())?;
+            Some((
+                (info["source"].as_str()?.to_string(), info["destination"].as_str()?.to_string()),
+                authority.to_string(),
+            ))
+        })
+        .collect()
+}
+
+/// Token transfers read from the parsed `transfer`, `transferChecked` and
+/// `transferCheckedWithFee` instructions of a `JsonParsed` transaction, top-level
+/// and inner, for token programs selected by `program`. The counterpart of
+/// `parse_token_transfers` for --validate; plain `transfer` instructions don't
+/// name their mint, so it comes from the token balances.
+pub fn parse_instruction_transfers(
+    transaction: &EncodedTransaction,
+    meta: &solana_transaction_status::UiTransactionStatusMeta,
+    account_keys: &[String],
+    program: TokenProgram,
+) -> Vec<TokenTransferInfo> {
+    let mut instructions: Vec<&UiInstruction> = match transaction {
+        EncodedTransaction::Json(ui_transaction) => match &ui_transaction.message {
+            UiMessage::Parsed(message) => message.instructions.iter().collect(),
+            UiMessage::Raw(_) => return Vec::new(),
+        },
+        _ => return Vec::new(),
+    };
+    if let solana_transaction_status::option_serializer::OptionSerializer::Some(inner) = &meta.inner_instructions {
+        instructions.extend(inner.iter().flat_map(|inner| inner.instructions.iter()));
+    }
+
+    instructions
+        .into_iter()
+        .filter_map(|instruction| {
+            let UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed)) = instruction else {
+                return None;
+            };
+            if !program.matches(&parsed.program_id)
+                || !matches!(
+                    parsed.parsed["type"].as_str(),
+                    Some("transfer" | "transferChecked" | "transferCheckedWithFee")
+                )
+            {
+                return None;
+            }
+
+            let info = &parsed.parsed["info"];
+            let from_account = info["source"].as_str()?.to_string();
+            let to_account = info["destination"].as_str()?.to_string();
+            let mint = match info["mint"].as_str() {
+                Some(mint) => mint.to_string(),
+                None => token_account_mint(meta, account_keys, &from_account)
+                    .or_else(|| token_account_mint(meta, account_keys, &to_account))?,
+            };
+            // Checked variants carry the amount inside `tokenAmount`
+            let amount = info["amount"]
+                .as_str()
+                .or_else(|| info["tokenAmount"]["amount"].as_str())?
+                .parse()
+                .ok()?;
+
+            Some(TokenTransferInfo {
+                mint,
+                amount,
+                decimals: info["tokenAmount"]["decimals"].as_u64().unwrap_or_default() as u8,
+                transfer_fee: info["feeAmount"]["amount"]
+                    .as_str()
+                    .and_then(|fee| fee.parse().ok())
+                    .unwrap_or_default(),
+                from_owner: token_account_owner(meta, account_keys, &from_account),
+                to_owner: token_account_owner(meta, account_keys, &to_account),
+                from_account,
+                to_account,
+            })
+        })
+        .collect()
+}
+
+/// Legs of `balance` and `instruction` transfers that the other list doesn't
+/// have. Amounts between the same two token accounts are summed first, since
+/// a balance diff can only see their total.
+pub fn compare_transfer_legs(
+    balance: &[TokenTransferInfo],
+    instruction: &[TokenTransferInfo],
+) -> (Vec<TransferLeg>, Vec<TransferLeg>) {
+    let legs = |transfers: &[TokenTransferInfo]| {
+        let mut totals: HashMap<(String, String, String), u64> = HashMap::new();
+        // Moving tokens to the same account changes no balance
+        for transfer in transfers.iter().filter(|transfer| transfer.from_account != transfer.to_account) {
+            let key = (transfer.mint.clone(), transfer.from_account.clone(), transfer.to_account.clone());
+            *totals.entry(key).or_default() += transfer.amount;
+        }
+        totals
+    };
+    let only_in = |ours: &HashMap<(String, String, String), u64>, theirs: &HashMap<(String, String, String), u64>| {
+        let mut only: Vec<TransferLeg> = ours
+            .iter()
+            .filter(|(key, amount)| theirs.get(*key) != Some(*amount))
+            .map(|((mint, from_account, to_account), amount)| TransferLeg {
+                mint: mint.clone(),
+                from_account: from_account.clone(),
+                to_account: to_account.clone(),
+                amount: *amount,
+            })
+            .collect();
+        only.sort_by(|a, b| (&a.mint, &a.from_account, &a.to_account).cmp(&(&b.mint, &b.from_account, &b.to_account)));
+        only
+    };
+
+    let (balance, instruction) = (legs(balance), legs(instruction));
+    (only_in(&balance, &instruction), only_in(&instruction, &balance))
+}
+
+/// Id of the program an instruction calls
+fn instruction_program(instruction: &UiInstruction, account_keys: &[String]) -> Option<String> {
+    match instruction {
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed)) => Some(parsed.program_id.clone()),
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(decoded)) => Some(decoded.program_id.clone()),
+        UiInstruction::Compiled(compiled) => account_keys.get(compiled.program_id_index as usize).cloned(),
+    }
+}
+
+/// Every program a transaction invoked, by a top-level instruction or
+/// through CPI
+pub fn invoked_programs(
+    transaction: &EncodedTransaction,
+    meta: &solana_transaction_status::UiTransactionStatusMeta,
+    account_keys: &[String],
+) -> HashSet<String> {
+    let mut programs: HashSet<String> = match transaction {
+        EncodedTransaction::Json(ui_transaction) => match &ui_transaction.message {
+            UiMessage::Parsed(message) => message
+                .instructions
+                .iter()
+                .filter_map(|instruction| instruction_program(instruction, account_keys))
+                .collect(),
+            UiMessage::Raw(message) => message
+                .instructions
+                .iter()
+                .filter_map(|instruction| account_keys.get(instruction.program_id_index as usize).cloned())
+                .collect(),
+        },
+        _ => return HashSet::new(),
+    };
+
+    if let solana_transaction_status::option_serializer::OptionSerializer::Some(inner) = &meta.inner_instructions {
+        programs.extend(
+            inner
+                .iter()
+                .flat_map(|inner| inner.instructions.iter())
+                .filter_map(|instruction| instruction_program(instruction, account_keys)),
+        );
+    }
+
+    programs
+}
+
+fn parse_supply_instruction(parsed: &serde_json::Value) -> Option<SupplyChange> {
+    let info = &parsed["info"];
+    let (direction, authority) = match parsed["type"].as_str()? {
+        "mintTo" | "mintToChecked" => (TransferDirection::Mint, ["mintAuthority", "multisigMintAuthority"]),
+        "burn" | "burnChecked" => (TransferDirection::Burn, ["authority", "multisigAuthority"]),
+        _ => return None,
+    };
+
+    // Checked variants carry the amount inside `tokenAmount`
+    let amount = info["amount"]
+        .as_str()
+        .or_else(|| info["tokenAmount"]["amount"].as_str())?
+        .parse()
+        .ok()?;
+
+    Some(SupplyChange {
+        direction,
+        mint: info["mint"].as_str()?.to_string(),
+        amount,
+        account: info["account"].as_str()?.to_string(),
+        authority: authority
+            .iter()
+            .find_map(|field| info[*field].as_str())
+            .unwrap_or_default()
+            .to_string(),
+    })
+}
+
+/// Owner of `account` according to the transaction's token balances, or an
+/// empty string if it has none
+pub fn token_account_owner(
+    meta: &solana_transaction_status::UiTransactionStatusMeta,
+    account_keys: &[String],
+    account: &str,
+) -> String {
+    let Some(index) = account_keys.iter().position(|key| key == account) else {
+        return String::new();
+    };
+
+    [&meta.post_token_balances, &meta.pre_token_balances]
+        .into_iter()
+        .filter_map(|balances| match balances {
+            solana_transaction_status::option_serializer::OptionSerializer::Some(balances) => Some(balances),
+            _ => None,
+        })
+        .flatten()
+        .filter(|balance| balance.account_index as usize == index)
+        .find_map(|balance| match &balance.owner {
+            solana_transaction_status::option_serializer::OptionSerializer::Some(owner) => Some(owner.clone()),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+/// Mint of `account` according to the transaction's token balances
+fn token_account_mint(
+    meta: &solana_transaction_status::UiTransactionStatusMeta,
+    account_keys: &[String],
+    account: &str,
+) -> Option<String> {
+    let index = account_keys.iter().position(|key| key == account)?;
+
+    [&meta.pre_token_balances, &meta.post_token_balances]
+        .into_iter()
+        .filter_map(|balances| match balances {
+            solana_transaction_status::option_serializer::OptionSerializer::Some(balances) => Some(balances),
+            _ => None,
+        })
+        .flatten()
+        .find(|balance| balance.account_index as usize == index)
+        .map(|balance| balance.mint.clone())
+}
+
+/// Program that owns a token balance entry. Older RPC nodes omit the field,
+/// in which case the balance can only belong to the classic token program.
+fn balance_program_id(balance: &UiTransactionTokenBalance) -> String {
+    match &balance.program_id {
+        solana_transaction_status::option_serializer::OptionSerializer::Some(program_id) => program_id.clone(),
+        _ => TOKEN_PROGRAM_ID.to_string(),
+    }
+}
+
+/// USDC balance of each token account `owner` holds across the given token
+/// balance entries, by address
+pub fn owner_usdc_balances(
+    balances: &[UiTransactionTokenBalance],
+    account_keys: &[String],
+    owner: &str,
+    mints: &MintFilter,
+) -> HashMap<String, u64> {
+    balances
+        .iter()
+        .filter(|balance| mints.is_usdc(&balance.mint))
+        .filter(|balance| match &balance.owner {
+            solana_transaction_status::option_serializer::OptionSerializer::Some(o) => o == owner,
+            _ => false,
+        })
+        .filter_map(|balance| {
+            let account = account_keys.get(balance.account_index as usize)?;
+            Some((account.clone(), parse_token_amount(&balance.ui_token_amount.amount)))
+        })
+        .collect()
+}
+
+/// Derive token transfers by diffing pre and post token balances.
+///
+/// Decreases are paired with increases of exactly the same amount. Token-2022
+/// mints with the transfer-fee extension withhold part of every transfer, so
+/// the recipient's increase is smaller than the sender's decrease and the exact
+/// match fails. For Token-2022 balances, an unmatched decrease is therefore
+/// paired with the largest remaining increase below it, and the difference is
+/// reported as `transfer_fee`.
+///
+/// `supply_changes` are subtracted first: a minted increase or burned decrease
+/// has no other side and would otherwise be mispaired.
 pub fn parse_token_transfers(
     meta: &solana_transaction_status::UiTransactionStatusMeta,
+    account_keys: &[String],
+    program: TokenProgram,
+    supply_changes: &[SupplyChange],
+    mints: &MintFilter,
 ) -> Option<Vec<TokenTransferInfo>> {
+    let account_address = |index: usize| account_keys.get(index).cloned().unwrap_or_default();
+
     // Handle OptionSerializer by extracting the actual values
     let pre_balances = match &meta.pre_token_balances {
         solana_transaction_status::option_serializer::OptionSerializer::Some(balances) => balances,
@@ -24,84 +542,110 @@ pub fn parse_token_transfers(
         _ => return None,
     };
 
-    // Create maps for easier lookup
-    let mut pre_balance_map: HashMap<usize, &UiTransactionTokenBalance> = HashMap::new();
-    let mut post_balance_map: HashMap<usize, &UiTransactionTokenBalance> = HashMap::new();
+    // Create maps for easier lookup. Balances are keyed by account index and
+    // mint: an account closed and recreated within the transaction can hold a
+    // different mint afterwards, and each mint's side is its own balance change.
+    let mut pre_balance_map: HashMap<(usize, &str), &UiTransactionTokenBalance> = HashMap::new();
+    let mut post_balance_map: HashMap<(usize, &str), &UiTransactionTokenBalance> = HashMap::new();
 
     for balance in pre_balances {
-        pre_balance_map.insert(balance.account_index as usize, balance);
+        pre_balance_map.insert((balance.account_index as usize, balance.mint.as_str()), balance);
     }
 
     for balance in post_balances {
-        post_balance_map.insert(balance.account_index as usize, balance);
+        post_balance_map.insert((balance.account_index as usize, balance.mint.as_str()), balance);
     }
 
     let mut transfers = Vec::new();
 
     // Find all accounts that had balance changes
-    let mut all_accounts: std::collections::HashSet<usize> = std::collections::HashSet::new();
-    
-    for balance in pre_balances {
-        all_accounts.insert(balance.account_index as usize);
-    }
-    for balance in post_balances {
-        all_accounts.insert(balance.account_index as usize);
-    }
+    let mut all_accounts: HashSet<(usize, &str)> = HashSet::new();
+    all_accounts.extend(pre_balance_map.keys());
+    all_accounts.extend(post_balance_map.keys());
 
     // Group accounts by mint
     let mut mint_accounts: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut mint_programs: HashMap<String, String> = HashMap::new();
     
-    for &account_index in &all_accounts {
-        let mint = if let Some(pre) = pre_balance_map.get(&account_index) {
-            pre.mint.clone()
-        } else if let Some(post) = post_balance_map.get(&account_index) {
-            post.mint.clone()
+    for key in &all_accounts {
+        let balance = if let Some(pre) = pre_balance_map.get(key) {
+            pre
+        } else if let Some(post) = post_balance_map.get(key) {
+            post
         } else {
             continue;
         };
-        
-        mint_accounts.entry(mint).or_insert_with(Vec::new).push(account_index);
+        let account_index = key.0;
+
+        let program_id = balance_program_id(balance);
+        if !program.matches(&program_id) {
+            continue;
+        }
+
+        mint_programs.insert(balance.mint.clone(), program_id);
+        mint_accounts.entry(balance.mint.clone()).or_default().push(account_index);
     }
 
     // Process each mint group to find transfers
     for (mint, accounts) in mint_accounts {
-        // Only process if it's USDC
-        if !is_usdc_mint(&mint) {
+        // Only process the mints the filter selects
+        if !mints.is_indexed(&mint) {
             continue;
         }
 
         // Calculate balance changes for each account
-        let mut balance_changes: Vec<(usize, i64, String)> = Vec::new();
+        let mut balance_changes: Vec<(usize, i128, String)> = Vec::new();
+        let mut decimals = mints.decimals(&mint);
         
         for &account_index in &accounts {
-            let pre_amount = if let Some(pre) = pre_balance_map.get(&account_index) {
+            let key = (account_index, mint.as_str());
+            let pre_amount = if let Some(pre) = pre_balance_map.get(&key) {
                 parse_token_amount(&pre.ui_token_amount.amount)
             } else {
                 0
             };
             
-            let post_amount = if let Some(post) = post_balance_map.get(&account_index) {
+            let post_amount = if let Some(post) = post_balance_map.get(&key) {
                 parse_token_amount(&post.ui_token_amount.amount)
             } else {
                 0
             };
             
-            let change = post_amount as i64 - pre_amount as i64;
+            if let Some(balance) = post_balance_map.get(&key).or(pre_balance_map.get(&key)) {
+                decimals = mints.decimals_override(&mint).unwrap_or(balance.ui_token_amount.decimals);
+            }
+
+            // Minted and burned amounts have no counterpart account, so take
+            // them out before pairing decreases with increases
+            let address = account_address(account_index);
+            let supply: i128 = supply_changes
+                .iter()
+                .filter(|supply| supply.mint == mint && supply.account == address)
+                .map(|supply| match supply.direction {
+                    TransferDirection::Burn => -(supply.amount as i128),
+                    _ => supply.amount as i128,
+                })
+                .sum();
+
+            let change = post_amount as i128 - pre_amount as i128 - supply;
             
             if change != 0 {
-                let owner = if let Some(post) = post_balance_map.get(&account_index) {
-                    match &post.owner {
-                        solana_transaction_status::option_serializer::OptionSerializer::Some(owner) => owner.clone(),
-                        _ => String::new(),
-                    }
-                } else if let Some(pre) = pre_balance_map.get(&account_index) {
-                    match &pre.owner {
-                        solana_transaction_status::option_serializer::OptionSerializer::Some(owner) => owner.clone(),
-                        _ => String::new(),
-                    }
-                } else {
-                    String::new()
-                };
+                // Tokens leaving an account left its owner from before the
+                // transaction, which is all a closed account still has; tokens
+                // arriving went to the owner after it. Fall back to the other
+                // side when the preferred entry is missing or has no owner.
+                let (pre, post) = (pre_balance_map.get(&key), post_balance_map.get(&key));
+                let sides = if change < 0 { [pre, post] } else { [post, pre] };
+                let owner = sides
+                    .into_iter()
+                    .flatten()
+                    .find_map(|balance| match &balance.owner {
+                        solana_transaction_status::option_serializer::OptionSerializer::Some(owner) if !owner.is_empty() => {
+                            Some(owner.clone())
+                        }
+                        _ => None,
+                    })
+                    .unwrap_or_default();
                 
                 balance_changes.push((account_index, change, owner));
             }
@@ -116,6 +660,7 @@ pub fn parse_token_transfers(
             .collect();
 
         // Try to match transfers
+        let mut unmatched = Vec::new();
         while let Some(decrease) = decreases.pop() {
             let decrease_amount = (-decrease.1) as u64;
             
@@ -126,9 +671,44 @@ pub fn parse_token_transfers(
                 transfers.push(TokenTransferInfo {
                     mint: mint.clone(),
                     amount: decrease_amount,
+                    decimals,
+                    transfer_fee: 0,
                     from_owner: decrease.2.clone(),
                     to_owner: increase.2.clone(),
+                    from_account: account_address(decrease.0),
+                    to_account: account_address(increase.0),
                 });
+            } else {
+                unmatched.push(decrease);
+            }
+        }
+
+        // Token-2022 transfer fees: the recipient gets less than the sender paid
+        if mint_programs.get(&mint).map(String::as_str) == Some(TOKEN_2022_PROGRAM_ID) {
+            for decrease in unmatched {
+                let decrease_amount = (-decrease.1) as u64;
+
+                let best = increases
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (_, change, _))| (*change as u64) < decrease_amount)
+                    .max_by_key(|(_, (_, change, _))| *change)
+                    .map(|(pos, _)| pos);
+
+                if let Some(increase_pos) = best {
+                    let increase = increases.remove(increase_pos);
+
+                    transfers.push(TokenTransferInfo {
+                        mint: mint.clone(),
+                        amount: decrease_amount,
+                        decimals,
+                        transfer_fee: decrease_amount - increase.1 as u64,
+                        from_owner: decrease.2.clone(),
+                        to_owner: increase.2.clone(),
+                        from_account: account_address(decrease.0),
+                        to_account: account_address(increase.0),
+                    });
+                }
             }
         }
     }
@@ -140,6 +720,359 @@ pub fn parse_token_transfers(
     }
 }
 
+/// Most decimals `format_amount` can place; 10^38 is the largest power of ten in a u128
+const MAX_FORMAT_DECIMALS: u8 = 38;
+
+/// Render a raw token amount in whole units with thousands separators and
+/// one place per decimal, e.g. `1234500000` at 6 decimals is `1,234.500000`.
+/// Works on the integer so no float error can creep in. `max_places` rounds
+/// the fraction, half away from zero, to at most that many places.
+pub fn format_amount(raw: i128, decimals: u8, max_places: Option<u8>) -> String {
+    let decimals = decimals.min(MAX_FORMAT_DECIMALS);
+    let places = max_places.map_or(decimals, |max_places| max_places.min(decimals));
+    let (negative, whole, fraction) = split_amount(raw, decimals, places);
+
+    let digits = whole.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3 + places as usize + 2);
+    if negative {
+        formatted.push('-');
+    }
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    if places > 0 {
+        formatted.push_str(&format!(".{:0width$}", fraction, width = places as usize));
+    }
+    formatted
+}
+
+/// A raw token amount in whole units, exactly and without separators or
+/// trailing zeros, e.g. `1234.5` for `1234500000` at 6 decimals. For
+/// machine-readable output.
+pub fn exact_amount(raw: i128, decimals: u8) -> String {
+    let decimals = decimals.min(MAX_FORMAT_DECIMALS);
+    let (negative, whole, fraction) = split_amount(raw, decimals, decimals);
+
+    let sign = if negative { "-" } else { "" };
+    let fraction = format!("{:0width$}", fraction, width = decimals as usize);
+    match fraction.trim_end_matches('0') {
+        "" => format!("{}{}", sign, whole),
+        fraction => format!("{}{}.{}", sign, whole, fraction),
+    }
+}
+
+/// Split a raw amount rounded to `places` of its `decimals` (half away from
+/// zero) into its sign, whole units and fraction, using integers only
+fn split_amount(raw: i128, decimals: u8, places: u8) -> (bool, u128, u128) {
+    let dropped = 10u128.pow(u32::from(decimals - places));
+    let rounded = (raw.unsigned_abs() + dropped / 2) / dropped;
+    let unit = 10u128.pow(u32::from(places));
+    (raw < 0 && rounded > 0, rounded / unit, rounded % unit)
+}
+
+/// Net change of each mint's balance held by `owner` over the transaction
+pub fn owner_balance_changes(
+    meta: &solana_transaction_status::UiTransactionStatusMeta,
+    owner: &str,
+) -> HashMap<String, i128> {
+    let mut changes: HashMap<String, i128> = HashMap::new();
+
+    let owned = |balance: &&UiTransactionTokenBalance| match &balance.owner {
+        solana_transaction_status::option_serializer::OptionSerializer::Some(o) => o == owner,
+        _ => false,
+    };
+
+    if let solana_transaction_status::option_serializer::OptionSerializer::Some(balances) = &meta.pre_token_balances {
+        for balance in balances.iter().filter(owned) {
+            *changes.entry(balance.mint.clone()).or_default() -= parse_token_amount(&balance.ui_token_amount.amount) as i128;
+        }
+    }
+
+    if let solana_transaction_status::option_serializer::OptionSerializer::Some(balances) = &meta.post_token_balances {
+        for balance in balances.iter().filter(owned) {
+            *changes.entry(balance.mint.clone()).or_default() += parse_token_amount(&balance.ui_token_amount.amount) as i128;
+        }
+    }
+
+    changes.retain(|_, change| *change != 0);
+    changes
+}
+
+/// Decide whether a transaction is a plain transfer or a swap from the
+/// wallet's per-mint balance changes: USDC leaving while another mint arrives
+/// (or the reverse) is a swap against that mint
+pub fn classify_transaction(changes: &HashMap<String, i128>, mints: &MintFilter) -> (TransactionKind, Option<String>) {
+    let usdc_change: i128 = changes
+        .iter()
+        .filter(|(mint, _)| mints.is_usdc(mint))
+        .map(|(_, change)| *change)
+        .sum();
+
+    if usdc_change == 0 {
+        return (TransactionKind::Transfer, None);
+    }
+
+    let counter_mint = changes
+        .iter()
+        .filter(|(mint, change)| !mints.is_usdc(mint) && change.signum() == -usdc_change.signum())
+        .max_by_key(|(_, change)| change.abs())
+        .map(|(mint, _)| mint.clone());
+
+    match counter_mint {
+        Some(mint) => (TransactionKind::Swap, Some(mint)),
+        None => (TransactionKind::Transfer, None),
+    }
+}
+
+/// First 8 characters of an address, for compact display
+pub fn short_address(address: &str) -> &str {
+    address.get(..8).unwrap_or(address)
+}
+
+/// Parse a human amount like "1500.25" into raw units for a mint with
+/// `decimals` decimals, without going through floating point. Returns `None`
+/// for malformed input or more fractional digits than the mint supports.
+pub fn parse_ui_amount(amount: &str, decimals: u8) -> Option<u128> {
+    let (whole, fraction) = amount.trim().split_once('.').unwrap_or((amount.trim(), ""));
+
+    if whole.is_empty() && fraction.is_empty()
+        || fraction.len() > decimals as usize
+        || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let scale = 10u128.checked_pow(decimals as u32)?;
+    let whole: u128 = if whole.is_empty() { 0 } else { whole.parse().ok()? };
+    let fraction: u128 = if fraction.is_empty() {
+        0
+    } else {
+        fraction.parse::<u128>().ok()? * 10u128.pow((decimals as usize - fraction.len()) as u32)
+    };
+
+    whole.checked_mul(scale)?.checked_add(fraction)
+}
+
 fn parse_token_amount(amount_str: &str) -> u64 {
     amount_str.parse::<u64>().unwrap_or(0)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mints::USDC_MAINNET;
+    use serde_json::{json, Value};
+    use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+
+    fn balance(index: usize, mint: &str, owner: Option<&str>, amount: u64) -> Value {
+        json!({
+            "accountIndex": index,
+            "mint": mint,
+            "uiTokenAmount": {
+                "uiAmount": amount as f64 / 1e6,
+                "decimals": 6,
+                "amount": amount.to_string(),
+                "uiAmountString": (amount as f64 / 1e6).to_string(),
+            },
+            "owner": owner,
+            "programId": TOKEN_PROGRAM_ID,
+        })
+    }
+
+    /// A parsed transaction with `keys` in its message and `loaded` as
+    /// writable lookup-table addresses, moving tokens from `pre` to `post`
+    fn transaction(keys: &[&str], loaded: &[&str], pre: Vec<Value>, post: Vec<Value>) -> EncodedConfirmedTransactionWithStatusMeta {
+        let key = |pubkey: &str, source: &str| json!({ "pubkey": pubkey, "writable": true, "signer": false, "source": source });
+        let account_keys: Vec<Value> = keys
+            .iter()
+            .map(|pubkey| key(pubkey, "transaction"))
+            .chain(loaded.iter().map(|pubkey| key(pubkey, "lookupTable")))
+            .collect();
+
+        serde_json::from_value(json!({
+            "slot": 100,
+            "blockTime": 1_700_000_000,
+            "transaction": {
+                "signatures": [solana_sdk::signature::Signature::new_unique().to_string()],
+                "message": {
+                    "accountKeys": account_keys,
+                    "recentBlockhash": "11111111111111111111111111111111",
+                    "instructions": [],
+                },
+            },
+            "meta": {
+                "err": null,
+                "status": { "Ok": null },
+                "fee": 5000,
+                "preBalances": [],
+                "postBalances": [],
+                "innerInstructions": [],
+                "logMessages": [],
+                "preTokenBalances": pre,
+                "postTokenBalances": post,
+                "rewards": [],
+                "loadedAddresses": { "writable": loaded, "readonly": [] },
+            },
+            "version": 0,
+        }))
+        .unwrap()
+    }
+
+    fn transfers(transaction: &EncodedConfirmedTransactionWithStatusMeta) -> Vec<TokenTransferInfo> {
+        let meta = transaction.transaction.meta.as_ref().unwrap();
+        let keys = transaction_account_keys(&transaction.transaction.transaction, meta);
+        parse_token_transfers(meta, &keys, TokenProgram::Any, &[], &MintFilter::usdc(None)).unwrap_or_default()
+    }
+
+    fn address() -> String {
+        Pubkey::new_unique().to_string()
+    }
+
+    #[test]
+    fn resolves_token_accounts_loaded_from_a_lookup_table() {
+        let (wallet, program, counterparty) = (address(), address(), address());
+        let (wallet_account, counterparty_account) = (address(), address());
+        let transaction = transaction(
+            &[&wallet, &program],
+            &[&wallet_account, &counterparty_account],
+            vec![balance(2, USDC_MAINNET, Some(&wallet), 5_000_000), balance(3, USDC_MAINNET, Some(&counterparty), 0)],
+            vec![balance(2, USDC_MAINNET, Some(&wallet), 3_000_000), balance(3, USDC_MAINNET, Some(&counterparty), 2_000_000)],
+        );
+
+        let meta = transaction.transaction.meta.as_ref().unwrap();
+        let keys = transaction_account_keys(&transaction.transaction.transaction, meta);
+        assert_eq!(keys, vec![wallet.clone(), program, wallet_account.clone(), counterparty_account.clone()]);
+
+        let transfers = transfers(&transaction);
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].amount, 2_000_000);
+        assert_eq!((transfers[0].from_owner.as_str(), transfers[0].to_owner.as_str()), (wallet.as_str(), counterparty.as_str()));
+        assert_eq!(transfers[0].from_account, wallet_account);
+        assert_eq!(transfers[0].to_account, counterparty_account);
+    }
+
+    #[test]
+    fn falls_back_when_a_balance_has_no_owner() {
+        let (sender, keys) = (address(), [address(), address(), address()]);
+        let transaction = transaction(
+            &[&keys[0], &keys[1], &keys[2]],
+            &[],
+            // The sender's pre entry lacks an owner, but its post entry has one
+            vec![balance(1, USDC_MAINNET, None, 5_000_000), balance(2, USDC_MAINNET, None, 0)],
+            vec![balance(1, USDC_MAINNET, Some(&sender), 0), balance(2, USDC_MAINNET, None, 5_000_000)],
+        );
+
+        let transfers = transfers(&transaction);
+
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].from_owner, sender);
+        assert_eq!(transfers[0].to_owner, ""); // No owner on either side
+        assert_eq!(transfers[0].to_account, keys[2]);
+    }
+
+    #[test]
+    fn keeps_mints_apart_on_a_reused_account_index() {
+        let (wallet, counterparty, other_mint) = (address(), address(), address());
+        let keys = [address(), address(), address()];
+        // Index 1 is closed and recreated for another mint within the transaction
+        let transaction = transaction(
+            &[&keys[0], &keys[1], &keys[2]],
+            &[],
+            vec![balance(1, USDC_MAINNET, Some(&wallet), 5_000_000), balance(2, USDC_MAINNET, Some(&counterparty), 0)],
+            vec![balance(1, &other_mint, Some(&wallet), 9_000_000), balance(2, USDC_MAINNET, Some(&counterparty), 5_000_000)],
+        );
+
+        let transfers = transfers(&transaction);
+
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].mint, USDC_MAINNET);
+        assert_eq!(transfers[0].amount, 5_000_000);
+        assert_eq!(transfers[0].from_account, keys[1]);
+        assert_eq!((transfers[0].from_owner.as_str(), transfers[0].to_owner.as_str()), (wallet.as_str(), counterparty.as_str()));
+    }
+
+    #[test]
+    fn closed_account_sends_its_whole_balance() {
+        let (wallet, counterparty) = (address(), address());
+        let keys = [address(), address(), address()];
+        // Index 1 is emptied and closed, so it has no post balance entry
+        let transaction = transaction(
+            &[&keys[0], &keys[1], &keys[2]],
+            &[],
+            vec![balance(1, USDC_MAINNET, Some(&wallet), 7_250_000), balance(2, USDC_MAINNET, Some(&counterparty), 1_000_000)],
+            vec![balance(2, USDC_MAINNET, Some(&counterparty), 8_250_000)],
+        );
+
+        let transfers = transfers(&transaction);
+
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].amount, 7_250_000);
+        assert_eq!(transfers[0].from_owner, wallet);
+        assert_eq!(transfers[0].from_account, keys[1]);
+        assert_eq!(transfers[0].to_owner, counterparty);
+    }
+
+    #[test]
+    fn formats_amounts() {
+        let cases: &[(i128, u8, Option<u8>, &str)] = &[
+            (0, 6, None, "0.000000"),
+            (1_234_500_000, 6, None, "1,234.500000"),
+            (1_234_500_000, 6, Some(2), "1,234.50"),
+            (1_234_567_890, 6, Some(0), "1,235"),
+            (-5_000_000, 6, Some(0), "-5"),
+            (-4_999, 6, Some(2), "0.00"), // Rounds to zero, so no sign
+            (999_995, 6, Some(2), "1.00"),
+            (42, 0, None, "42"),
+            (u64::MAX as i128, 6, None, "18,446,744,073,709.551615"),
+            (-(u64::MAX as i128), 0, None, "-18,446,744,073,709,551,615"),
+        ];
+
+        for &(raw, decimals, max_places, expected) in cases {
+            assert_eq!(format_amount(raw, decimals, max_places), expected, "format_amount({}, {}, {:?})", raw, decimals, max_places);
+        }
+    }
+
+    #[test]
+    fn exact_amounts_trim_trailing_zeros() {
+        let cases: &[(i128, u8, &str)] = &[
+            (0, 6, "0"),
+            (2_000_000, 6, "2"),
+            (1_500_000, 6, "1.5"),
+            (1_234_500_000, 6, "1234.5"),
+            (1, 6, "0.000001"),
+            (-1, 6, "-0.000001"),
+            (10, 1, "1"),
+            (u64::MAX as i128, 6, "18446744073709.551615"),
+            (u64::MAX as i128, 0, "18446744073709551615"),
+        ];
+
+        for &(raw, decimals, expected) in cases {
+            assert_eq!(exact_amount(raw, decimals), expected, "exact_amount({}, {})", raw, decimals);
+        }
+    }
+
+    #[test]
+    fn parses_ui_amounts() {
+        let cases: &[(&str, u8, Option<u128>)] = &[
+            ("0", 6, Some(0)),
+            ("1.5", 6, Some(1_500_000)),
+            ("1.500000", 6, Some(1_500_000)),
+            (".5", 6, Some(500_000)),
+            ("2.", 6, Some(2_000_000)),
+            (" 7 ", 6, Some(7_000_000)),
+            ("18446744073709.551615", 6, Some(u64::MAX as u128)),
+            ("1.0000001", 6, None), // More places than the mint has
+            ("", 6, None),
+            (".", 6, None),
+            ("-1", 6, None),
+            ("1,000", 6, None),
+            ("1e6", 6, None),
+        ];
+
+        for &(amount, decimals, expected) in cases {
+            assert_eq!(parse_ui_amount(amount, decimals), expected, "parse_ui_amount({:?}, {})", amount, decimals);
+        }
+    }
+}
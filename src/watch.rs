@@ -0,0 +1,343 @@
+use anyhow::Result;
+use futures::stream::{select_all, BoxStream, SelectAll, StreamExt};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+use tokio::time::Duration;
+
+use crate::rpc::RpcProvider;
+use crate::transfer::UsdcTransfer;
+use crate::SolanaIndexer;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A transaction touching several watched accounts is notified once per
+/// account; remember this many recent signatures to process it only once
+const SEEN_CAPACITY: usize = 10_000;
+
+/// A notification can arrive before the transaction is queryable over RPC
+const FETCH_ATTEMPTS: u32 = 3;
+
+/// How often an accounts subscription looks for token accounts the wallet
+/// opened since it started
+const ACCOUNT_REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Most signatures fetched for one token account per balance change
+const ACCOUNT_SIGNATURE_LIMIT: usize = 25;
+
+/// How `watch` learns about new transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subscription {
+    /// `logsSubscribe` on the wallet and its token accounts
+    Logs,
+    /// `accountSubscribe` on the wallet's USDC token accounts; quieter, since
+    /// only balance changes notify
+    Accounts,
+}
+
+/// Derive the websocket endpoint from an HTTP RPC URL the same way the Solana
+/// CLI does: swap the scheme, and bump the port for a local validator
+pub fn websocket_url(rpc_url: &str) -> String {
+    let url = if let Some(rest) = rpc_url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = rpc_url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        rpc_url.to_string()
+    };
+
+    url.replace(":8899", ":8900")
+}
+
+/// Bounded set of recently processed signatures
+struct RecentSignatures {
+    order: VecDeque<String>,
+    set: HashSet<String>,
+}
+
+impl RecentSignatures {
+    fn new() -> Self {
+        Self {
+            order: VecDeque::new(),
+            set: HashSet::new(),
+        }
+    }
+
+    /// Returns false if the signature was already seen
+    fn insert(&mut self, signature: &str) -> bool {
+        if !self.set.insert(signature.to_string()) {
+            return false;
+        }
+
+        self.order.push_back(signature.to_string());
+        if self.order.len() > SEEN_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.set.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// Stream transfers live through `subscription`, handing each batch found in
+/// a confirmed transaction to `on_transfers`. Reconnects with exponential
+/// backoff whenever the socket drops; never returns.
+pub async fn watch<P: RpcProvider>(
+    indexer: &SolanaIndexer<P>,
+    ws_url: &str,
+    subscription: Subscription,
+    mut on_transfers: impl FnMut(&[UsdcTransfer]),
+) {
+    let mut backoff = MIN_BACKOFF;
+    let mut seen = RecentSignatures::new();
+
+    loop {
+        println!("🔌 Connecting to {}...", ws_url);
+
+        let result = match subscription {
+            Subscription::Logs => subscribe_logs(indexer, ws_url, &mut seen, &mut on_transfers).await,
+            Subscription::Accounts => subscribe_accounts(indexer, ws_url, &mut seen, &mut on_transfers).await,
+        };
+        match result {
+            Ok(()) => {
+                println!("⚠️ Subscription stream closed");
+                backoff = MIN_BACKOFF;
+            }
+            Err(e) => println!("⚠️ Websocket error: {}", e),
+        }
+
+        println!("🔄 Reconnecting in {}s...", backoff.as_secs());
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+async fn subscribe_logs<P: RpcProvider>(
+    indexer: &SolanaIndexer<P>,
+    ws_url: &str,
+    seen: &mut RecentSignatures,
+    on_transfers: &mut impl FnMut(&[UsdcTransfer]),
+) -> Result<()> {
+    let client = PubsubClient::new(ws_url).await?;
+
+    // `mentions` only accepts a single address, so subscribe once per account
+    let mut addresses: Vec<String> = indexer.wallet_token_accounts.iter().cloned().collect();
+    addresses.push(indexer.wallet_pubkey.to_string());
+
+    let mut streams = Vec::new();
+    let mut unsubscribes = Vec::new();
+    for address in &addresses {
+        let (stream, unsubscribe) = client
+            .logs_subscribe(
+                RpcTransactionLogsFilter::Mentions(vec![address.clone()]),
+                RpcTransactionLogsConfig {
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            )
+            .await?;
+        streams.push(stream);
+        unsubscribes.push(unsubscribe);
+    }
+
+    println!("👀 Watching {} addresses for new transfers", addresses.len());
+
+    let mut notifications = select_all(streams);
+    while let Some(notification) = notifications.next().await {
+        let logs = notification.value;
+        if logs.err.is_none() {
+            process_signature(indexer, &logs.signature, seen, on_transfers).await;
+        }
+    }
+
+    drop(notifications);
+    for unsubscribe in unsubscribes {
+        unsubscribe().await;
+    }
+
+    Ok(())
+}
+
+/// Watch the wallet's USDC token accounts with `accountSubscribe`. A balance
+/// change doesn't say which transaction caused it, so each notification
+/// fetches the account's signatures since the last one handled. Accounts the
+/// wallet opens later are picked up every `ACCOUNT_REFRESH_INTERVAL`.
+async fn subscribe_accounts<P: RpcProvider>(
+    indexer: &SolanaIndexer<P>,
+    ws_url: &str,
+    seen: &mut RecentSignatures,
+    on_transfers: &mut impl FnMut(&[UsdcTransfer]),
+) -> Result<()> {
+    let client = PubsubClient::new(ws_url).await?;
+    let config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        commitment: Some(CommitmentConfig::confirmed()),
+        ..RpcAccountInfoConfig::default()
+    };
+
+    // Newest signature handled per account; `None` until it has any
+    let mut cursors: HashMap<Pubkey, Option<Signature>> = HashMap::new();
+    let mut notifications: SelectAll<BoxStream<'_, Pubkey>> = SelectAll::new();
+    let mut unsubscribes = Vec::new();
+
+    for account in token_accounts(indexer)? {
+        let (stream, unsubscribe) = client.account_subscribe(&account, Some(config.clone())).await?;
+        notifications.push(stream.map(move |_| account).boxed());
+        unsubscribes.push(unsubscribe);
+        cursors.insert(account, latest_signature(indexer, &account)?);
+    }
+
+    println!("👀 Watching {} token accounts for balance changes", cursors.len());
+
+    let mut refresh = tokio::time::interval(ACCOUNT_REFRESH_INTERVAL);
+    refresh.tick().await; // The first tick is immediate
+
+    loop {
+        tokio::select! {
+            notification = notifications.next() => {
+                let Some(account) = notification else {
+                    break;
+                };
+                let cursor = cursors.entry(account).or_default();
+                process_account(indexer, &account, cursor, seen, on_transfers).await;
+            }
+            _ = refresh.tick() => {
+                let accounts = match token_accounts(indexer) {
+                    Ok(accounts) => accounts,
+                    Err(e) => {
+                        println!("⚠️ Could not refresh token accounts: {}", e);
+                        continue;
+                    }
+                };
+
+                let new_accounts: Vec<Pubkey> = accounts.into_iter().filter(|account| !cursors.contains_key(account)).collect();
+                for account in new_accounts {
+                    let (stream, unsubscribe) = client.account_subscribe(&account, Some(config.clone())).await?;
+                    notifications.push(stream.map(move |_| account).boxed());
+                    unsubscribes.push(unsubscribe);
+                    println!("🆕 Watching new token account {}", account);
+
+                    // Its first transfers may predate the subscription
+                    let cursor = cursors.entry(account).or_default();
+                    process_account(indexer, &account, cursor, seen, on_transfers).await;
+                }
+            }
+        }
+    }
+
+    drop(notifications);
+    for unsubscribe in unsubscribes {
+        unsubscribe().await;
+    }
+
+    Ok(())
+}
+
+/// The wallet's USDC token accounts: its associated token accounts, which
+/// may not exist yet, and any others it owns
+fn token_accounts<P: RpcProvider>(indexer: &SolanaIndexer<P>) -> Result<HashSet<Pubkey>> {
+    let mut accounts = HashSet::new();
+    for account in &indexer.wallet_token_accounts {
+        accounts.insert(Pubkey::from_str(account)?);
+    }
+    for mint in indexer.mints.usdc_mints() {
+        for account in indexer.rpc.get_token_accounts_by_owner(&indexer.wallet_pubkey, &mint)? {
+            accounts.insert(Pubkey::from_str(&account.pubkey)?);
+        }
+    }
+    Ok(accounts)
+}
+
+/// Signatures of `account` newer than `until`, newest first
+fn signatures_since<P: RpcProvider>(
+    indexer: &SolanaIndexer<P>,
+    account: &Pubkey,
+    until: Option<Signature>,
+    limit: usize,
+) -> Result<Vec<(Signature, bool)>> {
+    let signatures = indexer.rpc.get_signatures_for_address_with_config(
+        account,
+        GetConfirmedSignaturesForAddress2Config {
+            before: None,
+            until,
+            limit: Some(limit),
+            commitment: Some(CommitmentConfig::confirmed()),
+        },
+    )?;
+
+    signatures
+        .into_iter()
+        .map(|info| Ok((Signature::from_str(&info.signature)?, info.err.is_some())))
+        .collect()
+}
+
+fn latest_signature<P: RpcProvider>(indexer: &SolanaIndexer<P>, account: &Pubkey) -> Result<Option<Signature>> {
+    Ok(signatures_since(indexer, account, None, 1)?.first().map(|(signature, _)| *signature))
+}
+
+/// Process the transactions that touched `account` since `cursor`, oldest
+/// first, and move the cursor to the newest
+async fn process_account<P: RpcProvider>(
+    indexer: &SolanaIndexer<P>,
+    account: &Pubkey,
+    cursor: &mut Option<Signature>,
+    seen: &mut RecentSignatures,
+    on_transfers: &mut impl FnMut(&[UsdcTransfer]),
+) {
+    let signatures = match signatures_since(indexer, account, *cursor, ACCOUNT_SIGNATURE_LIMIT) {
+        Ok(signatures) => signatures,
+        Err(e) => {
+            println!("⚠️ Could not fetch signatures for {}: {}", account, e);
+            return;
+        }
+    };
+
+    if let Some((newest, _)) = signatures.first() {
+        *cursor = Some(*newest);
+    }
+    for (signature, failed) in signatures.into_iter().rev() {
+        if !failed {
+            process_signature(indexer, &signature.to_string(), seen, on_transfers).await;
+        }
+    }
+}
+
+/// Process a notified transaction once, retrying while it isn't queryable yet
+async fn process_signature<P: RpcProvider>(
+    indexer: &SolanaIndexer<P>,
+    signature: &str,
+    seen: &mut RecentSignatures,
+    on_transfers: &mut impl FnMut(&[UsdcTransfer]),
+) {
+    if !seen.insert(signature) {
+        return;
+    }
+
+    let Ok(parsed) = Signature::from_str(signature) else {
+        return;
+    };
+
+    let mut attempt = 1;
+    loop {
+        match indexer.process_transaction(parsed).await {
+            Ok(transfers) => {
+                if !transfers.is_empty() {
+                    on_transfers(&transfers);
+                }
+                return;
+            }
+            Err(_) if attempt < FETCH_ATTEMPTS => {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                println!("⚠️ Error processing transaction {}: {}", signature, e);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::transfer::UsdcTransfer;
+
+/// Transfers per request; larger batches are split across several POSTs
+const CHUNK_SIZE: usize = 100;
+const MAX_ATTEMPTS: u32 = 4;
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    wallet: &'a str,
+    transfers: &'a [UsdcTransfer],
+}
+
+#[derive(Serialize)]
+struct OutflowAlert<'a> {
+    alert: &'static str,
+    wallet: &'a str,
+    net_outflow: u128, // Raw units
+    threshold: u128,   // Raw units
+    decimals: u8,
+    transfers: &'a [UsdcTransfer],
+}
+
+#[derive(Serialize)]
+struct FlaggedAlert<'a> {
+    alert: &'static str,
+    wallet: &'a str,
+    transfers: &'a [UsdcTransfer],
+}
+
+/// POST `transfers` to `url` as JSON, in chunks, so large batches don't
+/// produce oversized requests
+pub async fn post_transfers(url: &str, wallet: &str, transfers: &[UsdcTransfer]) -> Result<()> {
+    let client = reqwest::Client::new();
+
+    for chunk in transfers.chunks(CHUNK_SIZE) {
+        post_json(&client, url, &Payload { wallet, transfers: chunk }).await?;
+    }
+
+    Ok(())
+}
+
+/// POST a net outflow alert with the sent transfers that caused it
+pub async fn post_outflow_alert(
+    url: &str,
+    wallet: &str,
+    net_outflow: u128,
+    threshold: u128,
+    decimals: u8,
+    transfers: &[UsdcTransfer],
+) -> Result<()> {
+    let alert = OutflowAlert {
+        alert: "net_outflow_exceeded",
+        wallet,
+        net_outflow,
+        threshold,
+        decimals,
+        transfers,
+    };
+    post_json(&reqwest::Client::new(), url, &alert).await
+}
+
+/// POST an alert listing transfers flagged as anomalies
+pub async fn post_anomaly_alert(url: &str, wallet: &str, transfers: &[UsdcTransfer]) -> Result<()> {
+    let alert = FlaggedAlert {
+        alert: "anomalous_transfers",
+        wallet,
+        transfers,
+    };
+    post_json(&reqwest::Client::new(), url, &alert).await
+}
+
+/// POST an alert listing transfers to or from a denied address
+pub async fn post_denied_alert(url: &str, wallet: &str, transfers: &[UsdcTransfer]) -> Result<()> {
+    let alert = FlaggedAlert {
+        alert: "denied_counterparties",
+        wallet,
+        transfers,
+    };
+    post_json(&reqwest::Client::new(), url, &alert).await
+}
+
+/// POST `payload`, retrying with exponential backoff so a flaky endpoint
+/// doesn't drop alerts
+async fn post_json<T: Serialize>(client: &reqwest::Client, url: &str, payload: &T) -> Result<()> {
+    let mut attempt = 1;
+
+    loop {
+        let result = client
+            .post(url)
+            .json(payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        match result {
+            Ok(_) => return Ok(()),
+            Err(e) if attempt < MAX_ATTEMPTS => {
+                let delay = 1u64 << (attempt - 1);
+                println!("⚠️ Webhook delivery failed ({}), retrying in {}s...", e, delay);
+                tokio::time::sleep(tokio::time::Duration::from_secs(delay)).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(anyhow!("Webhook delivery failed after {} attempts: {}", MAX_ATTEMPTS, e));
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use chrono::{DateTime, Duration, Utc};
+use std::fmt;
+
+/// Which transactions a backfill covers
+#[derive(Debug, Clone, Copy)]
+pub enum IndexWindow {
+    /// Everything from the last `duration`, judged by block time
+    Lookback(std::time::Duration),
+    /// Everything between two slots (inclusive); `to: None` means up to the tip
+    Slots { from: u64, to: Option<u64> },
+}
+
+impl IndexWindow {
+    /// Resolve the window against `now`, read once when a backfill starts
+    pub fn bounds(&self, now: DateTime<Utc>) -> WindowBounds {
+        match *self {
+            IndexWindow::Lookback(duration) => WindowBounds::Time {
+                // A lookback too long to represent reaches back to genesis anyway
+                start: Duration::from_std(duration)
+                    .ok()
+                    .and_then(|duration| now.checked_sub_signed(duration)),
+                end: now,
+            },
+            IndexWindow::Slots { from, to } => WindowBounds::Slots { from, to },
+        }
+    }
+}
+
+/// The edges of a window, fixed up front so the pagination cut-off and the
+/// final filter agree on them. Both edges are inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowBounds {
+    /// Block times from `start` (`None`: genesis) through `end`
+    Time { start: Option<DateTime<Utc>>, end: DateTime<Utc> },
+    /// Slots from `from` through `to` (`None`: the tip)
+    Slots { from: u64, to: Option<u64> },
+}
+
+/// Where a transaction falls relative to a window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowPosition {
+    Before, // Older than the window
+    Inside,
+    After, // Newer than the window, e.g. landed after the backfill started
+}
+
+impl WindowBounds {
+    /// Whether time windows need each transaction's block time
+    pub fn needs_time(&self) -> bool {
+        matches!(self, WindowBounds::Time { .. })
+    }
+
+    /// Place a transaction by its slot or block time, whichever the window is
+    /// measured in. A time window treats an unknown block time as inside.
+    pub fn position(&self, slot: u64, timestamp: Option<DateTime<Utc>>) -> WindowPosition {
+        let (before, after) = match (*self, timestamp) {
+            (WindowBounds::Time { start, end }, Some(timestamp)) => {
+                (start.is_some_and(|start| timestamp < start), timestamp > end)
+            }
+            (WindowBounds::Time { .. }, None) => (false, false),
+            (WindowBounds::Slots { from, to }, _) => (slot < from, to.is_some_and(|to| slot > to)),
+        };
+
+        if before {
+            WindowPosition::Before
+        } else if after {
+            WindowPosition::After
+        } else {
+            WindowPosition::Inside
+        }
+    }
+
+    pub fn contains(&self, slot: u64, timestamp: DateTime<Utc>) -> bool {
+        self.position(slot, Some(timestamp)) == WindowPosition::Inside
+    }
+
+    /// Narrow the window to start no earlier than a transaction at `slot`
+    /// (if known) and `timestamp`, keeping that transaction inside. A slot
+    /// window can only be narrowed by slot.
+    pub fn starting_at(self, slot: Option<u64>, timestamp: DateTime<Utc>) -> Self {
+        match self {
+            WindowBounds::Time { start, end } => WindowBounds::Time {
+                start: Some(start.map_or(timestamp, |start| start.max(timestamp))),
+                end,
+            },
+            WindowBounds::Slots { from, to } => WindowBounds::Slots {
+                from: slot.map_or(from, |slot| from.max(slot)),
+                to,
+            },
+        }
+    }
+}
+
+impl fmt::Display for WindowBounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowBounds::Time { start: Some(start), end } => write!(f, "{} to {}", start, end),
+            WindowBounds::Time { start: None, end } => write!(f, "genesis to {}", end),
+            WindowBounds::Slots { from, to: Some(to) } => write!(f, "slots {}..={}", from, to),
+            WindowBounds::Slots { from, to: None } => write!(f, "slots {} to tip", from),
+        }
+    }
+}
+
+impl fmt::Display for IndexWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexWindow::Lookback(duration) => write!(f, "the last {}", describe(*duration)),
+            IndexWindow::Slots { from, to: Some(to) } => write!(f, "slots {}..={}", from, to),
+            IndexWindow::Slots { from, to: None } => write!(f, "slots {} to tip", from),
+        }
+    }
+}
+
+/// "24 hours", "7 days", "90 minutes": the largest whole unit, except that a
+/// single day reads better as hours
+fn describe(duration: std::time::Duration) -> String {
+    let seconds = duration.as_secs();
+    let (count, unit) = if seconds % 86_400 == 0 && seconds > 86_400 {
+        (seconds / 86_400, "day")
+    } else if seconds % 3600 == 0 {
+        (seconds / 3600, "hour")
+    } else if seconds % 60 == 0 {
+        (seconds / 60, "minute")
+    } else {
+        (seconds, "second")
+    };
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
+/// Parse a duration such as "90", "15m" or "1h 30m". A bare number is a
+/// count of `unit`s.
+pub fn parse_duration(value: &str, unit: std::time::Duration) -> Result<std::time::Duration, String> {
+    let value = value.trim();
+    if let Ok(count) = value.parse::<u32>() {
+        return Ok(unit * count);
+    }
+    humantime::parse_duration(value).map_err(|e| format!("invalid duration '{}': {}", value, e))
+}
+
+/// `--lookback` value parser: bare numbers are hours, and the window can't be empty
+pub fn parse_lookback(value: &str) -> Result<std::time::Duration, String> {
+    let duration = parse_duration(value, std::time::Duration::from_secs(3600))?;
+    if duration.is_zero() {
+        return Err("lookback must be longer than zero".to_string());
+    }
+    Ok(duration)
+}
+
+/// `--interval` value parser: bare numbers are seconds, and a zero interval
+/// would poll without pause
+pub fn parse_interval(value: &str) -> Result<std::time::Duration, String> {
+    let duration = parse_duration(value, std::time::Duration::from_secs(1))?;
+    if duration.is_zero() {
+        return Err("interval must be longer than zero".to_string());
+    }
+    Ok(duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap()
+    }
+
+    #[test]
+    fn time_window_includes_both_edges() {
+        let window = IndexWindow::Lookback(std::time::Duration::from_secs(3600));
+        let bounds = window.bounds(at(3600));
+        let slot = 0; // Ignored by time windows
+
+        assert!(bounds.contains(slot, at(0)));
+        assert!(bounds.contains(slot, at(3600)));
+        assert_eq!(bounds.position(slot, Some(at(-1))), WindowPosition::Before);
+        assert_eq!(bounds.position(slot, Some(at(1))), WindowPosition::Inside);
+        assert_eq!(bounds.position(slot, Some(at(3599))), WindowPosition::Inside);
+        assert_eq!(bounds.position(slot, Some(at(3601))), WindowPosition::After);
+    }
+
+    #[test]
+    fn slot_window_includes_both_edges() {
+        let bounds = IndexWindow::Slots { from: 100, to: Some(200) }.bounds(at(0));
+
+        assert!(bounds.contains(100, at(0)));
+        assert!(bounds.contains(200, at(0)));
+        assert_eq!(bounds.position(99, None), WindowPosition::Before);
+        assert_eq!(bounds.position(101, None), WindowPosition::Inside);
+        assert_eq!(bounds.position(199, None), WindowPosition::Inside);
+        assert_eq!(bounds.position(201, None), WindowPosition::After);
+    }
+
+    #[test]
+    fn open_slot_window_runs_to_the_tip() {
+        let bounds = IndexWindow::Slots { from: 100, to: None }.bounds(at(0));
+
+        assert_eq!(bounds.position(99, None), WindowPosition::Before);
+        assert_eq!(bounds.position(u64::MAX, None), WindowPosition::Inside);
+    }
+
+    #[test]
+    fn zero_durations_are_rejected() {
+        assert_eq!(parse_interval("0"), Err("interval must be longer than zero".to_string()));
+        assert_eq!(parse_interval("0s"), Err("interval must be longer than zero".to_string()));
+        assert_eq!(parse_lookback("0"), Err("lookback must be longer than zero".to_string()));
+        assert_eq!(parse_interval("30"), Ok(std::time::Duration::from_secs(30)));
+    }
+}